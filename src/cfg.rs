@@ -0,0 +1,463 @@
+//! Графы переходов: по командам основной памяти и по микрокомандам МПУ.
+//!
+//! Граф строится статически, по тому, что лежит в памяти, начиная с точки входа. Косвенные
+//! переходы (например, возврат из подпрограммы через BR (X)) заранее неизвестны, так что
+//! у таких блоков выходящих ребер нет.
+
+use crate::model::{Memory, Register, FETCH_START};
+use crate::parse::general::{GeneralCommandInfo, GeneralParser};
+use crate::parse::mc::{ControlCommand, McParser, MicroCommandInfo};
+use crate::parse::{CommandInfo, Parser};
+use crate::utils::bit_registers::sub_sum;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Почему управление идет по ребру
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// К следующей ячейке: обычное выполнение или невыполненное условие
+    Next,
+    /// Переход выполнился
+    Jump,
+    /// Следующая команда пропущена (ISZ, TSF)
+    Skip,
+    /// Вызов подпрограммы (JSR)
+    Call,
+}
+
+impl EdgeKind {
+    fn label(&self) -> &'static str {
+        match self {
+            EdgeKind::Next => "",
+            EdgeKind::Jump => "переход",
+            EdgeKind::Skip => "пропуск",
+            EdgeKind::Call => "вызов",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edge {
+    /// Адрес начала блока, откуда идет ребро
+    pub from: u16,
+    /// Адрес начала блока, куда идет ребро
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+/// Линейный участок: выполняется от первой ячейки до последней без переходов внутрь или наружу
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub start: u16,
+    pub end: u16,
+    /// Строки вида "адрес: мнемоника"
+    pub lines: Vec<String>,
+    /// К какой части программы относится блок. Нужно для графа микропрограммы
+    pub group: Option<&'static str>,
+}
+
+impl Block {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    /// Блоки по возрастанию адреса
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+    /// Откуда начинается выполнение
+    pub entries: Vec<u16>,
+}
+
+/// Куда может пойти управление после одной команды
+struct Step {
+    successors: Vec<(u16, EdgeKind)>,
+    /// Команда заканчивает блок, даже если у нее один обычный последователь
+    ends_block: bool,
+}
+
+impl Graph {
+    /// Собирает граф из всех ячеек, достижимых из `entries`. Точка входа, до которой уже дошли
+    /// из предыдущих, точкой входа не считается.
+    /// `step` говорит, куда уходит управление из ячейки, `line` - как ее подписать
+    fn build(
+        entries: impl IntoIterator<Item = u16>,
+        size: u16,
+        step: impl Fn(u16) -> Step,
+        line: impl Fn(u16) -> String,
+    ) -> Graph {
+        let mut graph = Graph::default();
+        let mut steps = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        for entry in entries {
+            if entry >= size || steps.contains_key(&entry) {
+                continue;
+            }
+            graph.entries.push(entry);
+            leaders.insert(entry);
+            let mut queue = VecDeque::from([entry]);
+            while let Some(address) = queue.pop_front() {
+                if address >= size || steps.contains_key(&address) {
+                    continue;
+                }
+                let step = step(address);
+                for (next, kind) in &step.successors {
+                    if step.ends_block || *kind != EdgeKind::Next {
+                        leaders.insert(*next);
+                    }
+                    queue.push_back(*next);
+                }
+                steps.insert(address, step);
+            }
+        }
+
+        // туда, куда приходят с нескольких мест (например, из разных точек входа), тоже начало блока
+        let mut incoming = BTreeMap::new();
+        for step in steps.values() {
+            for (next, _) in &step.successors {
+                *incoming.entry(*next).or_insert(0) += 1;
+            }
+        }
+        leaders.extend(incoming.iter().filter(|(_, n)| **n > 1).map(|(a, _)| *a));
+
+        for &start in leaders.iter().filter(|a| steps.contains_key(a)) {
+            let mut end = start;
+            loop {
+                let step = &steps[&end];
+                let next = end + 1;
+                let continues = !step.ends_block
+                    && step.successors == [(next, EdgeKind::Next)]
+                    && !leaders.contains(&next);
+                if !continues {
+                    break;
+                }
+                end = next;
+            }
+            graph.blocks.push(Block {
+                start,
+                end,
+                lines: (start..=end).map(&line).collect(),
+                group: None,
+            });
+            for (to, kind) in &steps[&end].successors {
+                if steps.contains_key(to) {
+                    graph.edges.push(Edge {
+                        from: start,
+                        to: *to,
+                        kind: *kind,
+                    });
+                }
+            }
+        }
+        graph
+    }
+
+    /// Граф программы в основной памяти, начиная с адреса `entry`
+    pub fn general(memory: &Memory<GeneralCommandInfo, GeneralParser>, entry: u16) -> Graph {
+        let size = memory.data.len() as u16;
+        Graph::build(
+            [entry],
+            size,
+            |address| general_step(memory, address),
+            |address| {
+                let opcode = memory.data[address as usize].get();
+                format!(
+                    "{:0>3X}: {}",
+                    address,
+                    memory.parser.parse(opcode).mnemonic()
+                )
+            },
+        )
+    }
+
+    /// Граф микропрограммы: от начала выборки команды, а потом от каждой непустой ячейки,
+    /// до которой оттуда не дойти (пультовые операции и прочее)
+    pub fn micro(memory: &Memory<MicroCommandInfo, McParser>) -> Graph {
+        let size = memory.data.len() as u16;
+        let entries = std::iter::once(FETCH_START as u16)
+            .chain((0..size).filter(|a| memory.data[*a as usize].get() != 0));
+        let mut graph = Graph::build(
+            entries,
+            size,
+            |address| micro_step(memory.data[address as usize].get(), address, size),
+            |address| {
+                let opcode = memory.data[address as usize].get();
+                format!(
+                    "{:0>2X}: {}",
+                    address,
+                    memory.parser.parse(opcode).mnemonic()
+                )
+            },
+        );
+        for block in &mut graph.blocks {
+            block.group = MICRO_PHASES
+                .iter()
+                .find(|(first, last, _)| (*first..=*last).contains(&block.start))
+                .map(|(_, _, name)| *name);
+        }
+        graph
+    }
+
+    pub fn block(&self, start: u16) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.start == start)
+    }
+
+    /// Блок, в котором лежит адрес
+    pub fn block_at(&self, address: u16) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.contains(address))
+    }
+
+    /// Номер яруса для каждого блока: сколько ребер до него от точек входа по кратчайшему пути
+    pub fn ranks(&self) -> BTreeMap<u16, usize> {
+        let mut ranks = BTreeMap::new();
+        let mut queue: VecDeque<(u16, usize)> = self.entries.iter().map(|e| (*e, 0)).collect();
+        while let Some((start, rank)) = queue.pop_front() {
+            if ranks.contains_key(&start) {
+                continue;
+            }
+            ranks.insert(start, rank);
+            for edge in self.edges.iter().filter(|e| e.from == start) {
+                queue.push_back((edge.to, rank + 1));
+            }
+        }
+        ranks
+    }
+
+    /// Граф в формате Graphviz: `dot -Tpng graph.dot -o graph.png`
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut result = format!(
+            "digraph \"{}\" {{\n\tnode [shape=box, fontname=\"monospace\"];\n",
+            name
+        );
+
+        let mut groups: BTreeMap<Option<&str>, Vec<&Block>> = BTreeMap::new();
+        for block in &self.blocks {
+            groups.entry(block.group).or_default().push(block);
+        }
+        for (idx, (group, blocks)) in groups.iter().enumerate() {
+            let indent = if let Some(group) = group {
+                result.push_str(&format!(
+                    "\tsubgraph cluster_{} {{\n\t\tlabel=\"{}\";\n",
+                    idx, group
+                ));
+                "\t\t"
+            } else {
+                "\t"
+            };
+            for block in blocks {
+                let label: String = block
+                    .lines
+                    .iter()
+                    .map(|l| format!("{}\\l", dot_escape(l)))
+                    .collect();
+                let style = if self.entries.contains(&block.start) {
+                    ", style=bold"
+                } else {
+                    ""
+                };
+                result.push_str(&format!(
+                    "{}b{:X} [label=\"{}\"{}];\n",
+                    indent, block.start, label, style
+                ));
+            }
+            if group.is_some() {
+                result.push_str("\t}\n");
+            }
+        }
+
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Next => "",
+                EdgeKind::Call => ", style=dashed",
+                _ => ", color=blue",
+            };
+            result.push_str(&format!(
+                "\tb{:X} -> b{:X} [label=\"{}\"{}];\n",
+                edge.from,
+                edge.to,
+                edge.kind.label(),
+                style
+            ));
+        }
+        result.push_str("}\n");
+        result
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn general_step(memory: &Memory<GeneralCommandInfo, GeneralParser>, address: u16) -> Step {
+    let opcode = memory.data[address as usize].get();
+    let size = memory.data.len() as u16;
+    let next = (address + 1) % size;
+    let skip = (address + 2) % size;
+    let target = opcode & 0x7FF;
+    let indirect = opcode & 0x0800 != 0;
+    let name = memory.parser.parse(opcode).name().to_string();
+
+    let (successors, ends_block) = match name.as_str() {
+        "HLT" => (vec![], true),
+        "BR" if indirect => (vec![], true),
+        "BR" => (vec![(target, EdgeKind::Jump)], true),
+        "BEQ" | "BMI" | "BPL" | "BCS" if indirect => (vec![(next, EdgeKind::Next)], true),
+        "BEQ" | "BMI" | "BPL" | "BCS" => {
+            (vec![(target, EdgeKind::Jump), (next, EdgeKind::Next)], true)
+        }
+        "ISZ" | "TSF" => (vec![(next, EdgeKind::Next), (skip, EdgeKind::Skip)], true),
+        // подпрограмма начинается со следующей за X ячейки, а возвращается к нам через BR (X)
+        "JSR" if indirect => (vec![(next, EdgeKind::Next)], true),
+        "JSR" => (
+            vec![
+                ((target + 1) % size, EdgeKind::Call),
+                (next, EdgeKind::Next),
+            ],
+            true,
+        ),
+        _ => (vec![(next, EdgeKind::Next)], false),
+    };
+    Step {
+        successors,
+        ends_block,
+    }
+}
+
+/// Части стандартной микропрограммы: первая и последняя ячейка и название
+pub const MICRO_PHASES: [(u16, u16, &str); 7] = [
+    (0x01, 0x0B, "Выборка команды"),
+    (0x0C, 0x1C, "Выборка адреса"),
+    (0x1D, 0x5D, "Исполнение адресных команд"),
+    (0x5E, 0x8E, "Исполнение безадресных команд и ввода-вывода"),
+    (0x8F, 0x98, "Прерывание"),
+    (0x99, 0xAC, "Пультовые операции"),
+    (0xF5, 0xF6, "Проверка тумблера"),
+];
+
+fn micro_step(opcode: u16, address: u16, size: u16) -> Step {
+    let next: Vec<(u16, EdgeKind)> = if address + 1 < size {
+        vec![(address + 1, EdgeKind::Next)]
+    } else {
+        vec![]
+    };
+    if sub_sum(opcode, 15, 14) < 2 {
+        return Step {
+            successors: next,
+            ends_block: false,
+        };
+    }
+
+    let command = ControlCommand(opcode);
+    let target = (command.jump_address() as u16, EdgeKind::Jump);
+    // 3 бит РС всегда 0, так что сравнение с ним - безусловный переход
+    let unconditional = command.register() == Register::Status
+        && command.bit_location() == 3
+        && !command.needed_bit();
+    let successors = if unconditional {
+        vec![target]
+    } else {
+        let mut successors = vec![target];
+        successors.extend(next);
+        successors
+    };
+    Step {
+        successors,
+        ends_block: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cfg::{Edge, EdgeKind, Graph};
+    use crate::model::Computer;
+
+    #[test]
+    fn general() {
+        let computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            let program = [
+                (0x10, 0xF200), // CLA
+                (0x11, 0x2020), // JSR 20
+                (0x12, 0x0030), // ISZ 30
+                (0x13, 0xC011), // BR 11
+                (0x14, 0xF000), // HLT
+                (0x20, 0x0000),
+                (0x21, 0xF800), // INC
+                (0x22, 0xCA20), // BR (20)
+            ];
+            for (address, value) in program {
+                mem.data[address].set(value);
+            }
+        }
+
+        let graph = Graph::general(&computer.general_memory.borrow(), 0x10);
+        let blocks: Vec<(u16, u16)> = graph.blocks.iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                (0x10, 0x10),
+                (0x11, 0x11),
+                (0x12, 0x12),
+                (0x13, 0x13),
+                (0x14, 0x14),
+                (0x21, 0x22)
+            ]
+        );
+        assert_eq!(graph.block(0x11).unwrap().lines, vec!["011: JSR 020"]);
+        assert_eq!(graph.block_at(0x22).unwrap().start, 0x21);
+
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            graph.edges,
+            vec![
+                edge(0x10, 0x11, EdgeKind::Next),
+                edge(0x11, 0x21, EdgeKind::Call),
+                edge(0x11, 0x12, EdgeKind::Next),
+                edge(0x12, 0x13, EdgeKind::Next),
+                edge(0x12, 0x14, EdgeKind::Skip),
+                edge(0x13, 0x11, EdgeKind::Jump),
+            ]
+        );
+        assert_eq!(graph.ranks()[&0x21], 2);
+
+        let dot = graph.to_dot("program");
+        assert!(dot.contains("b10 [label=\"010: CLA\\l\", style=bold];"));
+        assert!(dot.contains("b13 -> b11 [label=\"переход\", color=blue];"));
+    }
+
+    #[test]
+    fn micro() {
+        let computer = Computer::new();
+        let graph = Graph::micro(&computer.mc_memory.borrow());
+
+        assert_eq!(graph.entries[0], 0x01);
+        // выборка команды: 01-07 подряд, на 07 первое ветвление
+        let fetch = graph.block(0x01).unwrap();
+        assert_eq!((fetch.start, fetch.end), (0x01, 0x07));
+        assert_eq!(fetch.group, Some("Выборка команды"));
+
+        // 89: безусловный переход на начало выборки
+        let halt = graph.block_at(0x89).unwrap();
+        let from_halt: Vec<&Edge> = graph
+            .edges
+            .iter()
+            .filter(|e| e.from == halt.start)
+            .collect();
+        assert_eq!(from_halt.len(), 1);
+        assert_eq!((from_halt[0].to, from_halt[0].kind), (0x01, EdgeKind::Jump));
+
+        // условный переход на 0C: в блок адресной выборки или дальше
+        let address = graph.block(0x0C).unwrap();
+        assert_eq!(address.group, Some("Выборка адреса"));
+        assert_eq!(graph.edges.iter().filter(|e| e.from == 0x0C).count(), 2);
+
+        // пультовая операция "Пуск" до выборки не доходит, это отдельная точка входа
+        assert!(graph.entries.contains(&0xA8));
+        // блоки не пересекаются
+        for pair in graph.blocks.windows(2) {
+            assert!(pair[0].end < pair[1].start);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use crate::devices::{DeviceDriver, FileDevice, ScriptedDevice};
+use crate::model::{Computer, Engine};
+use crate::trace::{compare, Reference, StopConditions, TraceKind};
+
+use std::fs::File;
+
+const USAGE: &str = "Использование: bevm <программа.mm> [опции]\n\
+\n\
+Опции:\n\
+\t--start XXX        адрес с которого начать выполнение (по умолчанию первая загруженная ячейка)\n\
+\t--max-steps N      максимальное количество команд (по умолчанию 1000000)\n\
+\t--in N=файл        ВУ N берет байты для IN из файла\n\
+\t--out N=файл       ВУ N пишет байты из OUT в файл\n\
+\t--script N=файл    ВУ N работает по сценарию из файла\n\
+\t                   (N - номер ВУ в десятичной записи, от 0 до 15)\n\
+\t--engine micro|isa  исполнять команды микропрограммой (по умолчанию) или сразу целиком\n\
+\t--check-trace файл  сверить трассировку с эталонной таблицей в CSV\n\
+\n\
+Без аргументов запускается обычный графический интерфейс.";
+
+/// Опции, которые понимает консольный режим
+const OPTIONS: [&str; 9] = [
+    "--help",
+    "-h",
+    "--start",
+    "--max-steps",
+    "--in",
+    "--out",
+    "--script",
+    "--engine",
+    "--check-trace",
+];
+
+struct Options {
+    program: String,
+    start: Option<u16>,
+    max_steps: u64,
+    inputs: Vec<(usize, String)>,
+    outputs: Vec<(usize, String)>,
+    scripts: Vec<(usize, String)>,
+    engine: Engine,
+    check_trace: Option<String>,
+}
+
+fn parse_device(arg: &str) -> Result<(usize, String), String> {
+    let Some((num, file)) = arg.split_once('=') else {
+        return Err(format!("Ожидалось N=файл, а получено {arg}"));
+    };
+    let num = num
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n < 16)
+        .ok_or_else(|| format!("Неверный номер ВУ {num}"))?;
+
+    Ok((num, file.to_string()))
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        program: String::new(),
+        start: None,
+        max_steps: 1_000_000,
+        inputs: vec![],
+        outputs: vec![],
+        scripts: vec![],
+        engine: Engine::Microcode,
+        check_trace: None,
+    };
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            if !options.program.is_empty() {
+                return Err(format!("Неожиданный аргумент {arg}"));
+            }
+            options.program = arg;
+            continue;
+        }
+
+        let Some(value) = iter.next() else {
+            return Err(format!("Для {arg} нужно указать значение"));
+        };
+
+        match arg.as_str() {
+            "--start" => {
+                options.start = Some(
+                    u16::from_str_radix(&value, 16)
+                        .ok()
+                        .filter(|v| *v <= 0x7FF)
+                        .ok_or_else(|| format!("Неверный адрес {value}"))?,
+                )
+            }
+            "--max-steps" => {
+                options.max_steps = value
+                    .parse()
+                    .map_err(|_| format!("Не могу распарсить число {value}"))?
+            }
+            "--in" => options.inputs.push(parse_device(&value)?),
+            "--out" => options.outputs.push(parse_device(&value)?),
+            "--script" => options.scripts.push(parse_device(&value)?),
+            "--engine" => {
+                options.engine = match value.as_str() {
+                    "micro" => Engine::Microcode,
+                    "isa" => Engine::Instruction,
+                    _ => return Err(format!("Неизвестный движок {value}")),
+                }
+            }
+            "--check-trace" => options.check_trace = Some(value),
+            _ => return Err(format!("Неизвестная опция {arg}")),
+        }
+    }
+
+    if options.program.is_empty() {
+        return Err("Не указана программа".to_string());
+    }
+
+    Ok(options)
+}
+
+fn bind_devices(computer: &mut Computer, options: &Options) -> Result<(), String> {
+    let mut files: Vec<Option<FileDevice>> = vec![None; 16];
+
+    for (num, file) in &options.inputs {
+        let device = files[*num].take().unwrap_or_else(FileDevice::new);
+        files[*num] = Some(device.with_input(file)?);
+    }
+    for (num, file) in &options.outputs {
+        let device = files[*num].take().unwrap_or_else(FileDevice::new);
+        files[*num] = Some(device.with_output(file)?);
+    }
+
+    for (num, device) in files.into_iter().enumerate() {
+        if let Some(device) = device {
+            computer.io_drivers[num] = DeviceDriver::File(device);
+        }
+    }
+
+    for (num, file) in &options.scripts {
+        if !matches!(computer.io_drivers[*num], DeviceDriver::Manual) {
+            return Err(format!("ВУ-{num} уже привязано к файлу"));
+        }
+        computer.io_drivers[*num] = DeviceDriver::Scripted(ScriptedDevice::from_file(file)?);
+    }
+
+    Ok(())
+}
+
+fn load_program(computer: &mut Computer, file: &str) -> Result<Option<u16>, String> {
+    let mut f = File::open(file).map_err(|e| format!("Не могу открыть файл \"{file}\": {e}"))?;
+
+    let parsed =
+        crate::parse::file::parse_file(&mut f, &computer.general_memory.borrow().parser, 0x7FF)?;
+
+    let first = parsed.first().map(|(pos, _)| *pos);
+    let mem = &mut computer.general_memory.borrow_mut().data;
+    for (pos, v) in parsed {
+        mem.get_mut(pos as usize).unwrap().set(v);
+    }
+
+    Ok(first)
+}
+
+fn prepare(options: &Options) -> Result<Computer, String> {
+    let mut computer = Computer::new();
+
+    let first = load_program(&mut computer, &options.program)?;
+    bind_devices(&mut computer, options)?;
+
+    computer.registers.r_command_counter = options.start.or(first).unwrap_or(0);
+    computer.engine = options.engine;
+
+    Ok(computer)
+}
+
+fn execute(options: Options) -> Result<bool, String> {
+    let mut computer = prepare(&options)?;
+
+    let mut halted = false;
+    for _ in 0..options.max_steps {
+        computer.command_step();
+        if computer.registers.r_command == 0xF000 {
+            halted = true;
+            break;
+        }
+    }
+
+    let registers = &computer.registers;
+    println!(
+        "СК={:0>3X} А={:0>4X} C={} N={} Z={} команд={}",
+        registers.r_command_counter,
+        registers.r_counter,
+        registers.get_overflow() as u8,
+        registers.get_negative() as u8,
+        registers.get_null() as u8,
+        computer.steps
+    );
+
+    for (num, driver) in computer.io_drivers.iter().enumerate() {
+        if !driver.output().is_empty() {
+            let bytes = driver
+                .output()
+                .iter()
+                .map(|b| format!("{b:0>2X}"))
+                .collect::<Vec<String>>();
+            println!("ВУ-{num}: {}", bytes.join(" "));
+        }
+    }
+
+    Ok(halted)
+}
+
+/// Сверяет трассировку программы с эталоном. Возвращает, совпала ли она
+fn check_trace(options: &Options, file: &str) -> Result<bool, String> {
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| format!("Не могу прочитать файл \"{file}\": {e}"))?;
+    let reference = Reference::parse(TraceKind::General, &text)?;
+
+    let mut computer = prepare(options)?;
+    let comparison = compare(
+        TraceKind::General,
+        &mut computer,
+        &reference,
+        &StopConditions::default(),
+    );
+
+    let Some(divergence) = comparison.divergence else {
+        println!("Трассировка совпадает: {} строк", reference.rows.len());
+        return Ok(true);
+    };
+
+    let row = divergence.row;
+    match divergence.column {
+        Some(column) => println!(
+            "Строка {}, колонка \"{}\": в эталоне {}, у ЭВМ {}",
+            row + 1,
+            reference.header[column],
+            reference.rows[row][column],
+            comparison.actual[row][column]
+        ),
+        None if row < reference.rows.len() => println!(
+            "Строка {}: ЭВМ уже остановилась ({}), а в эталоне строки еще есть",
+            row + 1,
+            comparison.stop.title()
+        ),
+        None => println!("Строка {}: эталон закончился посреди команды", row + 1),
+    }
+
+    Ok(false)
+}
+
+/// Нужен ли консольный режим: указана программа или знакомая опция. Все остальное, например
+/// `-psn_...`, который добавляет Finder на macOS, не мешает запустить интерфейс
+pub fn wanted(args: &[String]) -> bool {
+    args.iter()
+        .any(|a| !a.starts_with('-') || OPTIONS.contains(&a.as_str()))
+}
+
+/// Релизная сборка под Windows - оконное приложение без своей консоли, так что печатать
+/// некуда. Подключаемся к консоли, из которой нас запустили, или заводим новую
+#[cfg(all(windows, not(debug_assertions)))]
+pub fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+        fn AllocConsole() -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    unsafe {
+        if AttachConsole(ATTACH_PARENT_PROCESS) == 0 {
+            AllocConsole();
+        }
+    }
+}
+
+#[cfg(not(all(windows, not(debug_assertions))))]
+pub fn attach_console() {}
+
+/// Запускает программу без графического интерфейса. Возвращает код выхода.
+pub fn run(args: Vec<String>) -> i32 {
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{USAGE}");
+        return 0;
+    }
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return 1;
+        }
+    };
+
+    if let Some(file) = &options.check_trace {
+        return match check_trace(&options, file) {
+            Ok(true) => 0,
+            Ok(false) => 3,
+            Err(e) => {
+                eprintln!("{e}");
+                1
+            }
+        };
+    }
+
+    match execute(options) {
+        Ok(true) => 0,
+        Ok(false) => {
+            eprintln!("Превышено максимальное количество команд");
+            2
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
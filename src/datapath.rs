@@ -0,0 +1,138 @@
+//! Какие связи операционной части БЭВМ работают в микрокоманде.
+//!
+//! Это то же самое, что показывают схемы в методичке: регистры, АЛУ, сдвигатель, БР, память
+//! и шина ВУ. Все выводится из полей микрокоманды, значения регистров не нужны.
+
+use crate::model::Register;
+use crate::parse::mc::{
+    CUpdate, ControlCommand, Memory, NZUpdate, OperationalCommand0, OperationalCommand1, Shift,
+};
+use crate::utils::bit_registers::sub_sum;
+
+/// Связь на схеме операционной части
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Path {
+    /// Регистр на левый вход АЛУ
+    Left(Register),
+    /// Регистр на правый вход АЛУ
+    Right(Register),
+    /// Результат АЛУ в БР
+    Alu,
+    /// А через сдвигатель в БР
+    Shift,
+    /// ОП[РА] в РД
+    MemoryRead,
+    /// РД в ОП[РА]
+    MemoryWrite,
+    /// БР в регистр
+    Output(Register),
+    /// Флаг переноса C
+    Carry,
+    /// Флаги N и Z по БР
+    Flags,
+    /// Обмен с ВУ и управление прерываниями
+    Io,
+    /// Управляющая микрокоманда проверяет бит регистра
+    Check(Register, u16),
+}
+
+/// Связи, которые задействует микрокоманда
+pub fn active_paths(opcode: u16) -> Vec<Path> {
+    match sub_sum(opcode, 15, 14) {
+        0 => operational0(OperationalCommand0(opcode)),
+        1 => operational1(OperationalCommand1(opcode)),
+        _ => {
+            let command = ControlCommand(opcode);
+            vec![Path::Check(command.register(), command.bit_location())]
+        }
+    }
+}
+
+fn operational0(command: OperationalCommand0) -> Vec<Path> {
+    // при сдвиге больше ничего не происходит
+    if command.shift() != Shift::None {
+        return vec![Path::Shift];
+    }
+
+    let mut result = vec![];
+    match command.memory() {
+        Memory::Read => result.push(Path::MemoryRead),
+        Memory::Write => result.push(Path::MemoryWrite),
+        Memory::None => {}
+    }
+    result.extend(command.left_input().map(Path::Left));
+    result.extend(command.right_input().map(Path::Right));
+    result.push(Path::Alu);
+    result
+}
+
+fn operational1(command: OperationalCommand1) -> Vec<Path> {
+    if command.hlt() {
+        return vec![];
+    }
+    // при обмене с ВУ остальные поля не исполняются
+    if !command.io().is_empty() {
+        return vec![Path::Io];
+    }
+
+    let mut result = vec![];
+    if command.c() != CUpdate::None {
+        result.push(Path::Carry);
+    }
+    if command.nz() != NZUpdate::None {
+        result.push(Path::Flags);
+    }
+    for register in command.output().unwrap_or_default() {
+        result.push(Path::Output(register));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datapath::{active_paths, Path};
+    use crate::model::Register;
+
+    #[test]
+    fn paths() {
+        // БР = СК, чтение памяти
+        assert_eq!(
+            active_paths(0x0301),
+            vec![
+                Path::MemoryRead,
+                Path::Right(Register::CommandCounter),
+                Path::Alu
+            ]
+        );
+        // БР = А + РД
+        assert_eq!(
+            active_paths(0x1100),
+            vec![
+                Path::Left(Register::Counter),
+                Path::Right(Register::Data),
+                Path::Alu
+            ]
+        );
+        // сдвиг А влево, чтение памяти не выполняется
+        assert_eq!(active_paths(0x0009), vec![Path::Shift]);
+        // РА = БР, N, Z
+        assert_eq!(
+            active_paths(0x4031),
+            vec![Path::Flags, Path::Output(Register::Address)]
+        );
+        // C = БР[0], РД = БР
+        assert_eq!(
+            active_paths(0x4042),
+            vec![Path::Carry, Path::Output(Register::Data)]
+        );
+        // связь с ВУ
+        assert_eq!(active_paths(0x4100), vec![Path::Io]);
+        // останов
+        assert_eq!(active_paths(0x4008), vec![]);
+        // если РК(15) = 0, перейти на 0C
+        assert_eq!(
+            active_paths(0xAF0C),
+            vec![Path::Check(Register::Command, 0xF)]
+        );
+    }
+}
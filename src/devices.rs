@@ -0,0 +1,401 @@
+use crate::model::IOCell;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// То, что стоит за ВУ. По умолчанию ВУ управляется руками через панель "Внешние устройства",
+/// но его можно привязать к файлу или к сценарию, чтобы гонять программы без участия человека.
+#[derive(Clone, Default)]
+pub enum DeviceDriver {
+    #[default]
+    Manual,
+    File(FileDevice),
+    Scripted(ScriptedDevice),
+}
+
+impl DeviceDriver {
+    pub fn title(&self) -> String {
+        match self {
+            DeviceDriver::Manual => "Вручную".to_string(),
+            DeviceDriver::File(f) => f.title(),
+            DeviceDriver::Scripted(s) => s.title(),
+        }
+    }
+
+    /// Вызывается в начале каждого цикла команды. `step` - номер этого цикла.
+    pub fn tick(&mut self, step: u64, cell: &mut IOCell) {
+        match self {
+            DeviceDriver::Manual => {}
+            DeviceDriver::File(f) => f.tick(cell),
+            DeviceDriver::Scripted(s) => s.tick(step, cell),
+        }
+    }
+
+    /// Программа выполнила IN
+    pub fn on_input(&mut self) {
+        if let DeviceDriver::File(f) = self {
+            f.consumed = true;
+        }
+    }
+
+    /// Программа выполнила OUT
+    pub fn on_output(&mut self, data: u8) -> Result<(), String> {
+        match self {
+            DeviceDriver::Manual => Ok(()),
+            DeviceDriver::File(f) => f.on_output(data),
+            DeviceDriver::Scripted(s) => {
+                s.output.push(data);
+                Ok(())
+            }
+        }
+    }
+
+    /// Копия для ЭВМ-двойника: ведет себя так же, но ничего не пишет на диск
+    pub fn detached(&self) -> DeviceDriver {
+        match self {
+            DeviceDriver::File(f) => DeviceDriver::File(FileDevice {
+                output_name: None,
+                ..f.clone()
+            }),
+            other => other.clone(),
+        }
+    }
+
+    /// То, от чего зависит дальнейшее поведение ВУ, для поиска зацикливания. `None`, если оно
+    /// зависит еще и от номера цикла команды: в сценарии остались события
+    pub fn loop_state(&self) -> Option<(usize, bool)> {
+        match self {
+            DeviceDriver::Manual => Some((0, false)),
+            DeviceDriver::File(f) => Some((f.position, f.consumed)),
+            DeviceDriver::Scripted(s) if s.cursor < s.events.len() => None,
+            DeviceDriver::Scripted(s) => Some((s.cursor, false)),
+        }
+    }
+
+    pub fn output(&self) -> &[u8] {
+        match self {
+            DeviceDriver::Manual => &[],
+            DeviceDriver::File(f) => f.output.as_slice(),
+            DeviceDriver::Scripted(s) => s.output.as_slice(),
+        }
+    }
+}
+
+/// ВУ, которое берет байты для IN из одного файла и складывает байты из OUT в другой.
+///
+/// Следующий байт подается только после того, как программа прочитала текущий (IN)
+/// и сбросила флаг готовности (CLF). Пока байты есть, ВУ снова становится готовым
+/// в начале следующего цикла команды.
+#[derive(Clone)]
+pub struct FileDevice {
+    input_name: Option<String>,
+    input: Vec<u8>,
+    position: usize,
+    consumed: bool,
+    output_name: Option<String>,
+    output: Vec<u8>,
+}
+
+impl FileDevice {
+    pub fn new() -> FileDevice {
+        FileDevice {
+            input_name: None,
+            input: vec![],
+            position: 0,
+            consumed: false,
+            output_name: None,
+            output: vec![],
+        }
+    }
+
+    pub fn with_input(mut self, file: &str) -> Result<FileDevice, String> {
+        self.input = std::fs::read(file)
+            .map_err(|e| format!("Не могу прочитать файл \"{file}\": {e}"))?;
+        self.input_name = Some(file.to_string());
+        self.position = 0;
+        self.consumed = false;
+        Ok(self)
+    }
+
+    #[cfg(test)]
+    pub fn with_input_bytes(mut self, input: Vec<u8>) -> FileDevice {
+        self.input = input;
+        self.position = 0;
+        self.consumed = false;
+        self
+    }
+
+    pub fn with_output(mut self, file: &str) -> Result<FileDevice, String> {
+        OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(file)
+            .map_err(|e| format!("Не могу открыть файл \"{file}\": {e}"))?;
+        self.output_name = Some(file.to_string());
+        Ok(self)
+    }
+
+    fn title(&self) -> String {
+        let mut parts = vec![];
+        if let Some(name) = &self.input_name {
+            parts.push(format!("<{} ({}/{})", name, self.position, self.input.len()));
+        }
+        if let Some(name) = &self.output_name {
+            parts.push(format!(">{} ({})", name, self.output.len()));
+        }
+        if parts.is_empty() {
+            "Файл".to_string()
+        } else {
+            parts.join(" ")
+        }
+    }
+
+    fn tick(&mut self, cell: &mut IOCell) {
+        if cell.ready {
+            return;
+        }
+
+        if self.consumed {
+            self.position += 1;
+            self.consumed = false;
+        }
+
+        if let Some(byte) = self.input.get(self.position) {
+            cell.data = *byte;
+            cell.ready = true;
+        } else if self.input_name.is_none() && self.output_name.is_some() {
+            // устройство вывода всегда готово принять следующий байт
+            cell.ready = true;
+        }
+    }
+
+    fn on_output(&mut self, data: u8) -> Result<(), String> {
+        self.output.push(data);
+
+        let Some(name) = &self.output_name else {
+            return Ok(());
+        };
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(name)
+            .map_err(|e| format!("Не могу открыть файл \"{name}\": {e}"))?;
+        f.write_all(&[data])
+            .map_err(|e| format!("Не могу записать в файл \"{name}\": {e}"))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptAction {
+    Ready(Option<u8>),
+    Data(u8),
+    Clear,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptEvent {
+    pub step: u64,
+    pub action: ScriptAction,
+}
+
+/// ВУ, которое живет по расписанию. Расписание описывается построчно:
+///
+/// ```text
+/// # шаг: действие
+/// 120: ready 41   # на 120 цикле команды положить 41 и выставить готовность
+/// 200: data 7F    # положить 7F, готовность не трогать
+/// 250: ready      # выставить готовность со старым значением
+/// 300: clear      # сбросить готовность
+/// ```
+///
+/// Номер шага десятичный, байты шестнадцатеричные. Все что выводится через OUT запоминается.
+#[derive(Clone)]
+pub struct ScriptedDevice {
+    name: String,
+    events: Vec<ScriptEvent>,
+    cursor: usize,
+    output: Vec<u8>,
+}
+
+impl ScriptedDevice {
+    pub fn new<S: Into<String>>(name: S, events: Vec<ScriptEvent>) -> ScriptedDevice {
+        let mut events = events;
+        events.sort_by_key(|e| e.step);
+        ScriptedDevice {
+            name: name.into(),
+            events,
+            cursor: 0,
+            output: vec![],
+        }
+    }
+
+    pub fn from_file(file: &str) -> Result<ScriptedDevice, String> {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| format!("Не могу прочитать файл \"{file}\": {e}"))?;
+        Ok(ScriptedDevice::new(file, parse_script(text.as_str())?))
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "Сценарий {} ({}/{})",
+            self.name,
+            self.cursor,
+            self.events.len()
+        )
+    }
+
+    fn tick(&mut self, step: u64, cell: &mut IOCell) {
+        while let Some(event) = self.events.get(self.cursor) {
+            if event.step > step {
+                break;
+            }
+            match event.action {
+                ScriptAction::Ready(data) => {
+                    if let Some(data) = data {
+                        cell.data = data;
+                    }
+                    cell.ready = true;
+                }
+                ScriptAction::Data(data) => cell.data = data,
+                ScriptAction::Clear => cell.ready = false,
+            }
+            self.cursor += 1;
+        }
+    }
+}
+
+pub fn parse_script(text: &str) -> Result<Vec<ScriptEvent>, String> {
+    let mut result = vec![];
+
+    for (line, line_num) in text.lines().zip(1..) {
+        let line = line.split('#').next().unwrap_or(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        macro_rules! err {
+            ($msg:expr) => {
+                format!("Ошибка в строке {}. Номер строки: {}. Сообщение: {}", line, line_num, $msg)
+            };
+        }
+
+        let Some((step, action)) = line.split_once(':') else {
+            return Err(err!("Ожидалось \"шаг: действие\""));
+        };
+
+        let Ok(step) = step.trim().parse::<u64>() else {
+            return Err(err!(format!("Не могу распарсить номер шага {}", step.trim())));
+        };
+
+        let mut words = action.split_ascii_whitespace();
+        let name = words.next().unwrap_or("").to_lowercase();
+        let arg = words.next();
+        if let Some(extra) = words.next() {
+            return Err(err!(format!("Неожиданные штуки: {extra}")));
+        }
+
+        let byte = match arg {
+            Some(arg) => match u8::from_str_radix(arg, 16) {
+                Ok(v) => Some(v),
+                Err(_) => return Err(err!(format!("Не могу распарсить байт {arg}"))),
+            },
+            None => None,
+        };
+
+        let action = match (name.as_str(), byte) {
+            ("ready", data) => ScriptAction::Ready(data),
+            ("data", Some(data)) => ScriptAction::Data(data),
+            ("data", None) => return Err(err!("Для data нужно указать байт")),
+            ("clear", None) => ScriptAction::Clear,
+            ("clear", Some(_)) => return Err(err!("clear не принимает аргументов")),
+            (name, _) => return Err(err!(format!("Неизвестное действие {name}"))),
+        };
+
+        result.push(ScriptEvent { step, action });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::{
+        parse_script, DeviceDriver, FileDevice, ScriptAction, ScriptEvent, ScriptedDevice,
+    };
+    use crate::model::IOCell;
+
+    #[test]
+    fn parse() {
+        let events = parse_script("# comment\n120: ready 41\n\n200: data 7f # c\n300: clear\n5: ready")
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ScriptEvent { step: 120, action: ScriptAction::Ready(Some(0x41)) },
+                ScriptEvent { step: 200, action: ScriptAction::Data(0x7F) },
+                ScriptEvent { step: 300, action: ScriptAction::Clear },
+                ScriptEvent { step: 5, action: ScriptAction::Ready(None) },
+            ]
+        );
+
+        assert!(parse_script("120 ready").is_err());
+        assert!(parse_script("x: ready").is_err());
+        assert!(parse_script("1: data").is_err());
+        assert!(parse_script("1: jump 10").is_err());
+    }
+
+    #[test]
+    fn scripted_tick() {
+        let events = parse_script("3: ready 41\n5: clear\n5: data 7\n8: ready").unwrap();
+        let mut driver = DeviceDriver::Scripted(ScriptedDevice::new("test", events));
+        let mut cell = IOCell::new();
+
+        driver.tick(2, &mut cell);
+        assert!(!cell.ready);
+
+        driver.tick(3, &mut cell);
+        assert!(cell.ready);
+        assert_eq!(cell.data, 0x41);
+
+        // пропущенные шаги не теряют события
+        driver.tick(6, &mut cell);
+        assert!(!cell.ready);
+        assert_eq!(cell.data, 7);
+
+        driver.tick(8, &mut cell);
+        assert!(cell.ready);
+        assert_eq!(cell.data, 7);
+        assert_eq!(driver.title(), "Сценарий test (4/4)");
+
+        driver.on_output(0x55).unwrap();
+        assert_eq!(driver.output(), [0x55]);
+    }
+
+    #[test]
+    fn file_input_handshake() {
+        let mut driver = DeviceDriver::File(FileDevice::new().with_input_bytes(vec![1, 2]));
+        let mut cell = IOCell::new();
+
+        driver.tick(0, &mut cell);
+        assert!(cell.ready);
+        assert_eq!(cell.data, 1);
+
+        // CLF без IN не должен терять байт
+        cell.ready = false;
+        driver.tick(1, &mut cell);
+        assert_eq!(cell.data, 1);
+
+        driver.on_input();
+        cell.ready = false;
+        driver.tick(2, &mut cell);
+        assert!(cell.ready);
+        assert_eq!(cell.data, 2);
+
+        driver.on_input();
+        cell.ready = false;
+        driver.tick(3, &mut cell);
+        assert!(!cell.ready);
+    }
+}
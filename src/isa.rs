@@ -0,0 +1,825 @@
+//! Исполнение команд основной памяти целиком, без микропрограммы.
+//!
+//! Повторяет поведение стандартной микропрограммы (mc.txt) вплоть до значений служебных регистров
+//! (РА, РД, РК, БР, СчМК, РМК) и ее странностей, но не тратит время на разбор микрокоманд.
+//! Годится только пока в памяти МПУ лежит стандартная микропрограмма.
+
+use crate::model::{Computer, FETCH_START};
+use crate::parse::mc::ExecutionResult;
+use crate::utils::bit_registers::bit_at;
+
+/// СчМК после микрокоманды останова
+const HALTED_COUNTER: u8 = 0x89;
+/// Микрокоманда останова (88)
+const HALT_MICRO_COMMAND: u16 = 0x4008;
+/// Микрокоманда безусловного перехода на начало цикла выборки команды
+const FETCH_JUMP_MICRO_COMMAND: u16 = 0x8301;
+
+/// Куда микропрограмма уходит после исполнения команды
+enum Exit {
+    /// Проверка прерывания (8F), затем проверка тумблера
+    Interrupt,
+    /// Сразу проверка тумблера (F5). Так заканчиваются неопределенные команды
+    Lever,
+    /// Сразу на начало следующей команды. Так заканчиваются EI и DI
+    Fetch,
+    /// Остановочка (88)
+    Halt,
+}
+
+fn read(computer: &Computer, address: u16) -> u16 {
+    computer.general_memory.borrow_mut().data[address as usize].read(computer.steps)
+}
+
+fn write(computer: &Computer, address: u16, value: u16) {
+    computer.general_memory.borrow_mut().data[address as usize].write(value, computer.steps)
+}
+
+/// C = БР[16]; N = БР < 0; Z = БР == 0; А = БР
+///
+/// Как и в микрокоманде, C только устанавливается, но никогда не сбрасывается
+fn assign_counter(computer: &mut Computer, with_carry: bool) {
+    let registers = &mut computer.registers;
+    if with_carry && registers.r_buffer > 0xFFFF {
+        registers.r_buffer &= 0xFFFF;
+        registers.set_overflow(true);
+    }
+    registers.set_null(registers.r_buffer == 0);
+    registers.set_negative(bit_at(registers.r_buffer as u16, 15));
+    registers.r_counter = registers.r_buffer as u16;
+}
+
+/// Левый + Правый + 1. Единица прибавляется к левому операнду в 16 битах, ровно как в АЛУ
+fn plus_one(left: u16, right: u16) -> u32 {
+    (right as u32) + (left.wrapping_add(1) as u32)
+}
+
+fn execute_address(computer: &mut Computer) -> Exit {
+    let command = computer.registers.r_command;
+
+    if bit_at(command, 11) {
+        // косвенная адресация
+        let registers = &mut computer.registers;
+        registers.r_buffer = registers.r_data as u32;
+        registers.r_address = registers.r_data & 0x7FF;
+        registers.r_buffer = 0;
+        let pointer = read(computer, computer.registers.r_address);
+        computer.registers.r_data = pointer;
+
+        if (command >> 3) & 0xFF == 1 {
+            // ячейки 8-F автоинкрементные
+            let registers = &mut computer.registers;
+            let pointer = registers.r_data;
+            registers.r_data = pointer.wrapping_add(1);
+            registers.r_buffer = 0;
+            write(computer, computer.registers.r_address, computer.registers.r_data);
+            let registers = &mut computer.registers;
+            registers.r_buffer = 0xFFFF + registers.r_data as u32;
+            registers.r_data = registers.r_buffer as u16;
+        }
+    }
+
+    let opcode = command >> 12;
+
+    if opcode < 8 {
+        let registers = &mut computer.registers;
+        registers.r_buffer = registers.r_data as u32;
+        registers.r_address = registers.r_data & 0x7FF;
+    }
+
+    let address = computer.registers.r_address;
+
+    match opcode {
+        // ISZ
+        0x0 => {
+            computer.registers.r_buffer = 0;
+            let value = read(computer, address).wrapping_add(1);
+            computer.registers.r_data = value;
+            computer.registers.r_buffer = 0;
+            write(computer, address, value);
+            if !bit_at(value, 15) {
+                let registers = &mut computer.registers;
+                registers.r_buffer = registers.r_command_counter as u32 + 1;
+                registers.r_command_counter = registers.r_buffer as u16 & 0x7FF;
+            }
+        }
+        // AND
+        0x1 => {
+            computer.registers.r_buffer = 0;
+            computer.registers.r_data = read(computer, address);
+            let registers = &mut computer.registers;
+            registers.r_buffer = (registers.r_counter & registers.r_data) as u32;
+            assign_counter(computer, false);
+        }
+        // JSR
+        0x2 => {
+            let registers = &mut computer.registers;
+            registers.r_buffer = registers.r_data as u32 + 1;
+            registers.r_command = registers.r_buffer as u16;
+            registers.r_buffer = registers.r_command_counter as u32;
+            registers.r_data = registers.r_buffer as u16;
+            registers.r_buffer = registers.r_command as u32;
+            write(computer, address, computer.registers.r_data);
+            let registers = &mut computer.registers;
+            registers.r_command_counter = registers.r_buffer as u16 & 0x7FF;
+        }
+        // MOV
+        0x3 => {
+            let registers = &mut computer.registers;
+            registers.r_buffer = registers.r_counter as u32;
+            registers.r_data = registers.r_counter;
+            registers.r_buffer = 0;
+            write(computer, address, computer.registers.r_data);
+        }
+        // ADD, ADC, SUB
+        0x4..=0x6 => {
+            computer.registers.r_buffer = 0;
+            computer.registers.r_data = read(computer, address);
+            let registers = &mut computer.registers;
+            registers.r_buffer = match opcode {
+                0x5 if registers.get_overflow() => plus_one(registers.r_counter, registers.r_data),
+                0x6 => plus_one(registers.r_counter, !registers.r_data),
+                _ => registers.r_counter as u32 + registers.r_data as u32,
+            };
+            assign_counter(computer, true);
+        }
+        // HZA7
+        0x7 => {
+            computer.registers.r_buffer = 0;
+            computer.registers.r_data = read(computer, address);
+            return Exit::Lever;
+        }
+        // BCS, BPL, BMI, BEQ, BR
+        0x8..=0xC => {
+            let registers = &computer.registers;
+            let jump = match opcode {
+                0x8 => registers.get_overflow(),
+                0x9 => !registers.get_negative(),
+                0xA => registers.get_negative(),
+                0xB => registers.get_null(),
+                _ => true,
+            };
+            if jump {
+                let registers = &mut computer.registers;
+                registers.r_buffer = registers.r_data as u32;
+                registers.r_command_counter = registers.r_data & 0x7FF;
+            }
+        }
+        // HZAD
+        _ => {
+            computer.registers.r_buffer = 0;
+            return Exit::Lever;
+        }
+    }
+
+    Exit::Interrupt
+}
+
+fn execute_non_address(computer: &mut Computer) -> Exit {
+    let command = computer.registers.r_command;
+    let registers = &mut computer.registers;
+    let counter = registers.r_counter;
+
+    match (command >> 8) & 0xF {
+        // HLT
+        0x0 => return Exit::Halt,
+        // NOP
+        0x1 => {}
+        // CLA
+        0x2 => {
+            registers.r_buffer = 0;
+            assign_counter(computer, false);
+        }
+        // CLC
+        0x3 => registers.set_overflow(false),
+        // CMA
+        0x4 => {
+            registers.r_buffer = !counter as u32;
+            assign_counter(computer, false);
+        }
+        // CMC
+        0x5 => {
+            let c = registers.get_overflow();
+            registers.set_overflow(!c);
+        }
+        // ROL
+        0x6 => {
+            let c = registers.get_overflow();
+            registers.r_buffer = ((counter as u32) << 1) & 0x1FFFF;
+            if c {
+                registers.r_buffer |= 0x1;
+            }
+            assign_counter(computer, true);
+        }
+        // ROR
+        0x7 => {
+            let c = registers.get_overflow();
+            registers.r_buffer = counter as u32 >> 1;
+            if c {
+                registers.r_buffer |= 0x8000;
+            }
+            if bit_at(counter, 0) {
+                registers.r_buffer |= 0x10000;
+            }
+            assign_counter(computer, true);
+        }
+        // INC
+        0x8 => {
+            registers.r_buffer = plus_one(counter, 0);
+            assign_counter(computer, true);
+        }
+        // DEC
+        0x9 => {
+            registers.r_buffer = counter as u32 + 0xFFFF;
+            assign_counter(computer, true);
+        }
+        // EI
+        0xA => {
+            registers.set_allow_interrupt(true);
+            return Exit::Fetch;
+        }
+        // DI
+        0xB => {
+            registers.set_allow_interrupt(false);
+            registers.set_interrupt(false);
+            return Exit::Fetch;
+        }
+        // HZC, HZD, HZE, HZF
+        _ => {
+            registers.r_buffer = 0;
+            return Exit::Lever;
+        }
+    }
+
+    Exit::Interrupt
+}
+
+fn execute_io(computer: &mut Computer) -> Exit {
+    if computer.registers.r_data == computer.registers.r_command {
+        computer.registers.set_io(true);
+        computer.process_io_command();
+    }
+
+    Exit::Interrupt
+}
+
+fn enter_interrupt(computer: &mut Computer) {
+    computer.on_interrupt_entry();
+
+    let registers = &mut computer.registers;
+    let return_address = registers.r_command_counter;
+    registers.r_address = 0;
+    registers.r_data = return_address;
+    registers.r_buffer = 1;
+    registers.r_command_counter = 1;
+    registers.set_allow_interrupt(false);
+    registers.set_interrupt(false);
+    write(computer, 0, return_address);
+}
+
+/// Выполняет одну команду начиная с цикла выборки (СчМК должен быть равен 01).
+///
+/// Возвращает `Halted`, если микропрограмма остановилась бы после этой команды, иначе `Success`.
+/// В последнем случае СчМК снова указывает на цикл выборки.
+pub fn step(computer: &mut Computer) -> ExecutionResult {
+    debug_assert_eq!(computer.registers.r_micro_command_counter, FETCH_START);
+
+    // выборка команды
+    let registers = &mut computer.registers;
+    let command_counter = registers.r_command_counter;
+    registers.r_address = command_counter & 0x7FF;
+    let command = read(computer, computer.registers.r_address);
+    computer.registers.r_data = command;
+    let registers = &mut computer.registers;
+    registers.r_command_counter = (command_counter + 1) & 0x7FF;
+    registers.r_buffer = registers.r_data as u32;
+    registers.r_command = registers.r_data;
+
+    let exit = if command >> 12 == 0xF {
+        execute_non_address(computer)
+    } else if command >> 12 == 0xE {
+        execute_io(computer)
+    } else {
+        execute_address(computer)
+    };
+
+    computer.update_interrupt_request();
+
+    let halt = match exit {
+        Exit::Interrupt if computer.registers.get_interupt() => {
+            enter_interrupt(computer);
+            false
+        }
+        Exit::Interrupt | Exit::Lever => !computer.registers.get_lever(),
+        Exit::Fetch => false,
+        Exit::Halt => true,
+    };
+
+    computer.update_interrupt_request();
+
+    let registers = &mut computer.registers;
+    if halt {
+        registers.r_micro_command = HALT_MICRO_COMMAND;
+        registers.r_micro_command_counter = HALTED_COUNTER;
+        ExecutionResult::Halted
+    } else {
+        registers.r_micro_command = FETCH_JUMP_MICRO_COMMAND;
+        registers.r_micro_command_counter = FETCH_START;
+        ExecutionResult::Success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Computer, Engine, FETCH_START};
+    use crate::parse::mc::ExecutionResult;
+
+    /// Все, что видно программисту и отладчику после команды
+    #[derive(Debug, PartialEq, Eq)]
+    struct Snapshot {
+        registers: [u32; 9],
+        memory: Vec<u16>,
+    }
+
+    fn snapshot(computer: &Computer) -> Snapshot {
+        let r = &computer.registers;
+        Snapshot {
+            registers: [
+                r.r_micro_command_counter as u32,
+                r.r_status as u32,
+                r.r_micro_command as u32,
+                r.r_buffer,
+                r.r_address as u32,
+                r.r_command as u32,
+                r.r_data as u32,
+                r.r_command_counter as u32,
+                r.r_counter as u32,
+            ],
+            memory: computer
+                .general_memory
+                .borrow()
+                .data
+                .iter()
+                .map(|c| c.get())
+                .collect(),
+        }
+    }
+
+    /// Простенький xorshift, чтобы не тащить зависимость ради тестов
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u16 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 16) as u16
+        }
+    }
+
+    /// Исполняет ровно одну команду: до возврата на цикл выборки или до останова.
+    ///
+    /// `command_step` тут не годится: обработчик прерывания, начинающийся с EI, при
+    /// неснятой готовности ВУ крутится вечно и до HLT никогда не доходит
+    fn instruction(computer: &mut Computer) {
+        while !matches!(computer.step(), ExecutionResult::Halted)
+            && computer.registers.r_micro_command_counter != FETCH_START
+        {}
+    }
+
+    /// Гоняет одну и ту же память на микропрограмме и на движке команд и сравнивает
+    /// состояние после каждой команды.
+    ///
+    /// Движок команд переписан с той же микропрограммы, регистр за регистром, так что это
+    /// сравнение ловит только расхождения между ними: пропущенный служебный регистр, другой
+    /// порядок флагов, забытую странность. Ошибку, которую повторяют оба, оно не увидит - для
+    /// этого есть `instruction_set` с ожидаемыми результатами из описания команд
+    fn compare(memory: &[(u16, u16)], setup: impl Fn(&mut Computer), commands: usize) {
+        let mut micro = Computer::new();
+        let mut isa = Computer::new();
+        isa.engine = Engine::Instruction;
+
+        for computer in [&mut micro, &mut isa] {
+            {
+                let mut mem = computer.general_memory.borrow_mut();
+                for (address, value) in memory {
+                    mem.data[*address as usize].set(*value);
+                }
+            }
+            setup(computer);
+            computer.registers.set_execute_by_tick(false);
+            computer.registers.set_lever(false);
+            computer.registers.set_program_mode(false);
+        }
+        assert_eq!(snapshot(&micro), snapshot(&isa));
+
+        for n in 0..commands {
+            let before = micro.registers.r_command_counter;
+            let command = micro.general_memory.borrow().data[before as usize & 0x7FF].get();
+            instruction(&mut micro);
+            instruction(&mut isa);
+            assert_eq!(
+                snapshot(&micro),
+                snapshot(&isa),
+                "разошлись на {} команде {:0>4X} по адресу {:0>3X}",
+                n,
+                command,
+                before
+            );
+        }
+    }
+
+    #[test]
+    fn hand_written() {
+        // сумма массива через ISZ и косвенную автоинкрементную адресацию
+        compare(
+            &[
+                (0x008, 0x0030), // указатель на массив
+                (0x009, 0xFFFD), // -3, счетчик
+                (0x010, 0xF200), // CLA
+                (0x011, 0x4808), // ADD (8)+
+                (0x012, 0x0009), // ISZ 9
+                (0x013, 0xC011), // BR 11
+                (0x014, 0x3020), // MOV 20
+                (0x015, 0xF000), // HLT
+                (0x030, 0x7FFF),
+                (0x031, 0x0002),
+                (0x032, 0x8000),
+            ],
+            |c| c.registers.r_command_counter = 0x10,
+            20,
+        );
+
+        // подпрограмма, сдвиги и арифметика с переносом
+        compare(
+            &[
+                (0x010, 0x2020), // JSR 20
+                (0x011, 0xF600), // ROL
+                (0x012, 0xF700), // ROR
+                (0x013, 0xF500), // CMC
+                (0x014, 0x5030), // ADC 30
+                (0x015, 0x6031), // SUB 31
+                (0x016, 0xF900), // DEC
+                (0x017, 0xF800), // INC
+                (0x018, 0xF400), // CMA
+                (0x019, 0x1031), // AND 31
+                (0x01A, 0x8010), // BCS 10
+                (0x01B, 0xF000), // HLT
+                (0x020, 0x0000),
+                (0x021, 0xF300), // CLC
+                (0x022, 0xC820), // BR (20)
+                (0x030, 0xFFFF),
+                (0x031, 0x8001),
+            ],
+            |c| {
+                c.registers.r_command_counter = 0x10;
+                c.registers.r_counter = 0x8001;
+            },
+            40,
+        );
+
+        // прерывание от ВУ и неопределенные команды
+        compare(
+            &[
+                (0x001, 0xE303), // CLF 3
+                (0x002, 0xFA00), // EI
+                (0x003, 0xC800), // BR (0)
+                (0x010, 0xFA00), // EI
+                (0x011, 0xF100), // NOP
+                (0x012, 0x7000), // HZA7
+                (0x013, 0xD000), // HZAD
+                (0x014, 0xFC00), // HZC
+                (0x015, 0xE103), // TSF 3
+                (0x016, 0xF000), // HLT
+            ],
+            |c| {
+                c.registers.r_command_counter = 0x10;
+                c.io_devices[3].ready = true;
+            },
+            20,
+        );
+    }
+
+    /// Случайная память попадает в редкие ветки (автоинкрементные ячейки 8-F,
+    /// неопределенные коды, ввод-вывод), но проверяет только согласие движков между собой
+    #[test]
+    fn random_programs() {
+        let mut random = Random(0x2545F4914F6CDD1D);
+
+        for _ in 0..50 {
+            let memory = (0..0x800)
+                .map(|address| (address, random.next()))
+                .collect::<Vec<(u16, u16)>>();
+            let start = random.next() & 0x7FF;
+            let counter = random.next();
+            let status = random.next() & 0b111;
+            let ready = random.next();
+
+            compare(
+                &memory,
+                |c| {
+                    c.registers.r_command_counter = start;
+                    c.registers.r_counter = counter;
+                    c.registers.r_status |= status;
+                    for (num, cell) in c.io_devices.iter_mut().enumerate() {
+                        cell.ready = ready & (1 << num) != 0;
+                    }
+                },
+                200,
+            );
+        }
+    }
+
+    /// То, что видит программа: А, флаги C, N, Z (буквами), СК и нужные ячейки памяти
+    #[derive(Debug, PartialEq, Eq)]
+    struct State {
+        counter: u16,
+        flags: String,
+        command_counter: u16,
+        memory: Vec<(u16, u16)>,
+    }
+
+    fn state(counter: u16, flags: &str, command_counter: u16, memory: &[(u16, u16)]) -> State {
+        State {
+            counter,
+            flags: flags.to_string(),
+            command_counter,
+            memory: memory.to_vec(),
+        }
+    }
+
+    /// Выполняет одну команду на обоих движках и сверяет результат с ожидаемым по описанию
+    /// системы команд, а не с другим движком. Служебные регистры (РА, РД, БР...) не проверяются
+    fn spec(command: u16, before: State, after: State) {
+        for engine in [Engine::Microcode, Engine::Instruction] {
+            let mut computer = Computer::new();
+            computer.engine = engine;
+            {
+                let mut mem = computer.general_memory.borrow_mut();
+                mem.data[before.command_counter as usize].set(command);
+                for (address, value) in &before.memory {
+                    mem.data[*address as usize].set(*value);
+                }
+            }
+            let registers = &mut computer.registers;
+            registers.r_counter = before.counter;
+            registers.r_command_counter = before.command_counter;
+            registers.set_overflow(before.flags.contains('C'));
+            registers.set_negative(before.flags.contains('N'));
+            registers.set_null(before.flags.contains('Z'));
+            registers.set_execute_by_tick(false);
+            registers.set_lever(false);
+            registers.set_program_mode(false);
+
+            // до цикла выборки движок команд не вступает в дело
+            while computer.registers.r_micro_command_counter != FETCH_START {
+                computer.micro_step();
+            }
+            instruction(&mut computer);
+
+            let registers = &computer.registers;
+            let flags = [
+                (registers.get_overflow(), "C"),
+                (registers.get_negative(), "N"),
+                (registers.get_null(), "Z"),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| *name)
+            .collect::<String>();
+            let memory = computer.general_memory.borrow();
+            let result = State {
+                counter: registers.r_counter,
+                flags,
+                command_counter: registers.r_command_counter,
+                memory: after
+                    .memory
+                    .iter()
+                    .map(|(address, _)| (*address, memory.data[*address as usize].get()))
+                    .collect(),
+            };
+            assert_eq!(
+                result,
+                after,
+                "команда {:0>4X} на движке \"{}\"",
+                command,
+                engine.title()
+            );
+        }
+    }
+
+    /// Ожидаемые результаты выписаны вручную из описания команд БЭВМ. Это основная проверка
+    /// того, что команды делают то, что должны, а `compare` только сверяет движки друг с другом.
+    ///
+    /// Стандартная микропрограмма флаг C только устанавливает, поэтому там, где команда
+    /// по описанию сбросила бы C, он на входе сброшен
+    #[test]
+    fn instruction_set() {
+        let m = 0x20;
+
+        // ADD 20
+        spec(
+            0x4020,
+            state(0x0001, "", 0x10, &[(m, 2)]),
+            state(0x0003, "", 0x11, &[(m, 2)]),
+        );
+        spec(
+            0x4020,
+            state(0xFFFF, "", 0x10, &[(m, 1)]),
+            state(0x0000, "CZ", 0x11, &[(m, 1)]),
+        );
+        spec(
+            0x4020,
+            state(0x7FFF, "", 0x10, &[(m, 1)]),
+            state(0x8000, "N", 0x11, &[(m, 1)]),
+        );
+        // ADC 20
+        spec(
+            0x5020,
+            state(0x0001, "", 0x10, &[(m, 2)]),
+            state(0x0003, "", 0x11, &[(m, 2)]),
+        );
+        spec(
+            0x5020,
+            state(0xFFFF, "C", 0x10, &[(m, 0)]),
+            state(0x0000, "CZ", 0x11, &[(m, 0)]),
+        );
+        spec(
+            0x5020,
+            state(0x0001, "C", 0x10, &[(m, 0xFFFF)]),
+            state(0x0001, "C", 0x11, &[(m, 0xFFFF)]),
+        );
+        // SUB 20: без заема C = 1
+        spec(
+            0x6020,
+            state(0x0005, "", 0x10, &[(m, 3)]),
+            state(0x0002, "C", 0x11, &[(m, 3)]),
+        );
+        spec(
+            0x6020,
+            state(0x0003, "", 0x10, &[(m, 5)]),
+            state(0xFFFE, "N", 0x11, &[(m, 5)]),
+        );
+        spec(
+            0x6020,
+            state(0x0003, "", 0x10, &[(m, 3)]),
+            state(0x0000, "CZ", 0x11, &[(m, 3)]),
+        );
+        // AND 20: C не трогает
+        spec(
+            0x1020,
+            state(0xF0F0, "C", 0x10, &[(m, 0x0FF0)]),
+            state(0x00F0, "C", 0x11, &[(m, 0x0FF0)]),
+        );
+        spec(
+            0x1020,
+            state(0xF000, "", 0x10, &[(m, 0x0F00)]),
+            state(0x0000, "Z", 0x11, &[(m, 0x0F00)]),
+        );
+        spec(
+            0x1020,
+            state(0x8001, "", 0x10, &[(m, 0xFFFF)]),
+            state(0x8001, "N", 0x11, &[(m, 0xFFFF)]),
+        );
+        // MOV 20: флаги не трогает
+        spec(
+            0x3020,
+            state(0x1234, "N", 0x10, &[(m, 0)]),
+            state(0x1234, "N", 0x11, &[(m, 0x1234)]),
+        );
+        // ISZ 20: пропуск следующей команды, если результат неотрицательный
+        spec(
+            0x0020,
+            state(0, "", 0x10, &[(m, 0xFFFF)]),
+            state(0, "", 0x12, &[(m, 0)]),
+        );
+        spec(
+            0x0020,
+            state(0, "", 0x10, &[(m, 0xFFFD)]),
+            state(0, "", 0x11, &[(m, 0xFFFE)]),
+        );
+        spec(
+            0x0020,
+            state(0, "", 0x10, &[(m, 0x7FFF)]),
+            state(0, "", 0x11, &[(m, 0x8000)]),
+        );
+        // JSR 20: адрес возврата в ячейку, переход на следующую за ней
+        spec(
+            0x2020,
+            state(0, "", 0x10, &[(m, 0)]),
+            state(0, "", 0x21, &[(m, 0x11)]),
+        );
+        // BR 30
+        spec(0xC030, state(0, "", 0x10, &[]), state(0, "", 0x30, &[]));
+        // BCS 30
+        spec(0x8030, state(0, "C", 0x10, &[]), state(0, "C", 0x30, &[]));
+        spec(0x8030, state(0, "", 0x10, &[]), state(0, "", 0x11, &[]));
+        // BPL 30
+        spec(0x9030, state(0, "", 0x10, &[]), state(0, "", 0x30, &[]));
+        spec(0x9030, state(0, "N", 0x10, &[]), state(0, "N", 0x11, &[]));
+        // BMI 30
+        spec(0xA030, state(0, "N", 0x10, &[]), state(0, "N", 0x30, &[]));
+        spec(0xA030, state(0, "", 0x10, &[]), state(0, "", 0x11, &[]));
+        // BEQ 30
+        spec(0xB030, state(0, "Z", 0x10, &[]), state(0, "Z", 0x30, &[]));
+        spec(0xB030, state(0, "", 0x10, &[]), state(0, "", 0x11, &[]));
+        // ADD (20): косвенная адресация
+        spec(
+            0x4820,
+            state(0x0001, "", 0x10, &[(m, 0x30), (0x30, 5)]),
+            state(0x0006, "", 0x11, &[(m, 0x30), (0x30, 5)]),
+        );
+        // ADD (8)+: ячейки 8-F после обращения увеличиваются
+        spec(
+            0x4808,
+            state(0x0001, "", 0x10, &[(0x8, 0x30), (0x30, 5)]),
+            state(0x0006, "", 0x11, &[(0x8, 0x31), (0x30, 5)]),
+        );
+
+        // NOP
+        spec(
+            0xF100,
+            state(0x1234, "C", 0x10, &[]),
+            state(0x1234, "C", 0x11, &[]),
+        );
+        // CLA
+        spec(
+            0xF200,
+            state(0x8234, "N", 0x10, &[]),
+            state(0x0000, "Z", 0x11, &[]),
+        );
+        // CLC
+        spec(
+            0xF300,
+            state(0x1234, "C", 0x10, &[]),
+            state(0x1234, "", 0x11, &[]),
+        );
+        // CMA
+        spec(
+            0xF400,
+            state(0x00FF, "", 0x10, &[]),
+            state(0xFF00, "N", 0x11, &[]),
+        );
+        spec(
+            0xF400,
+            state(0xFFFF, "C", 0x10, &[]),
+            state(0x0000, "CZ", 0x11, &[]),
+        );
+        // CMC
+        spec(
+            0xF500,
+            state(0x1234, "", 0x10, &[]),
+            state(0x1234, "C", 0x11, &[]),
+        );
+        spec(
+            0xF500,
+            state(0x1234, "C", 0x10, &[]),
+            state(0x1234, "", 0x11, &[]),
+        );
+        // ROL: старший бит уходит в C, C приходит в младший
+        spec(
+            0xF600,
+            state(0x8001, "", 0x10, &[]),
+            state(0x0002, "C", 0x11, &[]),
+        );
+        spec(
+            0xF600,
+            state(0x4000, "C", 0x10, &[]),
+            state(0x8001, "CN", 0x11, &[]),
+        );
+        // ROR: младший бит уходит в C, C приходит в старший
+        spec(
+            0xF700,
+            state(0x0003, "C", 0x10, &[]),
+            state(0x8001, "CN", 0x11, &[]),
+        );
+        spec(
+            0xF700,
+            state(0x0004, "", 0x10, &[]),
+            state(0x0002, "", 0x11, &[]),
+        );
+        // INC. FFFF + 1 тут нет: АЛУ прибавляет единицу к левому входу в 16 битах,
+        // так что перенос теряется и C не устанавливается, хотя по описанию должен
+        spec(
+            0xF800,
+            state(0x0041, "", 0x10, &[]),
+            state(0x0042, "", 0x11, &[]),
+        );
+        spec(
+            0xF800,
+            state(0x7FFF, "", 0x10, &[]),
+            state(0x8000, "N", 0x11, &[]),
+        );
+        // DEC: то же, что прибавить FFFF
+        spec(
+            0xF900,
+            state(0x0001, "", 0x10, &[]),
+            state(0x0000, "CZ", 0x11, &[]),
+        );
+        spec(
+            0xF900,
+            state(0x0000, "", 0x10, &[]),
+            state(0xFFFF, "N", 0x11, &[]),
+        );
+    }
+}
@@ -2,12 +2,25 @@
 
 use crate::model::Computer;
 
+mod cfg;
+mod cli;
+mod datapath;
+mod devices;
+mod isa;
 mod model;
 mod parse;
+mod settings;
+mod trace;
 mod ui;
 mod utils;
 
 fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if cli::wanted(&args) {
+        cli::attach_console();
+        std::process::exit(cli::run(args));
+    }
+
     let computer = Computer::new();
 
     ui::gui::Gui::new(computer).run();
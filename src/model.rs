@@ -1,14 +1,17 @@
+use crate::devices::DeviceDriver;
 use crate::parse::general::{GeneralCommandInfo, GeneralParser};
 use crate::parse::mc::{parse, ExecutionResult, McParser, MicroCommandInfo};
 use crate::parse::{CommandInfo, Parser};
+use crate::utils::bit_registers::bit_at;
 use core::ops::{BitAnd, BitOr, BitXor, Shl};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, VecDeque};
 use std::io::{BufRead, BufReader};
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::rc::Rc;
-use std::time::SystemTime;
 
-#[derive(Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Register {
     McCounter,
 
@@ -26,11 +29,12 @@ pub enum Register {
 }
 
 impl Register {
-    pub fn format(&self, computer: &Computer) -> String {
+    /// Разрядность регистра
+    pub fn bits(&self) -> u32 {
         match self {
-            Register::McCounter => format!("{:0>2X}", computer.registers.r_micro_command_counter),
-            Register::Buffer => format!("{:0>5X}", computer.registers.r_buffer),
-            _ => format!("{:0>4X}", self.get(computer)),
+            Register::McCounter => 8,
+            Register::Buffer => 17,
+            _ => 16,
         }
     }
 
@@ -158,7 +162,10 @@ pub struct Memory<I: CommandInfo, P: Parser<I>> {
 #[derive(Clone)]
 pub struct MemoryCell {
     data: u16,
-    last_touched: SystemTime,
+    last_touched: u64,
+    last_read: u64,
+    reads: u32,
+    executions: u32,
     pub mnemonic: Option<String>,
     pub name: Option<String>,
 }
@@ -167,7 +174,10 @@ impl MemoryCell {
     pub fn new() -> MemoryCell {
         MemoryCell {
             data: 0,
-            last_touched: SystemTime::UNIX_EPOCH,
+            last_touched: 0,
+            last_read: 0,
+            reads: 0,
+            executions: 0,
             mnemonic: None,
             name: None,
         }
@@ -175,21 +185,322 @@ impl MemoryCell {
 
     pub fn set(&mut self, data: u16) {
         self.data = data;
-        self.last_touched = SystemTime::now();
+    }
+
+    /// Запись в ячейку самой ЭВМ на цикле команды `step` ([`Computer::steps`]).
+    /// В отличие от [`MemoryCell::set`] запоминается для тепловой карты
+    pub fn write(&mut self, data: u16, step: u64) {
+        self.data = data;
+        self.last_touched = step;
     }
 
     pub fn get(&self) -> u16 {
         self.data
     }
+
+    /// Чтение ячейки самой ЭВМ на цикле команды `step`. В отличие от [`MemoryCell::get`]
+    /// запоминается для тепловой карты
+    pub fn read(&mut self, step: u64) -> u16 {
+        self.last_read = step;
+        self.reads = self.reads.saturating_add(1);
+        self.data
+    }
+
+    /// Отмечает, что с этой ячейки началось выполнение команды или микрокоманды
+    pub fn execute(&mut self) {
+        self.executions = self.executions.saturating_add(1);
+    }
+
+    /// На каком цикле команды ЭВМ последний раз писала в ячейку. 0 - ни разу
+    pub fn last_touched(&self) -> u64 {
+        self.last_touched
+    }
+
+    /// На каком цикле команды ЭВМ последний раз читала ячейку. 0 - ни разу
+    pub fn last_read(&self) -> u64 {
+        self.last_read
+    }
+
+    pub fn reads(&self) -> u32 {
+        self.reads
+    }
+
+    pub fn executions(&self) -> u32 {
+        self.executions
+    }
+
+    /// Забывает, когда и сколько раз ячейку трогали. Значение остается
+    pub fn reset_heat(&mut self) {
+        self.last_touched = 0;
+        self.last_read = 0;
+        self.reads = 0;
+        self.executions = 0;
+    }
+}
+
+/// Ячейка, которая поменялась между двумя моментами
+#[derive(Clone)]
+pub struct MemoryDelta {
+    pub address: u16,
+    pub before: u16,
+    pub after: u16,
+}
+
+/// Запомненное содержимое основной памяти и МПУ, с которым потом можно сравнить текущее
+#[derive(Clone)]
+pub struct MemoryCheckpoint {
+    pub general: Vec<u16>,
+    pub micro: Vec<u16>,
+    /// Сколько команд было начато к этому моменту
+    pub step: u64,
+}
+
+impl MemoryCheckpoint {
+    /// Что поменялось с момента точки: в основной памяти и в МПУ
+    pub fn diff(&self, computer: &Computer) -> (Vec<MemoryDelta>, Vec<MemoryDelta>) {
+        (
+            computer.general_memory.borrow().diff(&self.general),
+            computer.mc_memory.borrow().diff(&self.micro),
+        )
+    }
+}
+
+/// По чему искать ячейки памяти
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MemorySearch {
+    /// Значение в хексе
+    Value,
+    /// Часть мнемоники без учета регистра
+    Mnemonic,
+    /// Метка из загруженного файла
+    Label,
+}
+
+impl MemorySearch {
+    pub const ALL: [MemorySearch; 3] = [
+        MemorySearch::Value,
+        MemorySearch::Mnemonic,
+        MemorySearch::Label,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            MemorySearch::Value => "Значение",
+            MemorySearch::Mnemonic => "Мнемоника",
+            MemorySearch::Label => "Метка",
+        }
+    }
+}
+
+impl<I: CommandInfo, P: Parser<I>> Memory<I, P> {
+    /// Адреса ячеек, подходящих под запрос
+    pub fn find(&self, search: MemorySearch, query: &str) -> Vec<u16> {
+        let query = query.trim().to_uppercase();
+        if query.is_empty() {
+            return vec![];
+        }
+        let value = u16::from_str_radix(&query, 16).ok();
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| match search {
+                MemorySearch::Value => value == Some(cell.get()),
+                MemorySearch::Mnemonic => self
+                    .parser
+                    .parse(cell.get())
+                    .mnemonic()
+                    .to_uppercase()
+                    .contains(&query),
+                MemorySearch::Label => {
+                    cell.name.as_ref().map(|n| n.to_uppercase()) == Some(query.clone())
+                }
+            })
+            .map(|(idx, _)| idx as u16)
+            .collect()
+    }
+
+    /// Значения всех ячеек, чтобы потом сравнить с ними через [`Memory::diff`]
+    pub fn values(&self) -> Vec<u16> {
+        self.data.iter().map(MemoryCell::get).collect()
+    }
+
+    /// Совпадает ли память с запомненными `values`. В отличие от `values` ничего не выделяет
+    pub fn same_values(&self, values: &[u16]) -> bool {
+        self.data
+            .iter()
+            .map(MemoryCell::get)
+            .eq(values.iter().copied())
+    }
+
+    /// Ячейки, значения которых отличаются от запомненных `values`
+    pub fn diff(&self, values: &[u16]) -> Vec<MemoryDelta> {
+        self.data
+            .iter()
+            .zip(values.iter())
+            .enumerate()
+            .filter(|(_, (cell, old))| cell.get() != **old)
+            .map(|(address, (cell, old))| MemoryDelta {
+                address: address as u16,
+                before: *old,
+                after: cell.get(),
+            })
+            .collect()
+    }
+
+    fn check_range(&self, range: &Range<u16>) -> Result<(), String> {
+        if range.start >= range.end || range.end as usize > self.data.len() {
+            return Err(format!(
+                "Диапазон {:X}-{:X} не помещается в память размером {:X}",
+                range.start,
+                range.end.saturating_sub(1),
+                self.data.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Записывает значение во все ячейки диапазона
+    pub fn fill(&mut self, range: Range<u16>, value: u16) -> Result<(), String> {
+        self.check_range(&range)?;
+        for cell in &mut self.data[range.start as usize..range.end as usize] {
+            cell.set(value);
+        }
+        Ok(())
+    }
+
+    /// Копирует диапазон на адрес `to`. При `relocate` адреса в командах копии, указывающие внутрь
+    /// диапазона, сдвигаются вместе с ним. При `cut` исходные ячейки, которые не перезаписаны
+    /// копией, обнуляются, а метки переезжают вместе с ними.
+    ///
+    /// Остальная память не меняется: что в ней на самом деле ссылка, а что данные, не угадать.
+    /// Какие ячейки стоит проверить, подскажет [Memory::references]
+    pub fn copy(
+        &mut self,
+        range: Range<u16>,
+        to: u16,
+        relocate: bool,
+        cut: bool,
+    ) -> Result<(), String> {
+        self.check_range(&range)?;
+        let len = range.end - range.start;
+        let target = to..to.saturating_add(len);
+        self.check_range(&target)?;
+
+        let offset = to as i32 - range.start as i32;
+        let source: Vec<MemoryCell> = self.data[range.start as usize..range.end as usize].to_vec();
+
+        if cut {
+            for addr in range.clone() {
+                let cell = &mut self.data[addr as usize];
+                cell.set(0);
+                cell.name = None;
+            }
+        }
+        for (cell, addr) in source.into_iter().zip(target.clone()) {
+            let value = if relocate {
+                self.parser.relocate(cell.get(), &range, offset)
+            } else {
+                cell.get()
+            };
+            let dest = &mut self.data[addr as usize];
+            dest.set(value);
+            if cut {
+                dest.name = cell.name;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ячейки вне `skip`, похожие на команды с адресом внутри `range`
+    pub fn references(&self, range: &Range<u16>, skip: &Range<u16>) -> Vec<u16> {
+        (0..self.data.len() as u16)
+            .filter(|addr| !skip.contains(addr))
+            .filter(|addr| {
+                let value = self.data[*addr as usize].get();
+                // пустые ячейки тоже похожи на ISZ 0
+                value != 0 && self.parser.relocate(value, range, 1) != value
+            })
+            .collect()
+    }
+}
+
+/// О чем запись в логе. По этому лог можно фильтровать
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LogCategory {
+    /// Пересылки между регистрами и работа АЛУ
+    Registers,
+    /// Обмен с основной памятью
+    Memory,
+    /// Внешние устройства и прерывания
+    Io,
+    /// Флаги C, N и Z
+    Flags,
+    /// Переходы в микропрограмме и останов
+    Control,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 5] = [
+        LogCategory::Registers,
+        LogCategory::Memory,
+        LogCategory::Io,
+        LogCategory::Flags,
+        LogCategory::Control,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            LogCategory::Registers => "Регистры и АЛУ",
+            LogCategory::Memory => "Память",
+            LogCategory::Io => "ВУ и прерывания",
+            LogCategory::Flags => "Флаги",
+            LogCategory::Control => "Переходы и останов",
+        }
+    }
+
+    /// Имя в настройках
+    pub fn key(&self) -> &'static str {
+        match self {
+            LogCategory::Registers => "registers",
+            LogCategory::Memory => "memory",
+            LogCategory::Io => "io",
+            LogCategory::Flags => "flags",
+            LogCategory::Control => "control",
+        }
+    }
 }
 
 pub struct LogEntry {
     pub command_counter: u16,
     pub micro_counter: u8,
     pub micro_command: bool,
+    pub category: LogCategory,
+    /// Номер команды, во время которой сделана запись (`Computer::steps`)
+    pub step: u64,
+    /// Адрес этой команды
+    pub command_address: u16,
+    /// Ее код. Из памяти его не взять: команду могли с тех пор перезаписать
+    pub command: u16,
     pub info: String,
 }
 
+#[derive(Clone)]
+pub enum InterruptEventKind {
+    /// МПУ ушло на обработку прерывания. Запоминаем кто его запрашивал и куда надо будет вернуться.
+    Entry {
+        devices: Vec<usize>,
+        return_address: u16,
+    },
+    /// СК снова добрался до адреса возврата
+    Exit { return_address: u16 },
+}
+
+#[derive(Clone)]
+pub struct InterruptEvent {
+    pub step: u64,
+    pub kind: InterruptEventKind,
+}
+
 #[derive(Copy, Clone)]
 pub struct IOCell {
     pub data: u8,
@@ -197,7 +508,7 @@ pub struct IOCell {
 }
 
 impl IOCell {
-    fn new() -> IOCell {
+    pub fn new() -> IOCell {
         IOCell {
             data: 0,
             ready: false,
@@ -210,9 +521,52 @@ pub struct Computer {
     pub general_memory: Rc<RefCell<Memory<GeneralCommandInfo, GeneralParser>>>,
     pub mc_memory: Rc<RefCell<Memory<MicroCommandInfo, McParser>>>,
     pub io_devices: [IOCell; 16],
-    logs: Vec<LogEntry>,
+    pub io_drivers: [DeviceDriver; 16],
+    pub interrupt_mask: u16, // n-ый бит разрешает ВУ номер n запрашивать прерывание
+    pub steps: u64, // сколько циклов команды было начато
+    pub micro_steps: u64, // сколько микрокоманд было выполнено
+    pub interrupts: u64, // сколько раз МПУ уходило на обработку прерывания
+    pub breakpoints: BTreeSet<u16>, // адреса команд, перед которыми надо остановиться
+    pub engine: Engine,
+    /// Сколько последних записей хранит лог. None - без ограничений
+    pub log_limit: Option<usize>,
+    stock_microprogram: Vec<u16>,
+    /// Лежит ли в МПУ стандартная микропрограмма. None - надо проверить заново
+    stock_microprogram_loaded: Cell<Option<bool>>,
+    /// Сколько раз память меняли снаружи ЭВМ, см. [Computer::on_memory_edit]
+    memory_edits: Cell<u64>,
+    interrupt_events: Vec<InterruptEvent>,
+    pending_returns: Vec<u16>,
+    command_address: u16,
+    /// Код команды по `command_address` на момент ее выборки
+    command: u16,
+    /// Сколько записей попало в лог за все время, включая выкинутые
+    logged: u64,
+    /// Адрес последней выполненной микрокоманды. None, если команду выполнил движок команд
+    micro_command_address: Option<u8>,
+    logs: VecDeque<LogEntry>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Engine {
+    /// Каждая команда исполняется микропрограммой из памяти МПУ
+    Microcode,
+    /// Команды исполняются напрямую. Работает только со стандартной микропрограммой
+    Instruction,
+}
+
+impl Engine {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Engine::Microcode => "Микропрограмма",
+            Engine::Instruction => "Команды напрямую",
+        }
+    }
 }
 
+/// Адрес микрокоманды, с которой начинается цикл выборки команды
+pub const FETCH_START: u8 = 0x01;
+
 impl Computer {
     fn mem(len: usize) -> Vec<MemoryCell> {
         let mut result = Vec::<MemoryCell>::new();
@@ -232,16 +586,22 @@ impl Computer {
             let data = self.registers.r_counter.bitand(0xFF) as u8;
             self.log(
                 false,
+                LogCategory::Io,
                 format!(
                     "Перенес значение {data:0>2X} из младших разрядов аккамулятора в ВУ номер {num}"),
             );
             self.io_devices.get_mut(num).unwrap().data = data;
+            if let Err(e) = self.io_drivers[num].on_output(data) {
+                self.log(false, LogCategory::Io, e);
+            }
         } else if opcode.bitand(0x0200) == 0x0200 {
             self.registers.r_counter = self.registers.r_counter.bitand(0xFF00);
             let data = self.io_devices.get_mut(num).unwrap().data as u16;
+            self.io_drivers[num].on_input();
             self.registers.r_counter = self.registers.r_counter.bitor(data);
             self.log(
                 false,
+                LogCategory::Io,
                 format!(
                     "Перенес значение {data:0>2X} из  ВУ номер {num} в младшие разряды аккамулятора"
                 ),
@@ -251,28 +611,34 @@ impl Computer {
                 .set_io_ready(self.io_devices.get(num).unwrap().ready);
             self.log(
                 false,
+                LogCategory::Io,
                 format!("Опросил ВУ номер {num} на предмет готовности"),
             );
 
             if self.registers.get_io_ready() {
                 self.log(
                     false,
+                    LogCategory::Io,
                     format!("ВУ номер {num} оказалось готовым. Увеличил СК на единицу"),
                 );
                 self.registers.r_command_counter += 1;
             }
         } else {
-            self.log(false, format!("Сбросил флаг готовности ВУ номер {num}"));
+            self.log(
+                false,
+                LogCategory::Io,
+                format!("Сбросил флаг готовности ВУ номер {num}"),
+            );
             self.io_devices[num].ready = false;
         }
 
         let counter_now_null = self.registers.r_counter == 0;
 
-        println!("{} {}", self.registers.r_counter, counter_now_null);
         if self.registers.get_null() != counter_now_null {
             self.registers.set_null(counter_now_null);
             self.log(
                 true,
+                LogCategory::Flags,
                 format!(
                     "Установил флаг нуля в {}",
                     if counter_now_null { "1" } else { "0" }
@@ -280,18 +646,14 @@ impl Computer {
             );
         }
 
-        self.log(true, "Сбросил флаг ВВОД-ВВЫОД".to_string());
+        self.log(true, LogCategory::Io, "Сбросил флаг ВВОД-ВВЫОД".to_string());
         self.registers.set_io(false);
     }
 
-    pub fn reset_memory(&mut self) {
+    /// Содержимое памяти МПУ из mc.txt
+    fn stock_microprogram() -> Vec<u16> {
         let data = include_bytes!("mc.txt") as &[u8];
-        for x in &mut self.mc_memory.borrow_mut().data {
-            x.data = 0;
-        }
-        for x in &mut self.general_memory.borrow_mut().data {
-            x.data = 0;
-        }
+        let mut result = vec![0u16; 256];
         for line in BufReader::new(data)
             .lines()
             .map(std::result::Result::unwrap)
@@ -300,18 +662,90 @@ impl Computer {
             let address = u16::from_str_radix(splitted.first().unwrap(), 16).unwrap();
             let value = u16::from_str_radix(splitted.get(1).unwrap(), 16).unwrap();
 
-            self.mc_memory
-                .borrow_mut()
-                .data
-                .get_mut(address as usize)
-                .unwrap()
-                .set(value);
+            result[address as usize] = value;
+        }
+        result
+    }
+
+    pub fn reset_memory(&mut self) {
+        for x in &mut self.general_memory.borrow_mut().data {
+            x.data = 0;
+        }
+        for (x, value) in self
+            .mc_memory
+            .borrow_mut()
+            .data
+            .iter_mut()
+            .zip(self.stock_microprogram.iter())
+        {
+            x.set(*value);
+        }
+        self.on_memory_edit();
+    }
+
+    /// В памяти МПУ лежит стандартная микропрограмма, то есть можно исполнять команды напрямую.
+    ///
+    /// Сама ЭВМ в МПУ не пишет, так что ответ запоминается до следующего [Computer::on_memory_edit]
+    pub fn is_stock_microprogram(&self) -> bool {
+        if let Some(stock) = self.stock_microprogram_loaded.get() {
+            return stock;
+        }
+        let stock = self
+            .mc_memory
+            .borrow()
+            .data
+            .iter()
+            .zip(self.stock_microprogram.iter())
+            .all(|(cell, value)| cell.get() == *value);
+        self.stock_microprogram_loaded.set(Some(stock));
+        stock
+    }
+
+    /// Вызывается после того, как память поменяли снаружи ЭВМ: из окна, файла или отката
+    pub fn on_memory_edit(&self) {
+        self.stock_microprogram_loaded.set(None);
+        self.memory_edits.set(self.memory_edits.get() + 1);
+    }
+
+    /// Меняется, когда память поменяли снаружи ЭВМ. Например, загрузили новую программу
+    pub fn memory_edits(&self) -> u64 {
+        self.memory_edits.get()
+    }
+
+    /// Стоит ли ЭВМ перед выборкой команды, на которой есть точка останова
+    pub fn at_breakpoint(&self) -> bool {
+        self.registers.r_micro_command_counter == FETCH_START
+            && self.breakpoints.contains(&self.registers.r_command_counter)
+    }
+
+    /// Движок, которым на самом деле будут исполняться команды
+    pub fn effective_engine(&self) -> Engine {
+        match self.engine {
+            Engine::Instruction if self.is_stock_microprogram() => Engine::Instruction,
+            _ => Engine::Microcode,
         }
     }
 
     pub fn new() -> Computer {
         let mut result = Computer {
             io_devices: [IOCell::new(); 16],
+            io_drivers: Default::default(),
+            interrupt_mask: 0xFFFF,
+            steps: 0,
+            micro_steps: 0,
+            interrupts: 0,
+            breakpoints: BTreeSet::new(),
+            engine: Engine::Microcode,
+            log_limit: Some(100_000),
+            stock_microprogram: Self::stock_microprogram(),
+            stock_microprogram_loaded: Cell::new(None),
+            memory_edits: Cell::new(0),
+            interrupt_events: vec![],
+            pending_returns: vec![],
+            command_address: 0,
+            command: 0,
+            logged: 0,
+            micro_command_address: None,
             registers: Registers::new(),
             general_memory: Rc::new(RefCell::new(Memory {
                 data: Self::mem(2048),
@@ -325,43 +759,235 @@ impl Computer {
                 name: "mpu",
                 phantom: PhantomData::default(),
             })),
-            logs: Vec::<LogEntry>::new(),
+            logs: VecDeque::new(),
         };
         result.reset_memory();
 
         result
     }
 
-    pub fn log(&mut self, micro_command: bool, info: String) {
-        if self.logs.len() > 100 {
-            self.logs.remove(0);
+    /// Независимая копия ЭВМ: своя память, регистры и ВУ. Файловые ВУ копии ничего не пишут на диск,
+    /// логи не копируются, запоминается только их длина для `restore`.
+    pub fn fork(&self) -> Computer {
+        Computer {
+            registers: self.registers.clone(),
+            general_memory: Rc::new(RefCell::new(Memory {
+                data: self.general_memory.borrow().data.clone(),
+                parser: GeneralParser::new(),
+                name: "general",
+                phantom: PhantomData,
+            })),
+            mc_memory: Rc::new(RefCell::new(Memory {
+                data: self.mc_memory.borrow().data.clone(),
+                parser: McParser::new(),
+                name: "mpu",
+                phantom: PhantomData,
+            })),
+            io_devices: self.io_devices,
+            io_drivers: self.io_drivers.clone().map(|d| d.detached()),
+            interrupt_mask: self.interrupt_mask,
+            steps: self.steps,
+            micro_steps: self.micro_steps,
+            interrupts: self.interrupts,
+            breakpoints: self.breakpoints.clone(),
+            engine: self.engine,
+            log_limit: self.log_limit,
+            stock_microprogram: self.stock_microprogram.clone(),
+            stock_microprogram_loaded: self.stock_microprogram_loaded.clone(),
+            memory_edits: self.memory_edits.clone(),
+            interrupt_events: self.interrupt_events.clone(),
+            pending_returns: self.pending_returns.clone(),
+            command_address: self.command_address,
+            command: self.command,
+            logged: self.logged,
+            micro_command_address: self.micro_command_address,
+            logs: VecDeque::new(),
         }
-        self.logs.push(LogEntry {
+    }
+
+    /// Возвращает ЭВМ в состояние, сохраненное через `fork`.
+    ///
+    /// Память копируется на место, а не подменяется, потому что на нее ссылаются окна.
+    /// ВУ остаются как есть: что было прочитано из файла или записано в него, уже не вернуть.
+    /// Записи лога, сделанные после `fork`, выкидываются. Старые записи, вытесненные за это время
+    /// по `log_limit`, тоже уже не вернуть.
+    pub fn restore(&mut self, saved: &Computer) {
+        self.registers = saved.registers.clone();
+        self.general_memory.borrow_mut().data = saved.general_memory.borrow().data.clone();
+        self.mc_memory.borrow_mut().data = saved.mc_memory.borrow().data.clone();
+        // память вернулась к сохраненной, так что и проверка МПУ от нее
+        self.stock_microprogram_loaded
+            .set(saved.stock_microprogram_loaded.get());
+        self.io_devices = saved.io_devices;
+        self.interrupt_mask = saved.interrupt_mask;
+        self.steps = saved.steps;
+        self.micro_steps = saved.micro_steps;
+        self.interrupts = saved.interrupts;
+        self.engine = saved.engine;
+        self.interrupt_events = saved.interrupt_events.clone();
+        self.pending_returns = saved.pending_returns.clone();
+        self.command_address = saved.command_address;
+        self.command = saved.command;
+        self.micro_command_address = saved.micro_command_address;
+
+        let added = self.logged.saturating_sub(saved.logged) as usize;
+        self.logs.truncate(self.logs.len().saturating_sub(added));
+        self.logged = saved.logged;
+    }
+
+    pub fn log(&mut self, micro_command: bool, category: LogCategory, info: String) {
+        self.logs.push_back(LogEntry {
             micro_counter: self.registers.r_micro_command_counter,
             command_counter: self.registers.r_command_counter,
             micro_command,
+            category,
+            step: self.steps,
+            command_address: self.command_address,
+            command: self.command,
             info,
         });
+        self.logged += 1;
+        self.trim_logs();
+    }
+
+    /// Выкидывает самые старые записи, если их больше, чем `log_limit`
+    pub fn trim_logs(&mut self) {
+        if let Some(limit) = self.log_limit {
+            while self.logs.len() > limit {
+                self.logs.pop_front();
+            }
+        }
     }
 
     pub fn clear_logs(&mut self) {
         self.logs.clear();
     }
 
-    pub fn logs(&self) -> &Vec<LogEntry> {
+    pub fn logs(&self) -> &VecDeque<LogEntry> {
         &self.logs
     }
 
-    pub fn micro_step(&mut self) -> ExecutionResult {
-        let opcode = self
-            .mc_memory
+    /// Сколько записей попало в лог за все время. Первая запись в `logs` имеет номер
+    /// `logged - logs.len()`. Откатывается вместе с `restore`
+    pub fn logged(&self) -> u64 {
+        self.logged
+    }
+
+    /// Откуда была взята микрокоманда, лежащая в РМК. После переходов это не СчМК - 1
+    pub fn micro_command_address(&self) -> Option<u8> {
+        self.micro_command_address
+    }
+
+    /// Номера ВУ, которые сейчас запрашивают прерывание
+    pub fn interrupt_requests(&self) -> Vec<usize> {
+        self.io_devices
+            .iter()
+            .enumerate()
+            .filter(|(num, cell)| cell.ready && bit_at(self.interrupt_mask, *num as u8))
+            .map(|(num, _)| num)
+            .collect()
+    }
+
+    /// Вызывается микрокомандой перехода, когда она ушла на обработку прерывания
+    pub fn on_interrupt_entry(&mut self) {
+        let return_address = self.registers.r_command_counter;
+        self.interrupts += 1;
+        self.pending_returns.push(return_address);
+        self.push_interrupt_event(InterruptEventKind::Entry {
+            devices: self.interrupt_requests(),
+            return_address,
+        });
+    }
+
+    fn push_interrupt_event(&mut self, kind: InterruptEventKind) {
+        if self.interrupt_events.len() > 1000 {
+            self.interrupt_events.remove(0);
+        }
+        self.interrupt_events.push(InterruptEvent {
+            step: self.steps,
+            kind,
+        });
+    }
+
+    pub fn interrupt_events(&self) -> &Vec<InterruptEvent> {
+        &self.interrupt_events
+    }
+
+    /// Адреса возврата прерываний, которые еще не завершились. Последний - самый вложенный.
+    pub fn pending_returns(&self) -> &Vec<u16> {
+        &self.pending_returns
+    }
+
+    pub fn clear_interrupt_events(&mut self) {
+        self.interrupt_events.clear();
+        self.pending_returns.clear();
+    }
+
+    /// Запоминает память, чтобы потом посмотреть, что в ней поменялось
+    pub fn checkpoint(&self) -> MemoryCheckpoint {
+        MemoryCheckpoint {
+            general: self.general_memory.borrow().values(),
+            micro: self.mc_memory.borrow().values(),
+            step: self.steps,
+        }
+    }
+
+    fn on_fetch_start(&mut self) {
+        if self.pending_returns.last() == Some(&self.registers.r_command_counter) {
+            let return_address = self.pending_returns.pop().unwrap();
+            self.push_interrupt_event(InterruptEventKind::Exit { return_address });
+        }
+
+        self.command_address = self.registers.r_command_counter;
+        if let Some(cell) = self
+            .general_memory
             .borrow_mut()
             .data
-            .get(self.registers.r_micro_command_counter as usize)
-            .unwrap()
-            .get();
+            .get_mut(self.command_address as usize)
+        {
+            cell.execute();
+            self.command = cell.get();
+        }
+        let step = self.steps;
+        for (driver, cell) in self.io_drivers.iter_mut().zip(self.io_devices.iter_mut()) {
+            driver.tick(step, cell);
+        }
+        self.steps += 1;
+    }
+
+    /// Выставляет флаг прерывания если какое-то ВУ его запрашивает и прерывания разрешены.
+    /// Вызывается перед каждой микрокомандой, так что МПУ всегда видит актуальное состояние ВУ.
+    pub fn update_interrupt_request(&mut self) {
+        let requested = self
+            .io_devices
+            .iter()
+            .enumerate()
+            .any(|(num, cell)| cell.ready && bit_at(self.interrupt_mask, num as u8));
+
+        let interrupt = requested && self.registers.get_allow_interupt();
+        if interrupt != self.registers.get_interupt() {
+            self.registers.set_interrupt(interrupt);
+        }
+    }
+
+    pub fn micro_step(&mut self) -> ExecutionResult {
+        if self.registers.r_micro_command_counter == FETCH_START {
+            self.on_fetch_start();
+        }
+        self.micro_steps += 1;
+        self.update_interrupt_request();
+        let opcode = {
+            let mut memory = self.mc_memory.borrow_mut();
+            let cell = memory
+                .data
+                .get_mut(self.registers.r_micro_command_counter as usize)
+                .unwrap();
+            cell.execute();
+            cell.get()
+        };
         let cmd = parse(opcode);
         self.registers.r_micro_command = opcode;
+        self.micro_command_address = Some(self.registers.r_micro_command_counter);
         let result = cmd.run(self);
         if !matches!(result, ExecutionResult::Jumped) {
             self.registers.r_micro_command_counter =
@@ -369,4 +995,275 @@ impl Computer {
         }
         result
     }
+
+    /// Делает один шаг выбранным движком: либо одну микрокоманду, либо, если СчМК стоит на начале
+    /// цикла выборки, сразу целую команду.
+    pub fn step(&mut self) -> ExecutionResult {
+        if self.registers.r_micro_command_counter == FETCH_START
+            && self.effective_engine() == Engine::Instruction
+        {
+            self.on_fetch_start();
+            self.micro_command_address = None;
+            crate::isa::step(self)
+        } else {
+            self.micro_step()
+        }
+    }
+
+    /// Выполняет одну команду основной памяти. То же самое, что и кнопка "Большой шаг".
+    pub fn command_step(&mut self) {
+        self.registers.set_execute_by_tick(false);
+        self.registers.set_lever(false);
+        self.registers.set_program_mode(false);
+        while !matches!(self.step(), ExecutionResult::Halted) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{
+        Computer, Engine, InterruptEventKind, LogCategory, MemorySearch, FETCH_START,
+    };
+    use std::rc::Rc;
+
+    fn load(computer: &mut Computer, start: u16, program: &[u16]) {
+        let mut mem = computer.general_memory.borrow_mut();
+        for (i, v) in program.iter().enumerate() {
+            mem.data[start as usize + i].set(*v);
+        }
+    }
+
+    #[test]
+    fn interrupt_without_gui() {
+        let mut computer = Computer::new();
+        // EI; NOP; NOP; NOP; HLT
+        load(&mut computer, 0x10, &[0xFA00, 0xF100, 0xF100, 0xF100, 0xF000]);
+        // обработчик прерывания: NOP
+        load(&mut computer, 0x01, &[0xF100]);
+        computer.registers.r_command_counter = 0x10;
+
+        computer.io_devices[3].ready = true;
+        computer.interrupt_mask = 0;
+        // после EI микропрограмма сразу переходит к выборке следующей команды
+        computer.command_step();
+        computer.command_step();
+        assert_eq!(computer.registers.r_command_counter, 0x13);
+
+        computer.interrupt_mask = 1 << 3;
+        computer.command_step();
+        // адрес возврата сохранился в нулевой ячейке, а управление ушло на обработчик
+        assert_eq!(computer.general_memory.borrow().data[0].get(), 0x14);
+        assert_eq!(computer.registers.r_command_counter, 0x02);
+        assert!(!computer.registers.get_allow_interupt());
+        assert!(matches!(
+            computer.interrupt_events().last().unwrap().kind,
+            InterruptEventKind::Entry { return_address: 0x14, .. }
+        ));
+        assert_eq!(computer.pending_returns(), &vec![0x14]);
+    }
+
+    #[test]
+    fn fork_and_restore() {
+        let mut computer = Computer::new();
+        // CLA; MOV 20; HLT
+        load(&mut computer, 0x10, &[0xF200, 0x3020, 0xF000]);
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_counter = 0x1234;
+
+        let mut fork = computer.fork();
+        fork.command_step();
+        fork.command_step();
+        assert_eq!(fork.general_memory.borrow().data[0x20].get(), 0);
+        assert_eq!(fork.registers.r_command_counter, 0x12);
+        // оригинал не тронут
+        assert_eq!(computer.registers.r_command_counter, 0x10);
+        assert_eq!(computer.registers.r_counter, 0x1234);
+
+        computer.log(false, LogCategory::Control, "до".to_string());
+        let saved = computer.fork();
+        let memory = computer.general_memory.clone();
+        computer.general_memory.borrow_mut().data[0x20].set(0xAAAA);
+        computer.command_step();
+        computer.restore(&saved);
+        assert_eq!(computer.registers.r_command_counter, 0x10);
+        assert_eq!(computer.registers.r_counter, 0x1234);
+        // память та же самая, что видят окна
+        assert!(Rc::ptr_eq(&memory, &computer.general_memory));
+        assert_eq!(memory.borrow().data[0x20].get(), 0);
+        // в логе только то, что было до fork
+        assert_eq!(computer.logs().len(), 1);
+        assert_eq!(computer.logs()[0].info, "до");
+    }
+
+    #[test]
+    fn micro_command_address() {
+        let mut computer = Computer::new();
+        // CLA; NOP; NOP
+        load(&mut computer, 0x10, &[0xF200, 0xF100, 0xF100]);
+        computer.registers.r_command_counter = 0x10;
+
+        let mut jumped = false;
+        // выборка и декодирование переходят по микропрограмме
+        for _ in 0..20 {
+            let counter = computer.registers.r_micro_command_counter;
+            computer.micro_step();
+            assert_eq!(computer.micro_command_address(), Some(counter));
+            jumped |= computer.registers.r_micro_command_counter != counter.wrapping_add(1);
+        }
+        assert!(jumped);
+
+        computer.engine = Engine::Instruction;
+        while computer.registers.r_micro_command_counter != FETCH_START {
+            computer.micro_step();
+        }
+        computer.step();
+        assert_eq!(computer.micro_command_address(), None);
+    }
+
+    #[test]
+    fn engine() {
+        let mut computer = Computer::new();
+        // CLA; MOV 20; HLT
+        load(&mut computer, 0x10, &[0xF200, 0x3020, 0xF000]);
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_counter = 0x1234;
+        computer.engine = Engine::Instruction;
+        assert_eq!(computer.effective_engine(), Engine::Instruction);
+
+        computer.command_step();
+        computer.command_step();
+        assert_eq!(computer.general_memory.borrow().data[0x20].get(), 0);
+        assert_eq!(computer.registers.r_command_counter, 0x12);
+        // команда выполнилась целиком, а не микрокомандами
+        assert_eq!(computer.micro_command_address(), None);
+
+        // пока о записи не сообщили, ответ берется из запомненного
+        computer.mc_memory.borrow_mut().data[0xF0].set(0xABCD);
+        assert_eq!(computer.effective_engine(), Engine::Instruction);
+        computer.on_memory_edit();
+        assert_eq!(computer.effective_engine(), Engine::Microcode);
+
+        computer.reset_memory();
+        assert_eq!(computer.effective_engine(), Engine::Instruction);
+    }
+
+    #[test]
+    fn log() {
+        let mut computer = Computer::new();
+        // CLA; MOV 20; HLT
+        load(&mut computer, 0x10, &[0xF200, 0x3020, 0xF000]);
+        computer.registers.r_command_counter = 0x10;
+        computer.log_limit = None;
+        for _ in 0..3 {
+            computer.command_step();
+        }
+
+        let logs = computer.logs();
+        let mov = logs
+            .iter()
+            .filter(|e| e.command_address == 0x11)
+            .collect::<Vec<_>>();
+        assert!(mov.iter().all(|e| e.step == mov[0].step && e.command == 0x3020));
+        assert!(mov.iter().any(|e| e.category == LogCategory::Memory));
+        assert!(logs
+            .iter()
+            .any(|e| e.command_address == 0x10 && e.category == LogCategory::Flags));
+        assert_eq!(logs.back().unwrap().category, LogCategory::Control);
+
+        computer.log_limit = Some(5);
+        computer.trim_logs();
+        assert_eq!(computer.logs().len(), 5);
+        assert_eq!(computer.logs().back().unwrap().command_address, 0x12);
+    }
+
+    #[test]
+    fn memory_operations() {
+        let computer = Computer::new();
+        let mut mem = computer.general_memory.borrow_mut();
+        // 10: ADD 13; 11: BR 10; 12: HLT; 13: 0005
+        for (i, v) in [0x4013, 0xC010, 0xF000, 0x0005].iter().enumerate() {
+            mem.data[0x10 + i].set(*v);
+        }
+        mem.data[0x10].name = Some("start".to_string());
+        // ссылка на кусок снаружи
+        mem.data[0x20].set(0xC010);
+
+        assert_eq!(mem.find(MemorySearch::Value, "c010"), vec![0x11, 0x20]);
+        assert_eq!(mem.find(MemorySearch::Mnemonic, "add"), vec![0x10]);
+        assert_eq!(mem.find(MemorySearch::Label, "START"), vec![0x10]);
+
+        mem.copy(0x10..0x14, 0x30, true, false).unwrap();
+        let copied: Vec<u16> = mem.data[0x30..0x34].iter().map(|c| c.get()).collect();
+        assert_eq!(copied, vec![0x4033, 0xC030, 0xF000, 0x0005]);
+        assert_eq!(mem.data[0x11].get(), 0xC010);
+
+        mem.copy(0x10..0x14, 0x12, true, true).unwrap();
+        let moved: Vec<u16> = mem.data[0x10..0x16].iter().map(|c| c.get()).collect();
+        assert_eq!(moved, vec![0, 0, 0x4015, 0xC012, 0xF000, 0x0005]);
+        // ссылка снаружи осталась как была, но ее видно
+        assert_eq!(mem.data[0x20].get(), 0xC010);
+        assert_eq!(mem.references(&(0x10..0x14), &(0x12..0x16)), vec![0x20]);
+        assert_eq!(mem.data[0x12].name.as_deref(), Some("start"));
+        assert_eq!(mem.data[0x10].name, None);
+
+        mem.fill(0x30..0x34, 0xF100).unwrap();
+        assert!(mem.data[0x30..0x34].iter().all(|c| c.get() == 0xF100));
+        assert!(mem.fill(0x7FF..0x801, 0).is_err());
+        assert!(mem.copy(0x10..0x20, 0x7F8, false, false).is_err());
+    }
+
+    #[test]
+    fn heat() {
+        for engine in [Engine::Microcode, Engine::Instruction] {
+            let mut computer = Computer::new();
+            computer.engine = engine;
+            // ADD 13; HLT
+            load(&mut computer, 0x10, &[0x4013, 0xF000]);
+            load(&mut computer, 0x13, &[0x0005]);
+            computer.registers.r_command_counter = 0x10;
+            computer.command_step();
+            computer.command_step();
+
+            let mem = computer.general_memory.borrow();
+            assert_eq!(mem.data[0x10].executions(), 1);
+            assert_eq!(mem.data[0x11].executions(), 1);
+            assert_eq!(mem.data[0x13].executions(), 0);
+            assert_eq!(mem.data[0x13].reads(), 1);
+            assert_eq!(mem.data[0x13].last_read(), 1);
+            assert_eq!(mem.data[0x13].last_touched(), 0);
+            // выборка команды - тоже чтение
+            assert_eq!(mem.data[0x10].reads(), 1);
+        }
+
+        let mut computer = Computer::new();
+        computer.command_step();
+        let fetch = computer.mc_memory.borrow().data[1].executions();
+        assert_eq!(fetch, 1);
+
+        computer.general_memory.borrow_mut().data[0].reset_heat();
+        assert_eq!(computer.general_memory.borrow().data[0].executions(), 0);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut computer = Computer::new();
+        // MOV 20; HLT
+        load(&mut computer, 0x10, &[0x3020, 0xF000]);
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_counter = 0x1234;
+        let checkpoint = computer.checkpoint();
+
+        computer.command_step();
+        computer.mc_memory.borrow_mut().data[0xF0].set(0xABCD);
+
+        let (general, micro) = checkpoint.diff(&computer);
+        assert_eq!(general.len(), 1);
+        assert_eq!(
+            (general[0].address, general[0].before, general[0].after),
+            (0x20, 0, 0x1234)
+        );
+        assert_eq!(micro.len(), 1);
+        assert_eq!(micro[0].address, 0xF0);
+        assert_eq!(checkpoint.step, 0);
+    }
 }
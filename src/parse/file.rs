@@ -8,6 +8,18 @@ pub fn parse_file<T: Read, I: CommandInfo, P: Parser<I>>(
     parser: &P,
     max_size: u16,
 ) -> Result<Vec<(u16, u16)>, String> {
+    parse_file_with_labels(data, parser, max_size).map(|(result, _)| result)
+}
+
+/// Ячейки (адрес, значение) и метки с их адресами
+pub type LabeledProgram = (Vec<(u16, u16)>, HashMap<String, u16>);
+
+/// То же, что [`parse_file`], но еще отдает метки (`$имя`) с их адресами
+pub fn parse_file_with_labels<T: Read, I: CommandInfo, P: Parser<I>>(
+    data: &mut T,
+    parser: &P,
+    max_size: u16,
+) -> Result<LabeledProgram, String> {
     let reader = BufReader::new(data);
     let mut cursor = 0;
 
@@ -33,7 +45,7 @@ pub fn parse_file<T: Read, I: CommandInfo, P: Parser<I>>(
                 }
 
                 let Ok(pos) = u16::from_str_radix(arg, 16) else {
-                    return Err(err!(format!("Не могу распарсить число {arg}")))
+                    return Err(err!(format!("Не могу распарсить число {arg}")));
                 };
 
                 if pos < cursor {
@@ -125,7 +137,7 @@ pub fn parse_file<T: Read, I: CommandInfo, P: Parser<I>>(
         }
     }
 
-    Ok(result)
+    Ok((result, variables))
 }
 
 #[derive(Debug, PartialEq)]
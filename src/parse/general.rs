@@ -3,6 +3,7 @@ use crate::parse::{CommandInfo, Parser};
 use core::ops::{BitAnd, BitOr, BitXor};
 use imgui::Ui;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::rc::Rc;
 
 pub struct GeneralParser {
@@ -198,6 +199,18 @@ impl Parser<GeneralCommandInfo> for GeneralParser {
 
         command.unwrap().rev_parse(str)
     }
+
+    fn relocate(&self, opcode: u16, range: &Range<u16>, offset: i32) -> u16 {
+        // адресные команды - все, кроме безадресных (F) и команд ввода-вывода (E)
+        if opcode >> 12 >= 0xE {
+            return opcode;
+        }
+        let address = opcode.bitand(0x7FF);
+        if !range.contains(&address) {
+            return opcode;
+        }
+        opcode.bitand(!0x7FF) | ((address as i32 + offset) as u16).bitand(0x7FF)
+    }
 }
 
 trait GeneralCommand {
@@ -429,6 +442,11 @@ impl GeneralCommandInfo {
     fn new(info: Rc<dyn GeneralCommand>, opcode: u16) -> GeneralCommandInfo {
         GeneralCommandInfo { info, opcode }
     }
+
+    /// Мнемоника без аргумента: "BR", а не "BR 015"
+    pub fn name(&self) -> &str {
+        self.info.mnemonic()
+    }
 }
 
 impl CommandInfo for GeneralCommandInfo {
@@ -1,4 +1,4 @@
-use crate::model::{Computer, Register};
+use crate::model::{Computer, LogCategory, Register};
 use crate::parse::{CommandInfo, Parser};
 use crate::utils::bit_registers::*;
 use core::ops::*;
@@ -124,13 +124,16 @@ pub trait MicroCommand {
     fn draw_highlight(&self, ui: &Ui);
     fn opcode(&self) -> u16;
     fn horizontal(&self) -> u32;
+    /// Какие поля микрокоманды что-то делают: обмен с памятью, сдвиг, флаги, управление ВУ.
+    /// Сработал ли условный переход, отсюда не видно - это зависит от регистров
+    fn fired(&self) -> Vec<&'static str>;
 }
 
-pub struct OperationalCommand0(u16);
+pub struct OperationalCommand0(pub u16);
 
-pub struct OperationalCommand1(u16);
+pub struct OperationalCommand1(pub u16);
 
-pub struct ControlCommand(u16);
+pub struct ControlCommand(pub u16);
 
 pub struct MicroCommandInfo {
     command: Box<dyn MicroCommand>,
@@ -176,6 +179,18 @@ impl Parser<MicroCommandInfo> for McParser {
     fn rev_parse(&self, _: &str) -> Result<u16, String> {
         unimplemented!()
     }
+
+    fn relocate(&self, opcode: u16, range: &Range<u16>, offset: i32) -> u16 {
+        // адрес перехода есть только в управляющих микрокомандах
+        if sub_sum(opcode, 15, 14) < 2 {
+            return opcode;
+        }
+        let address = opcode.bitand(0xFF);
+        if !range.contains(&address) {
+            return opcode;
+        }
+        opcode.bitand(0xFF00) | ((address as i32 + offset) as u16).bitand(0xFF)
+    }
 }
 
 pub fn parse(opcode: u16) -> Box<dyn MicroCommand> {
@@ -193,6 +208,7 @@ impl MicroCommand for ControlCommand {
     fn run(&self, computer: &mut Computer) -> ExecutionResult {
         computer.log(
             true,
+            LogCategory::Control,
             format!(
                 "Сравнил {} бит из регистра {} с {}",
                 self.bit_location(),
@@ -207,11 +223,18 @@ impl MicroCommand for ControlCommand {
         {
             computer.log(
                 true,
+                LogCategory::Control,
                 format!(
                     "Присвоил значение {:0>4X} регистру СчМК",
                     self.jump_address()
                 ),
             );
+            if self.register() == Register::Status
+                && self.bit_location() == 5
+                && self.needed_bit()
+            {
+                computer.on_interrupt_entry();
+            }
             computer.registers.r_micro_command_counter = self.jump_address();
             return ExecutionResult::Jumped;
         }
@@ -316,6 +339,10 @@ impl MicroCommand for ControlCommand {
 
         result
     }
+
+    fn fired(&self) -> Vec<&'static str> {
+        vec![]
+    }
 }
 
 fn set_bit(num: &mut u32, pos: u8, value: bool) {
@@ -333,14 +360,15 @@ impl MicroCommand for OperationalCommand0 {
 
                 let overflow = bit_at(computer.registers.r_counter, 0);
                 computer.registers.r_buffer = (computer.registers.r_counter as u32).shr(1u32);
-                computer.log(true, format!("Присвоил регистру БР значение {:0>4X} из сдвинутого вправо регистра А({:0>4X})", computer.registers.r_buffer, computer.registers.r_counter));
+                computer.log(true, LogCategory::Registers, format!("Присвоил регистру БР значение {:0>4X} из сдвинутого вправо регистра А({:0>4X})", computer.registers.r_buffer, computer.registers.r_counter));
                 if c {
                     computer.registers.r_buffer = computer.registers.r_buffer.bitor(0x8000);
-                    computer.log(true, "Установил 15 бит регистра БР в 1 так как до начала сдвига был установлен флаг C".to_string());
+                    computer.log(true, LogCategory::Registers, "Установил 15 бит регистра БР в 1 так как до начала сдвига был установлен флаг C".to_string());
                 }
                 if overflow {
                     computer.log(
                         true,
+                        LogCategory::Registers,
                         "Установил 16 бит регистра БР в 1 т.к. произошло переполнение".to_string(),
                     );
                     computer.registers.r_buffer = computer.registers.r_buffer.bitor(0x10000);
@@ -352,10 +380,10 @@ impl MicroCommand for OperationalCommand0 {
                 computer.registers.r_buffer = (computer.registers.r_counter as u32)
                     .shl(1u32)
                     .bitand(0x1FFFF);
-                computer.log(true, format!("Присвоил регистру БР значение {:0>4X} из сдвинутого влево регистра А({:0>4X})", computer.registers.r_buffer, computer.registers.r_counter));
+                computer.log(true, LogCategory::Registers, format!("Присвоил регистру БР значение {:0>4X} из сдвинутого влево регистра А({:0>4X})", computer.registers.r_buffer, computer.registers.r_counter));
                 if c {
                     computer.registers.r_buffer = computer.registers.r_buffer.bitor(0x1);
-                    computer.log(true, "Установил 0 бит регистра БР в 1 так как до начала сдвига был установлен флаг C".to_string());
+                    computer.log(true, LogCategory::Registers, "Установил 0 бит регистра БР в 1 так как до начала сдвига был установлен флаг C".to_string());
                 }
                 return ExecutionResult::Success;
             }
@@ -370,9 +398,10 @@ impl MicroCommand for OperationalCommand0 {
                     .data
                     .get_mut(computer.registers.r_address.bitand(0x7FF) as usize)
                     .unwrap()
-                    .set(computer.registers.r_data);
+                    .write(computer.registers.r_data, computer.steps);
                 computer.log(
                     false,
+                    LogCategory::Memory,
                     format!(
                         "Присвоил значение {:0>4X} в ячейку {:0>4X}",
                         computer.registers.r_data, computer.registers.r_address
@@ -386,9 +415,10 @@ impl MicroCommand for OperationalCommand0 {
                     .data
                     .get_mut(computer.registers.r_address.bitand(0x7FF) as usize)
                     .unwrap()
-                    .get();
+                    .read(computer.steps);
                 computer.log(
                     false,
+                    LogCategory::Memory,
                     format!(
                         "Прочитал значение {:0>4X} из ячейки {:0>4X}",
                         computer.registers.r_data, computer.registers.r_address
@@ -405,6 +435,7 @@ impl MicroCommand for OperationalCommand0 {
                 if complement == Complement::Left {
                     computer.log(
                         true,
+                        LogCategory::Registers,
                         format!(
                             "Инвертировал регистр {}({:0>4X})",
                             r.mnemonic(),
@@ -428,6 +459,7 @@ impl MicroCommand for OperationalCommand0 {
                 if complement == Complement::Right {
                     computer.log(
                         true,
+                        LogCategory::Registers,
                         format!(
                             "Инвертировал регистр {}({:0>4X})",
                             r.mnemonic(),
@@ -449,6 +481,7 @@ impl MicroCommand for OperationalCommand0 {
             Operation::LeftPlusRight => {
                 computer.log(
                     true,
+                    LogCategory::Registers,
                     format!(
                         "Произвел операцию {}({:0>4X} + {:0>4X}) и положил в БР",
                         self.expression_mnemonic(),
@@ -461,6 +494,7 @@ impl MicroCommand for OperationalCommand0 {
             Operation::LeftPlusRightPlusOne => {
                 computer.log(
                     true,
+                    LogCategory::Registers,
                     format!(
                         "Произвел операцию {}({:0>4X} + {:0>4X} + 1) и положил в БР",
                         self.expression_mnemonic(),
@@ -474,6 +508,7 @@ impl MicroCommand for OperationalCommand0 {
             Operation::LeftAndRight => {
                 computer.log(
                     true,
+                    LogCategory::Registers,
                     format!(
                         "Произвел операцию {}({:0>4X} & {:0>4X}) и положил в БР",
                         self.expression_mnemonic(),
@@ -674,12 +709,30 @@ impl MicroCommand for OperationalCommand0 {
 
         result
     }
+
+    fn fired(&self) -> Vec<&'static str> {
+        // при сдвиге больше ничего не происходит
+        match self.shift() {
+            Shift::Right => return vec!["Сдвиг вправо"],
+            Shift::Left => return vec!["Сдвиг влево"],
+            Shift::None => {}
+        }
+        match self.memory() {
+            Memory::Read => vec!["Чтение памяти"],
+            Memory::Write => vec!["Запись в память"],
+            Memory::None => vec![],
+        }
+    }
 }
 
 impl MicroCommand for OperationalCommand1 {
     fn run(&self, computer: &mut Computer) -> ExecutionResult {
         if self.hlt() {
-            computer.log(false, "Оппа, моя остановочка.".to_string());
+            computer.log(
+                false,
+                LogCategory::Control,
+                "Оппа, моя остановочка.".to_string(),
+            );
             return ExecutionResult::Halted;
         }
 
@@ -689,24 +742,28 @@ impl MicroCommand for OperationalCommand1 {
                 match cmd {
                     IOControl::Connect => {
                         if computer.registers.r_data == computer.registers.r_command {
-                            computer.log(true, "Установил флаг ВВОД-ВВЫОД и передал управление модулю взаимодействия с ВУ".to_string());
+                            computer.log(true, LogCategory::Io, "Установил флаг ВВОД-ВВЫОД и передал управление модулю взаимодействия с ВУ".to_string());
                             computer.registers.set_io(true);
                             computer.process_io_command();
                         } else {
-                            computer.log(true, "Было запрошено взаимодействие с ВУ но РД не равен РК. Запрос проигнорирован.".to_string());
+                            computer.log(true, LogCategory::Io, "Было запрошено взаимодействие с ВУ но РД не равен РК. Запрос проигнорирован.".to_string());
                         }
                     }
                     IOControl::DisableInterruption => {
-                        computer.log(false, "Запретил прерывания".to_string());
+                        computer.log(false, LogCategory::Io, "Запретил прерывания".to_string());
                         computer.registers.set_allow_interrupt(false);
                         computer.registers.set_interrupt(false);
                     }
                     IOControl::EnableInterruption => {
-                        computer.log(false, "Разрешил прерывания".to_string());
+                        computer.log(false, LogCategory::Io, "Разрешил прерывания".to_string());
                         computer.registers.set_allow_interrupt(true);
                     }
                     IOControl::Reset => {
-                        computer.log(false, "Сбросил флаги готовности ВУ".to_string());
+                        computer.log(
+                            false,
+                            LogCategory::Io,
+                            "Сбросил флаги готовности ВУ".to_string(),
+                        );
                         for device in &mut computer.io_devices {
                             device.ready = false;
                         }
@@ -719,7 +776,11 @@ impl MicroCommand for OperationalCommand1 {
 
         match self.c() {
             CUpdate::Reset => {
-                computer.log(false, "Сбросил флаг переноса".to_string());
+                computer.log(
+                    false,
+                    LogCategory::Flags,
+                    "Сбросил флаг переноса".to_string(),
+                );
                 computer.registers.set_overflow(false);
             }
             CUpdate::Assign => {
@@ -728,12 +789,17 @@ impl MicroCommand for OperationalCommand1 {
                     computer.registers.set_overflow(true);
                     computer.log(
                         false,
+                        LogCategory::Flags,
                         "Установил флаг переноса и убрал лишнюю единицу у БР".to_string(),
                     );
                 }
             }
             CUpdate::SetOne => {
-                computer.log(false, "Установил флаг переноса".to_string());
+                computer.log(
+                    false,
+                    LogCategory::Flags,
+                    "Установил флаг переноса".to_string(),
+                );
                 computer.registers.set_overflow(true);
             }
             CUpdate::None => {}
@@ -744,19 +810,27 @@ impl MicroCommand for OperationalCommand1 {
         if nz == NZUpdate::Z || nz == NZUpdate::NZ {
             if computer.registers.r_buffer == 0 {
                 computer.registers.set_null(true);
-                computer.log(false, "Установил флаг \"нуль\"".to_string());
+                computer.log(
+                    false,
+                    LogCategory::Flags,
+                    "Установил флаг \"нуль\"".to_string(),
+                );
             } else {
                 computer.registers.set_null(false);
-                computer.log(false, "Убрал флаг \"нуль\"".to_string());
+                computer.log(false, LogCategory::Flags, "Убрал флаг \"нуль\"".to_string());
             }
         }
         if nz == NZUpdate::N || nz == NZUpdate::NZ {
             if bit_at(computer.registers.r_buffer as u16, 15) {
                 computer.registers.set_negative(true);
-                computer.log(false, "Установил флаг \"знак\"".to_string());
+                computer.log(
+                    false,
+                    LogCategory::Flags,
+                    "Установил флаг \"знак\"".to_string(),
+                );
             } else {
                 computer.registers.set_negative(false);
-                computer.log(false, "Убрал флаг \"знак\"".to_string());
+                computer.log(false, LogCategory::Flags, "Убрал флаг \"знак\"".to_string());
             }
         }
 
@@ -764,6 +838,7 @@ impl MicroCommand for OperationalCommand1 {
             for register in v {
                 computer.log(
                     register != Register::Counter && register != Register::CommandCounter,
+                    LogCategory::Registers,
                     format!(
                         "Перенес значение {:0>4X} из регистра БР в регистр {}",
                         computer.registers.r_buffer,
@@ -975,10 +1050,47 @@ impl MicroCommand for OperationalCommand1 {
 
         result
     }
+
+    fn fired(&self) -> Vec<&'static str> {
+        if self.hlt() {
+            return vec!["Останов"];
+        }
+
+        let io = self.io();
+        if !io.is_empty() {
+            return io
+                .iter()
+                .map(|v| match v {
+                    IOControl::Connect => "Связь с ВУ",
+                    IOControl::Reset => "Сброс флагов ВУ",
+                    IOControl::DisableInterruption => "DI",
+                    IOControl::EnableInterruption => "EI",
+                })
+                .collect();
+        }
+
+        let mut result = vec![];
+        match self.c() {
+            CUpdate::Assign => result.push("C=перенос"),
+            CUpdate::Reset => result.push("C=0"),
+            CUpdate::SetOne => result.push("C=1"),
+            CUpdate::None => {}
+        }
+        match self.nz() {
+            NZUpdate::N => result.push("N"),
+            NZUpdate::Z => result.push("Z"),
+            NZUpdate::NZ => {
+                result.push("N");
+                result.push("Z");
+            }
+            NZUpdate::None => {}
+        }
+        result
+    }
 }
 
 #[derive(Eq, PartialEq)]
-enum Shift {
+pub enum Shift {
     Left,
     Right,
     None,
@@ -1035,7 +1147,7 @@ impl OperationalCommand0 {
         }
     }
 
-    fn shift(&self) -> Shift {
+    pub fn shift(&self) -> Shift {
         let b2 = bit_at(self.0, 2);
         let b3 = bit_at(self.0, 3);
 
@@ -1,4 +1,5 @@
 use imgui::Ui;
+use std::ops::Range;
 
 pub mod file;
 pub mod general;
@@ -16,4 +17,8 @@ pub trait Parser<T: CommandInfo> {
     fn supports_rev_parse(&self) -> bool;
 
     fn rev_parse(&self, str: &str) -> Result<u16, String>;
+
+    /// Сдвигает на `offset` адрес в команде, если он указывает внутрь `range`.
+    /// Нужно, чтобы перенесенный кусок программы продолжал работать
+    fn relocate(&self, opcode: u16, range: &Range<u16>, offset: i32) -> u16;
 }
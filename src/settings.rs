@@ -0,0 +1,160 @@
+//! Настройки, которые переживают перезапуск: тема, представление ячеек, фильтры лога,
+//! формат трассировки, недавние файлы.
+//!
+//! Лежат в папке настроек системы (`~/.config/bevm`, `%APPDATA%\bevm`,
+//! `~/Library/Application Support/bevm`) простым текстом: по строке `ключ = значение`.
+//! Списки записываются одинаковым ключом на нескольких строках.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Сколько недавних файлов помнить
+pub const RECENT_LIMIT: usize = 8;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Settings {
+    values: BTreeMap<String, Vec<String>>,
+}
+
+impl Settings {
+    /// Файл настроек. `None`, если система не говорит, где хранить настройки
+    pub fn path() -> Option<PathBuf> {
+        let dir = if cfg!(windows) {
+            PathBuf::from(std::env::var_os("APPDATA")?)
+        } else if cfg!(target_os = "macos") {
+            PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+        } else if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+            PathBuf::from(dir)
+        } else {
+            PathBuf::from(std::env::var_os("HOME")?).join(".config")
+        };
+        Some(dir.join("bevm").join("settings.conf"))
+    }
+
+    /// Читает настройки. Если файла нет или его не прочитать - настройки по умолчанию
+    pub fn load() -> Settings {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("Не знаю, где хранить настройки")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&path, self.to_text()).map_err(|e| e.to_string())
+    }
+
+    /// Строки без `=` и пустые строки пропускаются
+    pub fn parse(text: &str) -> Settings {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            if key.is_empty() {
+                continue;
+            }
+            settings
+                .values
+                .entry(key.to_string())
+                .or_default()
+                .push(value.trim().to_string());
+        }
+        settings
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (key, values) in &self.values {
+            for value in values {
+                text.push_str(&format!("{key} = {value}\n"));
+            }
+        }
+        text
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key)?.first().map(|v| v.as_str())
+    }
+
+    /// Значение, разобранное из строки. `None`, если его нет или оно не разбирается
+    pub fn parsed<T: std::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.parse().ok()
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.set_list(key, vec![value.to_string()]);
+    }
+
+    pub fn list(&self, key: &str) -> &[String] {
+        self.values.get(key).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// Пустой список удаляет ключ
+    pub fn set_list(&mut self, key: &str, values: Vec<String>) {
+        if values.is_empty() {
+            self.values.remove(key);
+        } else if self.values.get(key) != Some(&values) {
+            self.values.insert(key.to_string(), values);
+        }
+    }
+
+    /// Ставит файл первым в списке недавних, убирая повтор. Список не длиннее [RECENT_LIMIT]
+    pub fn push_recent(&mut self, key: &str, file: &str) {
+        let mut recent = self.list(key).to_vec();
+        recent.retain(|f| f != file);
+        recent.insert(0, file.to_string());
+        recent.truncate(RECENT_LIMIT);
+        self.set_list(key, recent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::{Settings, RECENT_LIMIT};
+
+    #[test]
+    fn round_trip() {
+        let mut settings = Settings::default();
+        settings.set("theme", "dark");
+        settings.set("log.limit", 500);
+        settings.set_list(
+            "log.categories",
+            vec!["memory".to_string(), "flags".to_string()],
+        );
+        settings.push_recent("recent.general", "/tmp/a = b.mm");
+
+        let parsed = Settings::parse(&settings.to_text());
+        assert_eq!(parsed, settings);
+        assert_eq!(parsed.get("theme"), Some("dark"));
+        assert_eq!(parsed.parsed::<i32>("log.limit"), Some(500));
+        assert_eq!(parsed.parsed::<i32>("theme"), None);
+        assert_eq!(parsed.list("log.categories"), ["memory", "flags"]);
+        assert_eq!(parsed.list("recent.general"), ["/tmp/a = b.mm"]);
+        assert_eq!(parsed.get("missing"), None);
+
+        let garbage = Settings::parse("no equals sign\n = no key\n\ntheme=light");
+        assert_eq!(garbage.get("theme"), Some("light"));
+        assert_eq!(garbage.to_text(), "theme = light\n");
+    }
+
+    #[test]
+    fn recent() {
+        let mut settings = Settings::default();
+        for i in 0..RECENT_LIMIT + 2 {
+            settings.push_recent("recent", &format!("{i}.mm"));
+        }
+        settings.push_recent("recent", "5.mm");
+
+        let recent = settings.list("recent");
+        assert_eq!(recent.len(), RECENT_LIMIT);
+        assert_eq!(recent[0], "5.mm");
+        assert_eq!(recent[1], "9.mm");
+        assert_eq!(recent.iter().filter(|f| *f == "5.mm").count(), 1);
+        assert!(!recent.contains(&"0.mm".to_string()));
+    }
+}
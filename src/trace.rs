@@ -0,0 +1,1594 @@
+//! Таблицы трассировки: какие бывают колонки и как их заполнять.
+
+use crate::model::{Computer, Engine, IOCell, MemoryDelta, Register, Registers, FETCH_START};
+use crate::parse::mc::{parse, ExecutionResult};
+use crate::parse::{CommandInfo, Parser};
+use crate::utils::bit_registers::bit_at;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// Что трассируем: команды основной памяти или микрокоманды
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum TraceKind {
+    General,
+    Micro,
+}
+
+impl TraceKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            TraceKind::General => "Основная память",
+            TraceKind::Micro => "Память МПУ",
+        }
+    }
+
+    /// Имя в настройках
+    pub fn key(&self) -> &'static str {
+        match self {
+            TraceKind::General => "general",
+            TraceKind::Micro => "micro",
+        }
+    }
+}
+
+/// Флаги РС, которые имеет смысл выводить отдельной колонкой
+pub const FLAGS: [(u8, &str); 7] = [
+    (0, "С"),
+    (1, "Z"),
+    (2, "N"),
+    (4, "Разр. прер."),
+    (5, "Прер."),
+    (6, "Ф"),
+    (7, "Тумблер"),
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Column {
+    /// Адрес выполненной команды (для МПУ - СчМК до выборки микрокоманды)
+    Address,
+    /// Код выполненной команды (для МПУ - выбранная микрокоманда)
+    Code,
+    /// Мнемоника выполненной команды
+    Mnemonic,
+    /// Горизонтальное представление микрокоманды
+    Horizontal,
+    /// Какие поля микрокоманды сработали и был ли переход
+    Fired,
+    /// Значение регистра после выполнения
+    Register(Register),
+    /// Бит РС после выполнения
+    Flag(u8),
+    /// Значение в регистре данных ВУ
+    Io(usize),
+    /// Адрес изменившейся ячейки
+    ChangedAddress,
+    /// Новое значение изменившейся ячейки
+    ChangedValue,
+}
+
+impl Column {
+    /// Все колонки, которые имеют смысл для этого вида трассировки
+    pub fn all(kind: TraceKind) -> Vec<Column> {
+        let mut result = vec![Column::Address, Column::Code, Column::Mnemonic];
+        if kind == TraceKind::Micro {
+            result.push(Column::Horizontal);
+            result.push(Column::Fired);
+        }
+        result.extend(
+            [
+                Register::CommandCounter,
+                Register::Address,
+                Register::Command,
+                Register::Data,
+                Register::Counter,
+                Register::Buffer,
+                Register::Status,
+                Register::McCounter,
+                Register::MicroCommand,
+            ]
+            .iter()
+            .map(|r| Column::Register(*r)),
+        );
+        result.extend(FLAGS.iter().map(|(bit, _)| Column::Flag(*bit)));
+        result.extend((0..16).map(Column::Io));
+        result.push(Column::ChangedAddress);
+        result.push(Column::ChangedValue);
+        result
+    }
+
+    pub fn header(kind: TraceKind, columns: &[Column]) -> Vec<String> {
+        columns.iter().map(|c| c.title(kind)).collect()
+    }
+
+    pub fn title(&self, kind: TraceKind) -> String {
+        match (self, kind) {
+            (Column::Address, TraceKind::General) => "Адрес".to_string(),
+            (Column::Address, TraceKind::Micro) => "СчМК до выборки МК".to_string(),
+            (Column::Code, TraceKind::General) => "Код".to_string(),
+            (Column::Code, TraceKind::Micro) => "ВМК".to_string(),
+            (Column::Mnemonic, _) => "Мнемоника".to_string(),
+            (Column::Horizontal, _) => "Горизонтальная МК".to_string(),
+            (Column::Fired, _) => "Сработало".to_string(),
+            (Column::Register(r), _) => r.mnemonic(),
+            (Column::Flag(bit), _) => FLAGS
+                .iter()
+                .find(|(b, _)| b == bit)
+                .map_or_else(|| format!("РС[{bit}]"), |(_, name)| name.to_string()),
+            (Column::Io(n), _) => format!("ВУ-{n:X}"),
+            (Column::ChangedAddress, _) => "Адрес".to_string(),
+            (Column::ChangedValue, _) => "Новый код".to_string(),
+        }
+    }
+
+    /// Заголовок группы колонок, как в шапке таблицы из методички
+    pub fn group(&self) -> &'static str {
+        match self {
+            Column::Address
+            | Column::Code
+            | Column::Mnemonic
+            | Column::Horizontal
+            | Column::Fired => "Выполняемая команда",
+            Column::ChangedAddress | Column::ChangedValue => "Изменившаяся ячейка",
+            _ => "Содержимое регистров после выполнения команды",
+        }
+    }
+
+    /// Колонки по заголовкам таблицы. Одинаковые заголовки ("Адрес") разбираются по порядку:
+    /// сначала адрес команды, потом адрес изменившейся ячейки. "Адресс" из таблиц старых версий
+    /// тоже понимается
+    pub fn parse_header(kind: TraceKind, header: &[String]) -> Result<Vec<Column>, String> {
+        let mut result: Vec<Column> = vec![];
+        for title in header {
+            let mut lowercase = title.trim().to_lowercase();
+            if lowercase == "адресс" {
+                lowercase = "адрес".to_string();
+            }
+            let column = Column::all(kind)
+                .into_iter()
+                .filter(|c| !result.contains(c))
+                .find(|c| c.title(kind).to_lowercase() == lowercase)
+                .ok_or_else(|| format!("Не знаю, что за колонка \"{title}\""))?;
+            result.push(column);
+        }
+        Ok(result)
+    }
+
+    /// Значения колонки для шага. У изменившихся ячеек их может быть сколько угодно, у остальных ровно одно
+    fn values(&self, kind: TraceKind, record: &TraceRecord) -> Vec<String> {
+        match self {
+            Column::ChangedAddress => record
+                .memory
+                .iter()
+                .map(|d| format!("{:0>3X}", d.address))
+                .collect(),
+            Column::ChangedValue => record
+                .memory
+                .iter()
+                .map(|d| format!("{:0>4X}", d.after))
+                .collect(),
+            _ => vec![self.value(kind, record)],
+        }
+    }
+
+    fn value(&self, kind: TraceKind, record: &TraceRecord) -> String {
+        let registers = &record.after;
+        match self {
+            Column::Address => format!("{:0>3X}", record.address),
+            Column::Code => format!("{:0>4X}", record.opcode),
+            Column::Mnemonic => record.mnemonic.clone(),
+            Column::Horizontal if kind == TraceKind::Micro => {
+                let horizontal = parse(record.opcode).horizontal();
+                format!("{:0>4X} {:0>4X}", horizontal >> 16, horizontal & 0xFFFF)
+            }
+            Column::Fired if kind == TraceKind::Micro => {
+                let mut fired = parse(record.opcode)
+                    .fired()
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<String>>();
+                let next = (record.address as u8).wrapping_add(1);
+                if record.after.r_micro_command_counter != next {
+                    fired.push(format!(
+                        "Переход на {:0>2X}",
+                        record.after.r_micro_command_counter
+                    ));
+                }
+                fired.join(", ")
+            }
+            Column::Horizontal | Column::Fired => String::new(),
+            // в таблицах МПУ из методички СК и РА трехзначные, а БР без семнадцатого бита
+            Column::Register(Register::CommandCounter) if kind == TraceKind::Micro => {
+                format!("{:0>3X}", registers.r_command_counter)
+            }
+            Column::Register(Register::Address) if kind == TraceKind::Micro => {
+                format!("{:0>3X}", registers.r_address)
+            }
+            Column::Register(Register::Buffer) if kind == TraceKind::Micro => {
+                format!("{:0>4X}", registers.r_buffer)
+            }
+            Column::Register(Register::McCounter) => {
+                format!("{:0>3X}", registers.r_micro_command_counter)
+            }
+            Column::Register(Register::Buffer) => format!("{:0>4X}", registers.r_buffer),
+            Column::Register(r) => format!("{:0>4X}", register_value(*r, registers)),
+            Column::Flag(bit) => {
+                if bit_at(registers.r_status, *bit) {
+                    "1".to_string()
+                } else {
+                    "0".to_string()
+                }
+            }
+            Column::Io(n) => format!("{:0>2X}", record.devices[*n].data),
+            Column::ChangedAddress | Column::ChangedValue => String::new(),
+        }
+    }
+}
+
+fn register_value(register: Register, registers: &Registers) -> u32 {
+    match register {
+        Register::McCounter => registers.r_micro_command_counter as u32,
+        Register::Status => registers.r_status as u32,
+        Register::MicroCommand => registers.r_micro_command as u32,
+        Register::Buffer => registers.r_buffer,
+        Register::Address => registers.r_address as u32,
+        Register::Command => registers.r_command as u32,
+        Register::Data => registers.r_data as u32,
+        Register::CommandCounter => registers.r_command_counter as u32,
+        Register::Counter => registers.r_counter as u32,
+    }
+}
+
+pub struct Preset {
+    pub name: &'static str,
+    pub kind: TraceKind,
+    pub columns: Vec<Column>,
+}
+
+/// Раскладки таблиц из методички
+pub fn presets() -> Vec<Preset> {
+    use Column::*;
+
+    let command = [Address, Code];
+    let registers = [
+        Register(crate::model::Register::CommandCounter),
+        Register(crate::model::Register::Address),
+        Register(crate::model::Register::Command),
+        Register(crate::model::Register::Data),
+        Register(crate::model::Register::Counter),
+        Flag(0),
+    ];
+    let changed = [ChangedAddress, ChangedValue];
+
+    vec![
+        Preset {
+            name: "Лаб. 1-4: выполнение программы",
+            kind: TraceKind::General,
+            columns: [&command[..], &registers[..], &changed[..]].concat(),
+        },
+        Preset {
+            name: "Лаб. 5: асинхронный обмен",
+            kind: TraceKind::General,
+            columns: [
+                &command[..],
+                &registers[..],
+                &[Flag(6), Io(1), Io(2), Io(3)][..],
+                &changed[..],
+            ]
+            .concat(),
+        },
+        Preset {
+            name: "Лаб. 6: прерывания",
+            kind: TraceKind::General,
+            columns: [
+                &command[..],
+                &registers[..],
+                &[Flag(4), Flag(5), Io(1), Io(2), Io(3)][..],
+                &changed[..],
+            ]
+            .concat(),
+        },
+        Preset {
+            name: "Лаб. 7: микропрограмма",
+            kind: TraceKind::Micro,
+            columns: vec![
+                Address,
+                Code,
+                Register(crate::model::Register::CommandCounter),
+                Register(crate::model::Register::Address),
+                Register(crate::model::Register::Command),
+                Register(crate::model::Register::Data),
+                Register(crate::model::Register::Counter),
+                Flag(0),
+                Register(crate::model::Register::Buffer),
+                Flag(2),
+                Flag(1),
+                Register(crate::model::Register::McCounter),
+            ],
+        },
+        Preset {
+            name: "Лаб. 7: разбор микрокоманд",
+            kind: TraceKind::Micro,
+            columns: vec![
+                Address,
+                Code,
+                Horizontal,
+                Mnemonic,
+                Fired,
+                Register(crate::model::Register::McCounter),
+            ],
+        },
+    ]
+}
+
+#[derive(Clone)]
+pub struct IoDelta {
+    pub device: usize,
+    pub before: IOCell,
+    pub after: IOCell,
+}
+
+/// Что произошло за один шаг трассировки: за команду или за микрокоманду
+#[derive(Clone)]
+pub struct TraceRecord {
+    /// Номер шага в трассировке, с нуля
+    pub step: usize,
+    /// Адрес команды (для МПУ - СчМК до выборки)
+    pub address: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub before: Registers,
+    pub after: Registers,
+    pub memory: Vec<MemoryDelta>,
+    pub io: Vec<IoDelta>,
+    /// Состояние ВУ после шага
+    pub devices: [IOCell; 16],
+    /// Сколько микрокоманд ушло на шаг. None, если команда исполнялась без микропрограммы
+    pub micro_steps: Option<u64>,
+}
+
+impl TraceRecord {
+    /// Биты РС, которые поменялись за шаг: номер, было, стало
+    pub fn flag_changes(&self) -> Vec<(u8, bool, bool)> {
+        (0..13)
+            .map(|bit| {
+                (
+                    bit,
+                    bit_at(self.before.r_status, bit),
+                    bit_at(self.after.r_status, bit),
+                )
+            })
+            .filter(|(_, before, after)| before != after)
+            .collect()
+    }
+
+    pub fn to_json(&self, kind: TraceKind) -> String {
+        let memory = self
+            .memory
+            .iter()
+            .map(|d| {
+                format!(
+                    "{{\"address\":{},\"before\":{},\"after\":{}}}",
+                    d.address, d.before, d.after
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let io = self
+            .io
+            .iter()
+            .map(|d| {
+                format!(
+                    "{{\"device\":{},\"data_before\":{},\"data_after\":{},\"ready_before\":{},\"ready_after\":{}}}",
+                    d.device, d.before.data, d.after.data, d.before.ready, d.after.ready
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let flags = self
+            .flag_changes()
+            .iter()
+            .map(|(bit, before, after)| {
+                format!(
+                    "{{\"bit\":{},\"name\":{},\"before\":{},\"after\":{}}}",
+                    bit,
+                    json_string(&Column::Flag(*bit).title(kind)),
+                    before,
+                    after
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+
+        format!(
+            "{{\"step\":{},\"kind\":\"{}\",\"address\":{},\"opcode\":{},\"mnemonic\":{},\
+            \"micro_steps\":{},\"before\":{},\"after\":{},\"flags\":[{}],\"memory\":[{}],\"io\":[{}]}}",
+            self.step,
+            match kind {
+                TraceKind::General => "general",
+                TraceKind::Micro => "micro",
+            },
+            self.address,
+            self.opcode,
+            json_string(&self.mnemonic),
+            self.micro_steps
+                .map_or("null".to_string(), |n| n.to_string()),
+            registers_json(&self.before),
+            registers_json(&self.after),
+            flags,
+            memory,
+            io
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:0>4x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn registers_json(registers: &Registers) -> String {
+    format!(
+        "{{\"mc_counter\":{},\"status\":{},\"micro_command\":{},\"buffer\":{},\"address\":{},\
+        \"command\":{},\"data\":{},\"command_counter\":{},\"counter\":{}}}",
+        registers.r_micro_command_counter,
+        registers.r_status,
+        registers.r_micro_command,
+        registers.r_buffer,
+        registers.r_address,
+        registers.r_command,
+        registers.r_data,
+        registers.r_command_counter,
+        registers.r_counter
+    )
+}
+
+/// Строка таблицы. В каждой колонке список значений: если за шаг изменилось несколько ячеек,
+/// строка делится на несколько подстрок, а остальные колонки растягиваются на всю ее высоту
+pub struct TraceRow {
+    pub cells: Vec<Vec<String>>,
+}
+
+impl TraceRow {
+    pub fn new(kind: TraceKind, columns: &[Column], record: &TraceRecord) -> TraceRow {
+        TraceRow {
+            cells: columns.iter().map(|c| c.values(kind, record)).collect(),
+        }
+    }
+
+    /// Сколько подстрок занимает строка
+    pub fn height(&self) -> usize {
+        self.cells.iter().map(|c| c.len()).max().unwrap_or(1).max(1)
+    }
+
+    /// Занимает ли колонка всю высоту строки одним значением
+    pub fn is_spanning(&self, column: usize) -> bool {
+        self.cells[column].len() <= 1 && self.height() > 1
+    }
+
+    /// Значение колонки в подстроке. Растянутые колонки дают значение только в первой подстроке
+    pub fn get(&self, column: usize, sub_row: usize) -> &str {
+        self.cells[column].get(sub_row).map_or("", |s| s.as_str())
+    }
+
+    /// Строка, разложенная на подстроки. Недостающие значения - пустые строки
+    pub fn sub_rows(&self) -> Vec<Vec<String>> {
+        (0..self.height())
+            .map(|sub_row| {
+                (0..self.cells.len())
+                    .map(|column| self.get(column, sub_row).to_string())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+pub struct Tracing {
+    pub kind: TraceKind,
+    pub header: Vec<String>,
+    /// Группы колонок для двухэтажной шапки: название и сколько колонок подряд в нее входит
+    pub groups: Vec<(String, usize)>,
+    pub tracing: Vec<TraceRow>,
+    pub records: Vec<TraceRecord>,
+}
+
+impl Tracing {
+    pub fn new(kind: TraceKind, columns: &[Column], records: Vec<TraceRecord>) -> Tracing {
+        let mut groups: Vec<(String, usize)> = vec![];
+        for column in columns {
+            match groups.last_mut() {
+                Some((name, count)) if name == column.group() => *count += 1,
+                _ => groups.push((column.group().to_string(), 1)),
+            }
+        }
+
+        Tracing {
+            kind,
+            header: Column::header(kind, columns),
+            groups,
+            tracing: records
+                .iter()
+                .map(|record| TraceRow::new(kind, columns, record))
+                .collect(),
+            records,
+        }
+    }
+
+    /// Все шаги одним JSON массивом
+    pub fn to_json(&self) -> String {
+        let records = self
+            .records
+            .iter()
+            .map(|r| format!("  {}", r.to_json(self.kind)))
+            .collect::<Vec<String>>()
+            .join(",\n");
+        format!("[\n{records}\n]\n")
+    }
+}
+
+/// Запоминает состояние ЭВМ перед шагом, чтобы потом собрать из него `TraceRecord`
+struct Snapshot {
+    registers: Registers,
+    memory: Vec<u16>,
+    devices: [IOCell; 16],
+    micro_steps: u64,
+}
+
+impl Snapshot {
+    fn take(computer: &Computer) -> Snapshot {
+        Snapshot {
+            registers: computer.registers.clone(),
+            memory: computer.general_memory.borrow().values(),
+            devices: computer.io_devices,
+            micro_steps: computer.micro_steps,
+        }
+    }
+
+    fn record(
+        self,
+        computer: &Computer,
+        step: usize,
+        address: u16,
+        opcode: u16,
+        mnemonic: String,
+        by_microcode: bool,
+    ) -> TraceRecord {
+        let memory = computer.general_memory.borrow().diff(&self.memory);
+        let io = computer
+            .io_devices
+            .iter()
+            .zip(self.devices.iter())
+            .enumerate()
+            .filter(|(_, (after, before))| after.data != before.data || after.ready != before.ready)
+            .map(|(device, (after, before))| IoDelta {
+                device,
+                before: *before,
+                after: *after,
+            })
+            .collect();
+
+        TraceRecord {
+            step,
+            address,
+            opcode,
+            mnemonic,
+            before: self.registers,
+            after: computer.registers.clone(),
+            memory,
+            io,
+            devices: computer.io_devices,
+            micro_steps: if by_microcode {
+                Some(computer.micro_steps - self.micro_steps)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Когда еще, кроме HLT и конца таблицы, надо остановить трассировку
+#[derive(Clone, Default)]
+pub struct StopConditions {
+    /// Следующая команда лежит по этому адресу
+    pub address: Option<u16>,
+    /// Следующая команда стоит на точке останова
+    pub breakpoints: bool,
+    /// Команда по адресу собирается выполниться в N-й раз
+    pub loop_head: Option<(u16, u32)>,
+    /// ЭВМ пришла в состояние, в котором уже была: регистры, память и ВУ совпадают.
+    /// Пока у какого-то ВУ сценарий еще не закончился, не проверяется
+    pub infinite_loop: bool,
+    /// МПУ ушло на обработку прерывания
+    pub interrupt: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    Halt,
+    Limit,
+    Address,
+    Breakpoint,
+    LoopHead,
+    InfiniteLoop,
+    Interrupt,
+    /// Трассировка не запускалась, а была записана по ходу работы с панелью управления
+    Recorded,
+}
+
+impl StopReason {
+    pub fn title(&self) -> &'static str {
+        match self {
+            StopReason::Halt => "ЭВМ выполнила HLT",
+            StopReason::Limit => "Закончилось место в таблице",
+            StopReason::Address => "Дошли до нужного адреса",
+            StopReason::Breakpoint => "Дошли до точки останова",
+            StopReason::LoopHead => "Начало цикла выполнилось нужное количество раз",
+            StopReason::InfiniteLoop => "ЭВМ зациклилась: это состояние уже было",
+            StopReason::Interrupt => "МПУ ушло на обработку прерывания",
+            StopReason::Recorded => "Записано с панели управления",
+        }
+    }
+}
+
+/// Сколько состояний помнить для поиска зацикливания. Потом они забываются, но цикл короче
+/// этого все равно находится
+const SEEN_LIMIT: usize = 100_000;
+
+/// Следит за условиями остановки по ходу трассировки
+struct Watcher<'a> {
+    conditions: &'a StopConditions,
+    loop_count: u32,
+    seen: HashSet<u64>,
+    interrupts: u64,
+}
+
+impl<'a> Watcher<'a> {
+    fn new(conditions: &'a StopConditions, computer: &Computer) -> Watcher<'a> {
+        Watcher {
+            conditions,
+            loop_count: 0,
+            seen: HashSet::new(),
+            interrupts: computer.interrupts,
+        }
+    }
+
+    /// Учитывает место, с которого начинается трассировка: если это начало цикла, то это уже
+    /// первый заход в него
+    fn start(&mut self, computer: &Computer, boundary: bool) -> Option<StopReason> {
+        if let Some((head, count)) = self.conditions.loop_head {
+            if boundary && head == computer.registers.r_command_counter {
+                self.loop_count += 1;
+                if self.loop_count >= count {
+                    return Some(StopReason::LoopHead);
+                }
+            }
+        }
+        None
+    }
+
+    /// `None`, если состояние зависит от номера цикла команды и сравнивать его нельзя
+    fn state_hash(computer: &Computer) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        let r = &computer.registers;
+        (
+            r.r_micro_command_counter,
+            r.r_status,
+            r.r_micro_command,
+            r.r_buffer,
+            r.r_address,
+            r.r_command,
+            r.r_data,
+            r.r_command_counter,
+            r.r_counter,
+        )
+            .hash(&mut hasher);
+        for cell in computer.general_memory.borrow().data.iter() {
+            cell.get().hash(&mut hasher);
+        }
+        for (cell, driver) in computer.io_devices.iter().zip(computer.io_drivers.iter()) {
+            (cell.data, cell.ready, driver.loop_state()?).hash(&mut hasher);
+        }
+        computer.interrupt_mask.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    /// Вызывается после каждого шага. `boundary` - ЭВМ стоит перед выборкой следующей команды,
+    /// то есть СК указывает на нее
+    fn check(&mut self, computer: &Computer, boundary: bool) -> Option<StopReason> {
+        if computer.registers.r_command == 0xF000 {
+            return Some(StopReason::Halt);
+        }
+
+        if self.conditions.interrupt && computer.interrupts != self.interrupts {
+            return Some(StopReason::Interrupt);
+        }
+        self.interrupts = computer.interrupts;
+
+        if !boundary {
+            return None;
+        }
+        let next = computer.registers.r_command_counter;
+
+        if self.conditions.infinite_loop {
+            if let Some(hash) = Self::state_hash(computer) {
+                if self.seen.len() >= SEEN_LIMIT {
+                    self.seen.clear();
+                }
+                if !self.seen.insert(hash) {
+                    return Some(StopReason::InfiniteLoop);
+                }
+            }
+        }
+
+        if self.conditions.address == Some(next) {
+            return Some(StopReason::Address);
+        }
+        if self.conditions.breakpoints && computer.breakpoints.contains(&next) {
+            return Some(StopReason::Breakpoint);
+        }
+        if let Some((head, count)) = self.conditions.loop_head {
+            if head == next {
+                self.loop_count += 1;
+                if self.loop_count >= count {
+                    return Some(StopReason::LoopHead);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Выполняет до `len` микрокоманд
+fn mc_tracing(
+    computer: &mut Computer,
+    len: usize,
+    watcher: &mut Watcher,
+    sink: &mut dyn FnMut(TraceRecord),
+) -> StopReason {
+    for step in 0..len {
+        let pos = computer.registers.r_micro_command_counter;
+        let code = computer
+            .mc_memory
+            .borrow()
+            .data
+            .get(pos as usize)
+            .unwrap()
+            .get();
+        let snapshot = Snapshot::take(computer);
+
+        computer.registers.set_execute_by_tick(false);
+        computer.registers.set_lever(false);
+        computer.registers.set_program_mode(false);
+
+        computer.micro_step();
+
+        let mnemonic = computer.mc_memory.borrow().parser.parse(code).mnemonic();
+        sink(snapshot.record(computer, step, pos as u16, code, mnemonic, true));
+
+        let boundary = computer.registers.r_micro_command_counter == FETCH_START;
+        if let Some(reason) = watcher.check(computer, boundary) {
+            return reason;
+        }
+    }
+
+    StopReason::Limit
+}
+
+/// Выполняет до `len` команд
+fn general_tracing(
+    computer: &mut Computer,
+    len: usize,
+    watcher: &mut Watcher,
+    sink: &mut dyn FnMut(TraceRecord),
+) -> StopReason {
+    for step in 0..len {
+        let pos = computer.registers.r_command_counter;
+        let code = computer
+            .general_memory
+            .borrow()
+            .data
+            .get(pos as usize)
+            .unwrap()
+            .get();
+        let snapshot = Snapshot::take(computer);
+        let by_microcode = computer.effective_engine() == Engine::Microcode;
+
+        computer.command_step();
+
+        let mnemonic = computer
+            .general_memory
+            .borrow()
+            .parser
+            .parse(code)
+            .mnemonic();
+        sink(snapshot.record(computer, step, pos, code, mnemonic, by_microcode));
+
+        if let Some(reason) = watcher.check(computer, true) {
+            return reason;
+        }
+    }
+
+    StopReason::Limit
+}
+
+/// Выполняет трассировку, отдавая каждый шаг в `sink` сразу после выполнения.
+/// Так можно писать в файл таблицы любой длины, не держа их в памяти
+pub fn run(
+    kind: TraceKind,
+    computer: &mut Computer,
+    len: usize,
+    stop: &StopConditions,
+    sink: &mut dyn FnMut(TraceRecord),
+) -> StopReason {
+    let mut watcher = Watcher::new(stop, computer);
+    let boundary = match kind {
+        TraceKind::General => true,
+        TraceKind::Micro => computer.registers.r_micro_command_counter == FETCH_START,
+    };
+    if let Some(reason) = watcher.start(computer, boundary) {
+        return reason;
+    }
+    match kind {
+        TraceKind::General => general_tracing(computer, len, &mut watcher, sink),
+        TraceKind::Micro => mc_tracing(computer, len, &mut watcher, sink),
+    }
+}
+
+/// Эталонная таблица трассировки, с которой сверяется ЭВМ. Например, присланная студентом
+pub struct Reference {
+    pub header: Vec<String>,
+    pub columns: Vec<Column>,
+    /// Строки таблицы как есть. У команды с несколькими изменившимися ячейками их несколько
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Разбивает строку CSV на поля. Поля в кавычках могут содержать разделитель, `""` внутри них - кавычка
+fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+impl Reference {
+    /// Разбирает CSV в том виде, в каком его сохраняет трассировка: разделитель - таб, поля в кавычках.
+    /// Таблицы, пересохраненные через Excel с запятыми или точками с запятой, тоже подходят
+    pub fn parse(kind: TraceKind, text: &str) -> Result<Reference, String> {
+        let mut lines = text
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+
+        let Some((_, first)) = lines.next() else {
+            return Err("Файл пустой".to_string());
+        };
+        let delimiter = ['\t', ';', ',']
+            .iter()
+            .copied()
+            .find(|d| first.contains(*d))
+            .unwrap_or('\t');
+
+        let mut header = split_csv_line(first, delimiter);
+        while header.last().map(String::is_empty) == Some(true) {
+            header.pop();
+        }
+        let columns = Column::parse_header(kind, &header)?;
+
+        // лишние пустые поля в конце строки оставляет сама трассировка, а вот остальное
+        // несовпадение с шапкой значит, что таблица поехала
+        let mut rows = vec![];
+        for (idx, line) in lines {
+            let mut row = split_csv_line(line, delimiter);
+            while row.len() > header.len() && row.last().map(String::is_empty) == Some(true) {
+                row.pop();
+            }
+            if row.len() != header.len() {
+                return Err(format!(
+                    "Строка {}: {} колонок, а в шапке {}",
+                    idx + 1,
+                    row.len(),
+                    header.len()
+                ));
+            }
+            rows.push(row);
+        }
+
+        Ok(Reference {
+            header,
+            columns,
+            rows,
+        })
+    }
+}
+
+/// Где трассировка ЭВМ впервые разошлась с эталоном
+pub struct Divergence {
+    /// Номер строки таблицы без шапки, с нуля
+    pub row: usize,
+    /// Первая несовпавшая колонка. None, если одна из таблиц просто кончилась раньше
+    pub column: Option<usize>,
+}
+
+pub struct Comparison {
+    /// Строки, которые получились у ЭВМ, в тех же колонках, что и в эталоне
+    pub actual: Vec<Vec<String>>,
+    pub divergence: Option<Divergence>,
+    pub stop: StopReason,
+}
+
+/// Одинаковы ли ячейки с точностью до регистра букв и ведущих нулей
+fn same_cell(expected: &str, actual: &str) -> bool {
+    if expected.eq_ignore_ascii_case(actual) {
+        return true;
+    }
+    match (
+        u32::from_str_radix(expected, 16),
+        u32::from_str_radix(actual, 16),
+    ) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Выполняет трассировку и сверяет ее с эталоном строка за строкой
+pub fn compare(
+    kind: TraceKind,
+    computer: &mut Computer,
+    reference: &Reference,
+    stop: &StopConditions,
+) -> Comparison {
+    let mut actual = vec![];
+    // каждый шаг дает хотя бы одну строку, так что больше шагов не понадобится
+    let len = reference.rows.len().max(1);
+    let stop = run(kind, computer, len, stop, &mut |record| {
+        actual.extend(TraceRow::new(kind, &reference.columns, &record).sub_rows());
+    });
+
+    let mut divergence = reference
+        .rows
+        .iter()
+        .zip(actual.iter())
+        .enumerate()
+        .find_map(|(row, (expected, actual))| {
+            expected
+                .iter()
+                .zip(actual.iter())
+                .position(|(e, a)| !same_cell(e, a))
+                .map(|column| Divergence {
+                    row,
+                    column: Some(column),
+                })
+        });
+    if divergence.is_none() && reference.rows.len() != actual.len() {
+        divergence = Some(Divergence {
+            row: reference.rows.len().min(actual.len()),
+            column: None,
+        });
+    }
+
+    Comparison {
+        actual,
+        divergence,
+        stop,
+    }
+}
+
+/// Сколько последних команд помнит `Recorder`
+pub const RECORDER_LIMIT: usize = 100_000;
+
+/// Команда, которая уже начала выполняться, но еще не дошла до следующей выборки
+struct Pending {
+    snapshot: Snapshot,
+    address: u16,
+    opcode: u16,
+}
+
+/// Записывает команды, которые выполняются с панели управления: большими шагами, микрошагами
+/// или в автоматическом режиме. Команда попадает в запись, когда МПУ возвращается на цикл выборки
+/// или останавливается, так что команда, пройденная по микрошагам, записывается один раз.
+///
+/// По умолчанию выключен: снимок памяти на каждой команде заметно тормозит автоматический режим
+pub struct Recorder {
+    pub enabled: bool,
+    /// Последние `RECORDER_LIMIT` команд
+    pub records: VecDeque<TraceRecord>,
+    /// Сколько команд записано всего, включая те, что уже выброшены из начала
+    pub total: usize,
+    pending: Option<Pending>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            enabled: false,
+            records: VecDeque::new(),
+            total: 0,
+            pending: None,
+        }
+    }
+
+    /// Выполняет шаг ЭВМ (`Computer::step` или `Computer::micro_step`) и записывает команду,
+    /// если она на этом шаге закончилась
+    pub fn step(
+        &mut self,
+        computer: &mut Computer,
+        step: impl FnOnce(&mut Computer) -> ExecutionResult,
+    ) -> ExecutionResult {
+        if self.enabled
+            && self.pending.is_none()
+            && computer.registers.r_micro_command_counter == FETCH_START
+        {
+            let address = computer.registers.r_command_counter;
+            let opcode = computer.general_memory.borrow().data[address as usize].get();
+            self.pending = Some(Pending {
+                snapshot: Snapshot::take(computer),
+                address,
+                opcode,
+            });
+        }
+
+        let result = step(computer);
+
+        let finished = matches!(result, ExecutionResult::Halted)
+            || computer.registers.r_micro_command_counter == FETCH_START;
+        if finished {
+            if let Some(pending) = self.pending.take() {
+                let mnemonic = computer
+                    .general_memory
+                    .borrow()
+                    .parser
+                    .parse(pending.opcode)
+                    .mnemonic();
+                let by_microcode = computer.micro_steps != pending.snapshot.micro_steps;
+                let record = pending.snapshot.record(
+                    computer,
+                    self.total,
+                    pending.address,
+                    pending.opcode,
+                    mnemonic,
+                    by_microcode,
+                );
+                self.total += 1;
+                self.records.push_back(record);
+                if self.records.len() > RECORDER_LIMIT {
+                    self.records.pop_front();
+                }
+            }
+        }
+
+        result
+    }
+
+    /// То же самое, что и `Computer::command_step`, только с записью
+    pub fn command_step(&mut self, computer: &mut Computer) {
+        computer.registers.set_execute_by_tick(false);
+        computer.registers.set_lever(false);
+        computer.registers.set_program_mode(false);
+        while !matches!(self.step(computer, Computer::step), ExecutionResult::Halted) {}
+    }
+
+    /// Забывает недовыполненную команду. Нужно, когда регистры поменяли в обход шагов
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.total = 0;
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::{DeviceDriver, ScriptAction, ScriptEvent, ScriptedDevice};
+    use crate::model::{Computer, Register};
+    use crate::trace::{
+        compare, presets, run, Column, Recorder, Reference, StopConditions, StopReason, TraceKind,
+        Tracing,
+    };
+
+    fn trace(
+        computer: &mut Computer,
+        len: usize,
+        columns: &[Column],
+        stop: &StopConditions,
+    ) -> (Tracing, StopReason) {
+        let mut records = vec![];
+        let reason = run(TraceKind::General, computer, len, stop, &mut |r| {
+            records.push(r)
+        });
+        (Tracing::new(TraceKind::General, columns, records), reason)
+    }
+
+    fn general_tracing(computer: &mut Computer, len: usize, columns: &[Column]) -> Tracing {
+        trace(computer, len, columns, &StopConditions::default()).0
+    }
+
+    #[test]
+    fn columns() {
+        let mut computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            // CLA; INC; MOV 20; HLT
+            for (i, v) in [0xF200, 0xF800, 0x3020, 0xF000].iter().enumerate() {
+                mem.data[0x10 + i].set(*v);
+            }
+        }
+        computer.registers.r_command_counter = 0x10;
+
+        let tracing = general_tracing(&mut computer, 100, &presets()[0].columns);
+        assert_eq!(
+            tracing.header,
+            vec![
+                "Адрес",
+                "Код",
+                "СК",
+                "РА",
+                "РК",
+                "РД",
+                "А",
+                "С",
+                "Адрес",
+                "Новый код"
+            ]
+        );
+        assert_eq!(
+            tracing.groups.iter().map(|g| g.1).collect::<Vec<usize>>(),
+            vec![2, 6, 2]
+        );
+        assert_eq!(tracing.tracing.len(), 4);
+        assert_eq!(
+            tracing.tracing[2].sub_rows(),
+            vec![vec![
+                "012", "3020", "0013", "0020", "3020", "0001", "0001", "0", "020", "0001"
+            ]]
+        );
+        assert_eq!(
+            tracing.tracing[1].sub_rows(),
+            vec![vec![
+                "011", "F800", "0012", "0011", "F800", "F800", "0001", "0", "", ""
+            ]]
+        );
+
+        computer.registers.r_command_counter = 0x10;
+        let tracing = general_tracing(
+            &mut computer,
+            1,
+            &[
+                Column::Mnemonic,
+                Column::Flag(1),
+                Column::Register(Register::Counter),
+            ],
+        );
+        assert_eq!(
+            tracing.tracing[0].sub_rows(),
+            vec![vec!["CLA", "1", "0000"]]
+        );
+    }
+
+    #[test]
+    fn several_changed_cells() {
+        let mut computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            // указатель на 30 в автоинкрементной ячейке; MOV (8)+
+            mem.data[0x8].set(0x0030);
+            mem.data[0x10].set(0x3808);
+        }
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_counter = 0x1234;
+
+        let columns = [
+            Column::Address,
+            Column::ChangedAddress,
+            Column::ChangedValue,
+        ];
+        let tracing = general_tracing(&mut computer, 1, &columns);
+        let row = &tracing.tracing[0];
+        assert_eq!(row.height(), 2);
+        assert!(row.is_spanning(0));
+        assert!(!row.is_spanning(1));
+        assert_eq!(
+            row.sub_rows(),
+            vec![vec!["010", "008", "0031"], vec!["", "030", "1234"]]
+        );
+    }
+
+    #[test]
+    fn records() {
+        let mut computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            // OUT 2; CLA; HLT
+            for (i, v) in [0xE302, 0xF200, 0xF000].iter().enumerate() {
+                mem.data[0x10 + i].set(*v);
+            }
+        }
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_counter = 0x41;
+
+        let tracing = general_tracing(&mut computer, 100, &[]);
+        let out = &tracing.records[0];
+        assert_eq!(out.io.len(), 1);
+        assert_eq!(out.io[0].device, 2);
+        assert_eq!(out.io[0].after.data, 0x41);
+
+        let cla = &tracing.records[1];
+        assert_eq!(cla.before.r_counter, 0x41);
+        assert_eq!(cla.after.r_counter, 0);
+        assert_eq!(cla.mnemonic, "CLA");
+        assert_eq!(cla.flag_changes(), vec![(1, false, true)]);
+        assert!(cla.micro_steps.unwrap() > 0);
+
+        let lines = tracing
+            .records
+            .iter()
+            .map(|r| r.to_json(TraceKind::General))
+            .collect::<Vec<String>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with(
+            "{\"step\":1,\"kind\":\"general\",\"address\":17,\"opcode\":61952,\"mnemonic\":\"CLA\""
+        ));
+        assert!(lines[1]
+            .contains("\"flags\":[{\"bit\":1,\"name\":\"Z\",\"before\":false,\"after\":true}]"));
+    }
+
+    #[test]
+    fn stop_conditions() {
+        let mut computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            // 10: INC; 11: BR 10
+            mem.data[0x10].set(0xF800);
+            mem.data[0x11].set(0xC010);
+            // 20: BR 20
+            mem.data[0x20].set(0xC020);
+        }
+
+        fn run_from(
+            computer: &mut Computer,
+            start: u16,
+            stop: StopConditions,
+        ) -> (Tracing, StopReason) {
+            computer.registers.r_command_counter = start;
+            trace(computer, 1000, &[], &stop)
+        }
+
+        let (tracing, reason) = run_from(&mut computer, 0x10, StopConditions::default());
+        assert_eq!(reason, StopReason::Limit);
+        assert_eq!(tracing.records.len(), 1000);
+
+        let (tracing, reason) = run_from(
+            &mut computer,
+            0x10,
+            StopConditions {
+                loop_head: Some((0x10, 3)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(reason, StopReason::LoopHead);
+        assert_eq!(tracing.records.len(), 4);
+
+        // первый заход в цикл - сам старт, так что второго ждать не надо
+        let (tracing, reason) = run_from(
+            &mut computer,
+            0x10,
+            StopConditions {
+                loop_head: Some((0x10, 1)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(reason, StopReason::LoopHead);
+        assert_eq!(tracing.records.len(), 0);
+
+        let (tracing, reason) = run_from(
+            &mut computer,
+            0x11,
+            StopConditions {
+                loop_head: Some((0x10, 3)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(reason, StopReason::LoopHead);
+        assert_eq!(tracing.records.len(), 5);
+
+        let (tracing, reason) = run_from(
+            &mut computer,
+            0x10,
+            StopConditions {
+                address: Some(0x11),
+                ..Default::default()
+            },
+        );
+        assert_eq!(reason, StopReason::Address);
+        assert_eq!(tracing.records.len(), 1);
+
+        // INC каждый раз меняет А, так что это не зацикливание, а BR на себя - да
+        let (tracing, reason) = run_from(
+            &mut computer,
+            0x20,
+            StopConditions {
+                infinite_loop: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(reason, StopReason::InfiniteLoop);
+        assert_eq!(tracing.records.len(), 2);
+
+        computer.breakpoints.insert(0x11);
+        let (tracing, reason) = run_from(
+            &mut computer,
+            0x10,
+            StopConditions {
+                breakpoints: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(reason, StopReason::Breakpoint);
+        assert_eq!(tracing.records.len(), 1);
+    }
+
+    #[test]
+    fn busy_wait_is_not_infinite_loop() {
+        let mut computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            // 10: TSF 1; 11: BR 10; 12: HLT
+            mem.data[0x10].set(0xE101);
+            mem.data[0x11].set(0xC010);
+            mem.data[0x12].set(0xF000);
+        }
+        let script = vec![ScriptEvent {
+            step: 20,
+            action: ScriptAction::Ready(Some(0x41)),
+        }];
+        computer.io_drivers[1] = DeviceDriver::Scripted(ScriptedDevice::new("test", script));
+        computer.registers.r_command_counter = 0x10;
+
+        let stop = StopConditions {
+            infinite_loop: true,
+            ..Default::default()
+        };
+        let (tracing, reason) = trace(&mut computer, 1000, &[], &stop);
+        assert_eq!(reason, StopReason::Halt);
+        assert!(tracing.records.len() > 20);
+
+        // сценарий кончился, готовность никто не выставит
+        computer.io_devices[1].ready = false;
+        computer.registers.r_command_counter = 0x10;
+        let (tracing, reason) = trace(&mut computer, 1000, &[], &stop);
+        assert_eq!(reason, StopReason::InfiniteLoop);
+        assert_eq!(tracing.records.len(), 3);
+    }
+
+    #[test]
+    fn recorder() {
+        let program = [
+            (0x10, 0xF200),
+            (0x11, 0xF800),
+            (0x12, 0x3020),
+            (0x13, 0xF000),
+        ];
+        let load = |computer: &mut Computer| {
+            let mut mem = computer.general_memory.borrow_mut();
+            // CLA; INC; MOV 20; HLT
+            for (address, value) in program.iter() {
+                mem.data[*address].set(*value);
+            }
+        };
+
+        let mut computer = Computer::new();
+        load(&mut computer);
+        computer.registers.r_command_counter = 0x10;
+        let expected = general_tracing(&mut computer, 100, &presets()[0].columns);
+
+        let mut computer = Computer::new();
+        load(&mut computer);
+        computer.registers.r_command_counter = 0x10;
+        let mut recorder = Recorder::new();
+        assert!(!recorder.enabled);
+        recorder.enabled = true;
+
+        // CLA по микрошагам записывается один раз, когда МПУ вернется на выборку
+        computer.registers.set_execute_by_tick(true);
+        recorder.step(&mut computer, Computer::micro_step);
+        assert!(recorder.records.is_empty());
+        while recorder.records.is_empty() {
+            recorder.step(&mut computer, Computer::micro_step);
+        }
+        assert_eq!(recorder.records.len(), 1);
+
+        for _ in 0..3 {
+            recorder.command_step(&mut computer);
+        }
+        assert_eq!(recorder.records.len(), 4);
+        assert_eq!(recorder.total, 4);
+
+        let recorded = Tracing::new(
+            TraceKind::General,
+            &presets()[0].columns,
+            recorder.records.iter().cloned().collect(),
+        );
+        assert_eq!(
+            recorded
+                .tracing
+                .iter()
+                .map(|r| r.sub_rows())
+                .collect::<Vec<_>>(),
+            expected
+                .tracing
+                .iter()
+                .map(|r| r.sub_rows())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(recorded.records[3].step, 3);
+        assert_eq!(recorded.records[3].mnemonic, "HLT");
+
+        recorder.enabled = false;
+        computer.registers.r_command_counter = 0x10;
+        recorder.command_step(&mut computer);
+        assert_eq!(recorder.records.len(), 4);
+    }
+
+    #[test]
+    fn reference() {
+        let load = || {
+            let computer = Computer::new();
+            {
+                let mut mem = computer.general_memory.borrow_mut();
+                // CLA; INC; MOV 20; HLT
+                for (i, v) in [0xF200, 0xF800, 0x3020, 0xF000].iter().enumerate() {
+                    mem.data[0x10 + i].set(*v);
+                }
+            }
+            let mut computer = computer;
+            computer.registers.r_command_counter = 0x10;
+            computer
+        };
+
+        // как сохраняет csv_converter
+        let csv = "Адрес\tКод\tА\tАдрес\tНовый код\t\n\
+            \"010\"\t\"F200\"\t\"0000\"\t\"\"\t\"\"\t\n\
+            \"011\"\t\"F800\"\t\"0001\"\t\"\"\t\"\"\t\n\
+            \"012\"\t\"3020\"\t\"0001\"\t\"020\"\t\"0001\"\t\n\
+            \"013\"\t\"F000\"\t\"0001\"\t\"\"\t\"\"\t";
+        let reference = Reference::parse(TraceKind::General, csv).unwrap();
+        assert_eq!(
+            reference.columns,
+            vec![
+                Column::Address,
+                Column::Code,
+                Column::Register(Register::Counter),
+                Column::ChangedAddress,
+                Column::ChangedValue
+            ]
+        );
+        assert_eq!(reference.rows.len(), 4);
+
+        let comparison = compare(
+            TraceKind::General,
+            &mut load(),
+            &reference,
+            &StopConditions::default(),
+        );
+        assert!(comparison.divergence.is_none());
+        assert_eq!(comparison.stop, StopReason::Halt);
+
+        // руками, через точку с запятой, без ведущих нулей и с ошибкой в А на третьей команде
+        let csv =
+            "адрес;код;А;Адрес;Новый код\n10;f200;0;;\n11;F800;1;;\n12;3020;2;20;1\n13;F000;1;;\n";
+        let reference = Reference::parse(TraceKind::General, csv).unwrap();
+        let divergence = compare(
+            TraceKind::General,
+            &mut load(),
+            &reference,
+            &StopConditions::default(),
+        )
+        .divergence
+        .unwrap();
+        assert_eq!(divergence.row, 2);
+        assert_eq!(divergence.column, Some(2));
+
+        // эталон кончился раньше, чем ЭВМ остановилась
+        let csv = "Адрес,Код\n010,F200\n011,F800\n";
+        let reference = Reference::parse(TraceKind::General, csv).unwrap();
+        let comparison = compare(
+            TraceKind::General,
+            &mut load(),
+            &reference,
+            &StopConditions::default(),
+        );
+        assert!(comparison.divergence.is_none());
+
+        // заголовки из таблиц старых версий
+        let reference = Reference::parse(TraceKind::General, "Адресс\tКод\tАдресс\n").unwrap();
+        assert_eq!(
+            reference.columns,
+            vec![Column::Address, Column::Code, Column::ChangedAddress]
+        );
+
+        assert!(Reference::parse(TraceKind::General, "Адрес\tЧто-то\n").is_err());
+
+        // строки не по шапке
+        let short = Reference::parse(TraceKind::General, "Адрес;Код;А\n010;F200;0\n\n011;F800\n");
+        assert_eq!(
+            short.err(),
+            Some("Строка 4: 2 колонок, а в шапке 3".to_string())
+        );
+        let long = Reference::parse(TraceKind::General, "Адрес,Код\n010,F200,0000\n");
+        assert!(long.is_err());
+    }
+
+    #[test]
+    fn micro_columns() {
+        let mut computer = Computer::new();
+        {
+            let mut mem = computer.general_memory.borrow_mut();
+            // CLA; HLT
+            mem.data[0x10].set(0xF200);
+            mem.data[0x11].set(0xF000);
+        }
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_micro_command_counter = 0x01;
+
+        let mut records = vec![];
+        run(
+            TraceKind::Micro,
+            &mut computer,
+            21,
+            &StopConditions::default(),
+            &mut |r| records.push(r),
+        );
+        let columns = [
+            Column::Address,
+            Column::Horizontal,
+            Column::Mnemonic,
+            Column::Fired,
+        ];
+        let tracing = Tracing::new(TraceKind::Micro, &columns, records);
+        let row = |idx: usize| tracing.tracing[idx].sub_rows().remove(0);
+
+        assert_eq!(
+            row(2),
+            vec![
+                "003",
+                "0080 0408",
+                "БР=0 + СК + 1; РД = *РА",
+                "Чтение памяти"
+            ]
+        );
+        assert_eq!(row(6)[3], "");
+        assert_eq!(row(9)[3], "Переход на 5E");
+        assert_eq!(row(15)[3], "N, Z");
+        assert_eq!(row(20)[2..], ["Остановочка.", "Останов"]);
+
+        assert!(!Column::all(TraceKind::General).contains(&Column::Fired));
+    }
+}
@@ -1,68 +1,185 @@
-use crate::model::{Computer, Memory, MemoryCell};
+use crate::model::{Computer, Memory, MemoryCell, MemorySearch};
 use crate::parse::{CommandInfo, Parser};
+use crate::settings::Settings;
 use crate::ui::gui::GuiState;
 use crate::ui::popup::{PopupMessage, PopupParseError};
 use crate::ui::window::Tool;
 use imgui::__core::cell::RefMut;
-use imgui::{InputTextFlags, Io, StyleColor, StyleVar, Ui};
+use imgui::{InputTextFlags, Io, ListClipper, MouseButton, StyleColor, StyleVar, Ui};
 use rfd::FileDialog;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
+use std::ops::Range;
 use std::rc::Rc;
+use std::time::Instant;
 
-#[derive(PartialEq, Eq)]
-enum CellRepresentation {
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CellRepresentation {
     Hex,
     Binary,
+    SignedDecimal,
+    UnsignedDecimal,
+    Text,
+    Instruction,
 }
 
 impl CellRepresentation {
-    fn title(&self) -> &'static str {
+    pub const ALL: [CellRepresentation; 6] = [
+        CellRepresentation::Hex,
+        CellRepresentation::Binary,
+        CellRepresentation::SignedDecimal,
+        CellRepresentation::UnsignedDecimal,
+        CellRepresentation::Text,
+        CellRepresentation::Instruction,
+    ];
+
+    /// Имя в настройках
+    pub fn key(&self) -> &'static str {
+        match self {
+            CellRepresentation::Hex => "hex",
+            CellRepresentation::Binary => "binary",
+            CellRepresentation::SignedDecimal => "signed",
+            CellRepresentation::UnsignedDecimal => "unsigned",
+            CellRepresentation::Text => "text",
+            CellRepresentation::Instruction => "instruction",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<CellRepresentation> {
+        CellRepresentation::ALL
+            .iter()
+            .copied()
+            .find(|r| r.key() == key)
+    }
+
+    pub fn title(&self) -> &'static str {
         match self {
             CellRepresentation::Hex => "Шестнадцетеричное",
             CellRepresentation::Binary => "Бинарное",
+            CellRepresentation::SignedDecimal => "Десятичное со знаком",
+            CellRepresentation::UnsignedDecimal => "Десятичное без знака",
+            CellRepresentation::Text => "Текст (два символа)",
+            CellRepresentation::Instruction => "Команда",
         }
     }
 
-    fn draw_hex(&self, cell: &mut MemoryCell, ui: &Ui) {
-        let cell = cell;
-        let mut data = format!("{:0>4X}", cell.get());
-        let width_t = ui.push_item_width(70.0);
-        if ui
-            .input_text("", &mut data)
-            .chars_hexadecimal(true)
-            .chars_noblank(true)
-            .build()
-        {
-            if let Ok(parsed) = u16::from_str_radix(&data, 16) {
-                cell.set(parsed)
+    /// Пункты меню для выбора представления
+    pub fn draw_selection(ui: &Ui, current: &mut CellRepresentation) {
+        for representation in CellRepresentation::ALL {
+            if ui
+                .menu_item_config(representation.title())
+                .selected(*current == representation)
+                .build()
+            {
+                *current = representation
             }
         }
-        width_t.end();
     }
-    fn draw_binary(&self, cell: &mut MemoryCell, ui: &Ui) {
-        let mut data = format!("{:0>16b}", cell.get());
-        let width_t = ui.push_item_width(160.0);
-        if ui
-            .input_text("", &mut data)
-            .chars_decimal(true)
-            .chars_noblank(true)
-            .build()
-        {
-            let data = data;
-            if let Ok(parsed) = u16::from_str_radix(&data, 2) {
-                cell.set(parsed)
-            }
+
+    fn width(&self, bits: u32) -> f32 {
+        match self {
+            CellRepresentation::Binary => bits as f32 * 10.0,
+            CellRepresentation::Text => 50.0,
+            CellRepresentation::Instruction => 200.0,
+            _ => 70.0,
         }
-        width_t.end()
     }
-    fn draw(&self, cell: &mut MemoryCell, ui: &Ui) {
+
+    /// Значение разрядности `bits` в этом представлении. Команды тут не разбираются,
+    /// для них значение показывается в шестнадцетеричном виде
+    pub fn format(&self, value: u32, bits: u32) -> String {
         match self {
-            CellRepresentation::Hex => self.draw_hex(cell, ui),
-            CellRepresentation::Binary => self.draw_binary(cell, ui),
+            CellRepresentation::Binary => format!("{:0>1$b}", value, bits as usize),
+            CellRepresentation::SignedDecimal => {
+                let shift = 32 - bits;
+                (((value << shift) as i32) >> shift).to_string()
+            }
+            CellRepresentation::UnsignedDecimal => value.to_string(),
+            CellRepresentation::Text => [(value >> 8) & 0xFF, value & 0xFF]
+                .iter()
+                .map(|c| match char::from_u32(*c) {
+                    Some(c) if c.is_ascii_graphic() || c == ' ' => c,
+                    _ => '·',
+                })
+                .collect(),
+            CellRepresentation::Hex | CellRepresentation::Instruction => {
+                format!("{:0>1$X}", value, bits.div_ceil(4) as usize)
+            }
         }
     }
+
+    /// Обратно к [`CellRepresentation::format`]. None, если строка не разбирается
+    /// или значение не влезает в `bits` разрядов
+    pub fn parse(&self, str: &str, bits: u32) -> Option<u32> {
+        let limit = 1i64 << bits;
+        let value = match self {
+            CellRepresentation::Binary => i64::from_str_radix(str, 2).ok()?,
+            CellRepresentation::SignedDecimal => {
+                let value = str.trim().parse::<i64>().ok()?;
+                if value < -limit / 2 || value >= limit / 2 {
+                    return None;
+                }
+                value & (limit - 1)
+            }
+            CellRepresentation::UnsignedDecimal => str.trim().parse::<i64>().ok()?,
+            CellRepresentation::Text => {
+                let chars: Vec<char> = str.chars().collect();
+                if chars.is_empty() || chars.len() > 2 || chars.iter().any(|c| !c.is_ascii()) {
+                    return None;
+                }
+                chars.iter().fold(0, |acc, c| (acc << 8) | *c as i64)
+            }
+            CellRepresentation::Hex | CellRepresentation::Instruction => {
+                i64::from_str_radix(str, 16).ok()?
+            }
+        };
+        (0..limit).contains(&value).then_some(value as u32)
+    }
+
+    /// Поле ввода значения разрядности `bits`. Возвращает новое значение, если его поменяли,
+    /// или введенную мнемонику с ошибкой, если ее не получилось разобрать.
+    /// `mnemonic` зовется только в представлении команды
+    pub fn input<I: CommandInfo, P: Parser<I>>(
+        &self,
+        ui: &Ui,
+        label: &str,
+        value: u32,
+        bits: u32,
+        parser: &P,
+        mnemonic: impl FnOnce() -> String,
+    ) -> Result<Option<u32>, (String, String)> {
+        let width_t = ui.push_item_width(self.width(bits));
+        let result = if *self == CellRepresentation::Instruction {
+            let mut content = mnemonic();
+            let supported = parser.supports_rev_parse();
+            if ui
+                .input_text(label, &mut content)
+                .read_only(!supported)
+                .enter_returns_true(true)
+                .build()
+            {
+                match parser.rev_parse(&content) {
+                    Ok(opcode) => Ok(Some(opcode as u32)),
+                    Err(msg) => Err((content, msg)),
+                }
+            } else {
+                Ok(None)
+            }
+        } else {
+            let mut content = self.format(value, bits);
+            let input = ui.input_text(label, &mut content).allow_tab_input(false);
+            let input = match self {
+                CellRepresentation::Hex => input.chars_hexadecimal(true).chars_noblank(true),
+                CellRepresentation::Text => input.enter_returns_true(true),
+                _ => input.chars_decimal(true).chars_noblank(true),
+            };
+            Ok(input.build().then(|| self.parse(&content, bits)).flatten())
+        };
+        width_t.end();
+        result
+    }
 }
 
 pub struct CellsTool<I: CommandInfo, P: Parser<I>, F>
@@ -72,6 +189,331 @@ where
     page: Rc<RefCell<Memory<I, P>>>,
     counter_register: F,
     representation: CellRepresentation,
+    breakpoints: bool,
+    operations: PageOperations,
+    heat: HeatMap,
+    /// Разобранные команды ячеек вместе со значением, из которого их разобрали
+    decoded: Vec<Option<(u16, I)>>,
+    /// Под каким именем память хранит настройки: "general" или "mc"
+    settings_key: Option<&'static str>,
+}
+
+/// Разобранная команда ячейки. Парсер зовется заново, только если значение поменялось
+fn decode<'a, I: CommandInfo, P: Parser<I>>(
+    cache: &'a mut [Option<(u16, I)>],
+    parser: &P,
+    idx: usize,
+    value: u16,
+) -> &'a I {
+    let entry = &mut cache[idx];
+    if !matches!(entry, Some((cached, _)) if *cached == value) {
+        *entry = Some((value, parser.parse(value)));
+    }
+    &entry.as_ref().unwrap().1
+}
+
+/// Когда ЭВМ дошла до какого цикла команды. Ячейки помнят только номер цикла, а в секунды
+/// для тепловой карты он переводится здесь, при отрисовке
+pub struct HeatClock {
+    /// Номер цикла и кадр, на котором его впервые увидели. Номера возрастают
+    frames: VecDeque<(u64, Instant)>,
+}
+
+impl HeatClock {
+    /// Дольше подсветка не горит
+    const HISTORY: f32 = 60.0;
+
+    pub fn new() -> HeatClock {
+        HeatClock {
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Вызывается каждый кадр
+    pub fn tick(&mut self, steps: u64) {
+        let now = Instant::now();
+        // после отката состояния ЭВМ будущее надо забыть
+        while matches!(self.frames.back(), Some((s, _)) if *s > steps) {
+            self.frames.pop_back();
+        }
+        if self.frames.back().map(|(s, _)| *s) != Some(steps) {
+            self.frames.push_back((steps, now));
+        }
+        while matches!(self.frames.get(1), Some((_, t)) if (now - *t).as_secs_f32() > Self::HISTORY)
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Сколько секунд назад ЭВМ была на цикле `step`. `None`, если давно или никогда
+    fn elapsed(&self, step: u64, now: Instant) -> Option<f32> {
+        let (first, _) = self.frames.front()?;
+        if step == 0 || step < *first {
+            return None;
+        }
+        let idx = self.frames.partition_point(|(s, _)| *s < step);
+        // если цикл еще не успели нарисовать, он был только что
+        Some(
+            self.frames
+                .get(idx)
+                .map_or(0.0, |(_, time)| (now - *time).as_secs_f32()),
+        )
+    }
+}
+
+/// Подсветка ячеек, которые ЭВМ недавно писала, читала или часто выполняет
+struct HeatMap {
+    enabled: bool,
+    /// За сколько секунд гаснет подсветка записи и чтения
+    fade: f32,
+}
+
+impl HeatMap {
+    const WRITTEN: [f32; 4] = [0.9, 0.0, 0.0, 0.7];
+    const READ: [f32; 4] = [0.0, 0.0, 0.9, 0.7];
+    const EXECUTED: [f32; 4] = [0.0, 0.7, 0.0, 0.7];
+
+    /// Цвет фона ячейки: запись красная, чтение синее, выполнение зеленое. Выполнение ярче у
+    /// тех ячеек, что выполнялись чаще остальных, запись и чтение со временем гаснут
+    fn color(
+        &self,
+        cell: &MemoryCell,
+        clock: &HeatClock,
+        now: Instant,
+        max_executions: u32,
+    ) -> Option<[f32; 4]> {
+        let freshness = |step: u64| match clock.elapsed(step, now) {
+            Some(elapsed) => (1.0 - elapsed / self.fade).max(0.0),
+            None => 0.0,
+        };
+        let written = freshness(cell.last_touched());
+        let read = freshness(cell.last_read());
+        let executed = if cell.executions() == 0 {
+            0.0
+        } else {
+            (cell.executions() as f32).ln_1p() / (max_executions as f32).ln_1p()
+        };
+
+        let strength = written.max(read).max(executed);
+        if strength <= 0.0 {
+            return None;
+        }
+        Some([
+            written * Self::WRITTEN[0],
+            executed * Self::EXECUTED[1],
+            read * Self::READ[2],
+            0.2 + strength * 0.5,
+        ])
+    }
+
+    fn draw_menu<I: CommandInfo, P: Parser<I>>(&mut self, ui: &Ui, page: &mut Memory<I, P>) {
+        if let Some(token) = ui.begin_menu("Тепловая карта") {
+            ui.checkbox("Показывать", &mut self.enabled);
+            let width_t = ui.push_item_width(120.0);
+            ui.slider("Затухание, с", 1.0, 60.0, &mut self.fade);
+            width_t.end();
+            ui.text_colored(Self::WRITTEN, "Запись");
+            ui.same_line();
+            ui.text_colored(Self::READ, "Чтение");
+            ui.same_line();
+            ui.text_colored(Self::EXECUTED, "Выполнение");
+            if ui.button("Сбросить") {
+                page.data.iter_mut().for_each(MemoryCell::reset_heat);
+            }
+            token.end()
+        }
+    }
+}
+
+/// Поиск и операции над диапазонами ячеек. Адреса и значения вводятся в хексе
+struct PageOperations {
+    search: MemorySearch,
+    query: String,
+    found: Vec<u16>,
+    current_found: usize,
+    address: String,
+    first: String,
+    last: String,
+    value: String,
+    destination: String,
+    relocate: bool,
+    /// Куда прокрутить на следующем кадре
+    scroll_to: Option<u16>,
+}
+
+impl PageOperations {
+    fn new() -> PageOperations {
+        PageOperations {
+            search: MemorySearch::Mnemonic,
+            query: String::new(),
+            found: vec![],
+            current_found: 0,
+            address: String::new(),
+            first: String::new(),
+            last: String::new(),
+            value: "0".to_string(),
+            destination: String::new(),
+            relocate: true,
+            scroll_to: None,
+        }
+    }
+
+    fn hex_input(ui: &Ui, label: &str, value: &mut String) {
+        let width_t = ui.push_item_width(60.0);
+        ui.input_text(label, value)
+            .chars_hexadecimal(true)
+            .chars_noblank(true)
+            .build();
+        width_t.end();
+    }
+
+    fn jump_to_found(&mut self, idx: usize) {
+        if !self.found.is_empty() {
+            self.current_found = idx % self.found.len();
+            self.scroll_to = Some(self.found[self.current_found]);
+        }
+    }
+
+    fn draw_search<I: CommandInfo, P: Parser<I>>(&mut self, ui: &Ui, page: &Memory<I, P>) {
+        for search in MemorySearch::ALL {
+            if ui.radio_button_bool(search.title(), self.search == search) {
+                self.search = search;
+            }
+        }
+        ui.separator();
+
+        let width_t = ui.push_item_width(150.0);
+        let entered = ui
+            .input_text("###query", &mut self.query)
+            .enter_returns_true(true)
+            .build();
+        width_t.end();
+        ui.same_line();
+        if ui.button("Найти") || entered {
+            self.found = page.find(self.search, &self.query);
+            self.jump_to_found(0);
+        }
+
+        if !self.found.is_empty() {
+            ui.text(format!(
+                "Найдено: {}, текущая {:0>3X}",
+                self.found.len(),
+                self.found[self.current_found]
+            ));
+            if ui.button("Предыдущая") {
+                self.jump_to_found(self.current_found + self.found.len() - 1);
+            }
+            ui.same_line();
+            if ui.button("Следующая") {
+                self.jump_to_found(self.current_found + 1);
+            }
+        } else if !self.query.is_empty() {
+            ui.text("Ничего не найдено");
+        }
+
+        ui.separator();
+        Self::hex_input(ui, "###address", &mut self.address);
+        ui.same_line();
+        if ui.button("Перейти к адресу") {
+            if let Ok(address) = u16::from_str_radix(&self.address, 16) {
+                self.scroll_to = Some(address.min(page.data.len() as u16 - 1));
+            }
+        }
+    }
+
+    fn parse_range(&self) -> Result<Range<u16>, String> {
+        let first = u16::from_str_radix(&self.first, 16)
+            .map_err(|_| format!("Не могу распарсить адрес \"{}\"", self.first))?;
+        let last = u16::from_str_radix(&self.last, 16)
+            .map_err(|_| format!("Не могу распарсить адрес \"{}\"", self.last))?;
+        if last < first {
+            return Err(format!("Конец диапазона {last:X} меньше начала {first:X}"));
+        }
+        let end = last
+            .checked_add(1)
+            .ok_or_else(|| format!("Адрес {last:X} за пределами памяти"))?;
+        Ok(first..end)
+    }
+
+    fn draw_operations<I: CommandInfo, P: Parser<I>>(
+        &mut self,
+        ui: &Ui,
+        page: &mut Memory<I, P>,
+        state: &mut GuiState,
+    ) {
+        Self::hex_input(ui, "С", &mut self.first);
+        ui.same_line();
+        Self::hex_input(ui, "по", &mut self.last);
+        ui.separator();
+
+        let mut result = None;
+
+        Self::hex_input(ui, "###value", &mut self.value);
+        ui.same_line();
+        if ui.button("Заполнить") {
+            result = Some(self.parse_range().and_then(|range| {
+                let value = u16::from_str_radix(&self.value, 16)
+                    .map_err(|_| format!("Не могу распарсить значение \"{}\"", self.value))?;
+                page.fill(range, value)
+            }));
+        }
+        ui.same_line();
+        if ui.button("Очистить") {
+            result = Some(self.parse_range().and_then(|range| page.fill(range, 0)));
+        }
+        ui.separator();
+
+        Self::hex_input(ui, "Куда", &mut self.destination);
+        ui.checkbox("Сдвигать адреса в командах", &mut self.relocate);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(
+                "Адреса, указывающие внутрь диапазона, сдвигаются вместе с ним. \
+                Остальная память не меняется: после перемещения покажу ячейки, которые \
+                ссылаются на старое место.\n\n\
+                Данные, похожие на адресные команды, тоже сдвинутся - проверьте результат",
+            );
+        }
+        for (title, cut) in [("Копировать", false), ("Переместить", true)] {
+            if ui.button(title) {
+                result = Some(self.parse_range().and_then(|range| {
+                    let to = u16::from_str_radix(&self.destination, 16).map_err(|_| {
+                        format!("Не могу распарсить адрес \"{}\"", self.destination)
+                    })?;
+                    page.copy(range.clone(), to, self.relocate, cut)?;
+                    self.scroll_to = Some(to);
+                    if cut {
+                        let target = to..to + (range.end - range.start);
+                        let references = page.references(&range, &target);
+                        if !references.is_empty() {
+                            let list = references
+                                .iter()
+                                .map(|addr| format!("{addr:0>3X}"))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            state.popup_manager.open(PopupMessage::new(
+                                "Проверьте ссылки",
+                                format!(
+                                    "Эти ячейки похожи на команды, которые ссылаются на старое место \
+                                    куска, и не были исправлены: {list}"
+                                ),
+                            ));
+                        }
+                    }
+                    Ok(())
+                }));
+            }
+            ui.same_line();
+        }
+        ui.new_line();
+
+        match result {
+            Some(Ok(())) => state.computer.on_memory_edit(),
+            Some(Err(msg)) => state
+                .popup_manager
+                .open(PopupMessage::new("Не получилось", msg)),
+            None => {}
+        }
+    }
 }
 
 impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> Tool for CellsTool<I, P, F>
@@ -80,6 +522,12 @@ where
 {
     fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
         self.draw_menu_bar(state, ui);
+        if let Some(key) = self.settings_key {
+            state.settings.set(
+                &format!("memory.{key}.representation"),
+                self.representation.key(),
+            );
+        }
 
         let s_token = ui.push_style_var(StyleVar::ChildBorderSize(0.0));
 
@@ -92,62 +540,145 @@ where
 
         let mut focused: Option<I> = None;
 
-        for (idx, cell) in data.iter_mut().enumerate() {
-            let token = ui.push_id(idx.to_string());
-            ui.text(format!("{:0>3X}", idx));
-            ui.same_line();
-            let t = if current_executed == idx as u16 {
-                if state.jump_requested {
-                    ui.set_scroll_here_y();
-                    state.jump_requested = false;
-                }
-                Some(ui.push_style_color(StyleColor::FrameBg, [1.0, 0.0, 0.0, 1.0]))
-            } else {
-                None
-            };
-            self.representation.draw(cell, ui);
-            if let Some(t) = t {
-                t.pop();
-            }
-            if ui.is_item_focused() {
-                focused = Some(parser.parse(cell.get()))
-            }
+        let now = Instant::now();
+        let max_executions = if self.heat.enabled {
+            data.iter().map(MemoryCell::executions).max().unwrap_or(0)
+        } else {
+            0
+        };
 
-            ui.same_line();
-            let command = parser.parse(cell.get());
+        // строки рисуются только видимые, так что прокручивать к ячейке приходится по высоте строки
+        let row_height = ui.frame_height_with_spacing();
+        let mut scroll_to = self.operations.scroll_to.take().map(|a| (a, 0.5));
+        if state.jump_requested {
+            scroll_to = Some((current_executed, 0.5));
+            state.jump_requested = false;
+        }
+        if let Some((address, ratio)) = scroll_to {
+            let y = address as f32 * row_height - ui.window_size()[1] * ratio;
+            ui.set_scroll_y(y.max(0.0));
+        }
 
-            if parser.supports_rev_parse() {
-                let mut content = String::with_capacity(50);
-                if next_rev_focused {
+        let mut clipper = ListClipper::new(data.len() as i32)
+            .items_height(row_height)
+            .begin(ui);
+        while clipper.step() {
+            for idx in clipper.display_start() as usize..clipper.display_end() as usize {
+                let cell = &mut data[idx];
+                let token = ui.push_id_usize(idx);
+                if self.breakpoints && state.computer.breakpoints.contains(&(idx as u16)) {
+                    ui.text_colored([1.0, 0.3, 0.3, 1.0], format!("{:0>3X}", idx));
+                } else if self.operations.found.contains(&(idx as u16)) {
+                    ui.text_colored([1.0, 0.9, 0.2, 1.0], format!("{:0>3X}", idx));
+                } else {
+                    ui.text(format!("{:0>3X}", idx));
+                }
+                if self.breakpoints && ui.is_item_clicked_with_button(MouseButton::Right) {
+                    let address = idx as u16;
+                    if !state.computer.breakpoints.remove(&address) {
+                        state.computer.breakpoints.insert(address);
+                    }
+                }
+                if ui.is_item_hovered() {
+                    let mut tooltip = vec![];
+                    if let Some(name) = &cell.name {
+                        tooltip.push(format!("Метка: {name}"));
+                    }
+                    if self.heat.enabled {
+                        tooltip.push(format!(
+                            "Чтений: {}, выполнений: {}",
+                            cell.reads(),
+                            cell.executions()
+                        ));
+                    }
+                    if self.breakpoints {
+                        tooltip
+                            .push("Правый клик - поставить или убрать точку останова".to_string());
+                    }
+                    if !tooltip.is_empty() {
+                        ui.tooltip_text(tooltip.join("\n"));
+                    }
+                }
+                ui.same_line();
+                let t = if current_executed == idx as u16 {
+                    Some(ui.push_style_color(StyleColor::FrameBg, [1.0, 0.0, 0.0, 1.0]))
+                } else if self.heat.enabled {
+                    self.heat
+                        .color(cell, &state.heat_clock, now, max_executions)
+                        .map(|color| ui.push_style_color(StyleColor::FrameBg, color))
+                } else {
+                    None
+                };
+                let instruction = self.representation == CellRepresentation::Instruction;
+                if instruction && next_rev_focused {
                     ui.set_keyboard_focus_here();
                     next_rev_focused = false
                 }
-                content.push_str(command.mnemonic().as_str());
-                if ui
-                    .input_text("###mnemonic", &mut content)
-                    .flags(InputTextFlags::empty())
-                    .enter_returns_true(true)
-                    .build()
-                {
-                    match parser.rev_parse(&content) {
-                        Ok(opcode) => {
-                            next_rev_focused = true;
-                            cell.set(opcode);
-                        }
-                        Err(msg) => state
-                            .popup_manager
-                            .open(PopupParseError::new(content.to_string(), msg.to_string())),
+                let value = cell.get();
+                let decoded = &mut self.decoded;
+                let result = self
+                    .representation
+                    .input(ui, "", value as u32, 16, &*parser, || {
+                        decode(decoded, &*parser, idx, value).mnemonic()
+                    });
+                match result {
+                    Ok(Some(value)) => {
+                        next_rev_focused = instruction;
+                        cell.set(value as u16);
+                        state.computer.on_memory_edit();
+                    }
+                    Ok(None) => {}
+                    Err((content, msg)) => {
+                        state.popup_manager.open(PopupParseError::new(content, msg))
                     }
                 }
-
+                if let Some(t) = t {
+                    t.pop();
+                }
                 if ui.is_item_focused() {
-                    focused = Some(command)
+                    focused = Some(parser.parse(cell.get()))
                 }
-            } else {
-                ui.text(command.mnemonic().as_str());
-            }
 
-            token.pop();
+                // в представлении команды мнемоника и так в поле ячейки
+                if !instruction {
+                    ui.same_line();
+                    let value = cell.get();
+                    let mut content = decode(&mut self.decoded, &*parser, idx, value).mnemonic();
+
+                    if parser.supports_rev_parse() {
+                        if next_rev_focused {
+                            ui.set_keyboard_focus_here();
+                            next_rev_focused = false
+                        }
+                        if ui
+                            .input_text("###mnemonic", &mut content)
+                            .flags(InputTextFlags::empty())
+                            .enter_returns_true(true)
+                            .build()
+                        {
+                            match parser.rev_parse(&content) {
+                                Ok(opcode) => {
+                                    next_rev_focused = true;
+                                    cell.set(opcode);
+                                    state.computer.on_memory_edit();
+                                }
+                                Err(msg) => state.popup_manager.open(PopupParseError::new(
+                                    content.to_string(),
+                                    msg.to_string(),
+                                )),
+                            }
+                        }
+
+                        if ui.is_item_focused() {
+                            focused = Some(parser.parse(value))
+                        }
+                    } else {
+                        ui.text(content);
+                    }
+                }
+
+                token.pop();
+            }
         }
 
         if let Some(focused) = focused {
@@ -164,10 +695,44 @@ where
 
 impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
     pub fn new(page: Rc<RefCell<Memory<I, P>>>, counter_register: F) -> CellsTool<I, P, F> {
+        let decoded = page.borrow().data.iter().map(|_| None).collect();
         CellsTool {
+            decoded,
+            settings_key: None,
             counter_register,
             page,
             representation: CellRepresentation::Hex,
+            breakpoints: false,
+            operations: PageOperations::new(),
+            heat: HeatMap {
+                enabled: false,
+                fade: 5.0,
+            },
+        }
+    }
+
+    /// Разрешает ставить точки останова правым кликом по адресу
+    pub fn with_breakpoints(mut self) -> CellsTool<I, P, F> {
+        self.breakpoints = true;
+        self
+    }
+
+    /// Восстанавливает представление ячеек из настроек и дальше запоминает его
+    /// и открытые файлы под именем `key`
+    pub fn with_settings(mut self, settings: &Settings, key: &'static str) -> CellsTool<I, P, F> {
+        if let Some(representation) = settings
+            .get(&format!("memory.{key}.representation"))
+            .and_then(CellRepresentation::from_key)
+        {
+            self.representation = representation;
+        }
+        self.settings_key = Some(key);
+        self
+    }
+
+    fn remember_file(&self, state: &mut GuiState, file: &str) {
+        if let Some(key) = self.settings_key {
+            state.remember_file(key, file);
         }
     }
 
@@ -176,6 +741,14 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             ui.menu("Опции", || {
                 self.draw_file_actions(state, ui);
                 self.draw_representation_selection(ui);
+                self.heat.draw_menu(ui, &mut self.page.borrow_mut());
+            });
+            ui.menu("Поиск", || {
+                self.operations.draw_search(ui, &self.page.borrow());
+            });
+            ui.menu("Диапазон", || {
+                self.operations
+                    .draw_operations(ui, &mut self.page.borrow_mut(), state);
             });
         })
     }
@@ -196,10 +769,13 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             .unwrap_or("".to_owned());
 
         match self.save_to_file(&filename) {
-            Ok(_) => state.popup_manager.open(PopupMessage::new(
-                "Успех",
-                format!("Успешно сохранил в файл {}", filename),
-            )),
+            Ok(_) => {
+                self.remember_file(state, &filename);
+                state.popup_manager.open(PopupMessage::new(
+                    "Успех",
+                    format!("Успешно сохранил в файл {}", filename),
+                ))
+            }
             Err(e) => state.popup_manager.open(PopupMessage::new(
                 "Провал",
                 format!("Не могу сохранить в файл \"{}\": {}", filename, e),
@@ -241,6 +817,20 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
     }
 
     fn choose_file(state: &mut GuiState, filter: Option<&str>) -> Option<File> {
+        let file_name = Self::choose_path(state, filter)?;
+        Self::open_file(state, &file_name)
+    }
+
+    fn open_file(state: &mut GuiState, file_name: &str) -> Option<File> {
+        File::open(file_name).map(Some).unwrap_or_else(|e| {
+            state
+                .popup_manager
+                .open(PopupMessage::new("Ошибка открытия файла", e.to_string()));
+            None
+        })
+    }
+
+    fn choose_path(state: &mut GuiState, filter: Option<&str>) -> Option<String> {
         let filter = filter.map(|f| [f]);
         let dialog = if filter.is_none() {
             FileDialog::new()
@@ -259,37 +849,48 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             .into_os_string()
             .into_string()
             .unwrap_or("".to_owned());
-
-        File::open(file_name).map(Some).unwrap_or_else(|e| {
-            state
-                .popup_manager
-                .open(PopupMessage::new("Ошибка открытия файла", e.to_string()));
-            None
-        })
+        Some(file_name)
     }
 
     fn on_load_from_file(&mut self, state: &mut GuiState) {
-        let Some(mut f) = Self::choose_file(state, Some("mm")) else { return };
-
-        let parse_result =
-            match crate::parse::file::parse_file(&mut f, &self.page.borrow().parser, 0xFF) {
-                Ok(result) => result,
-                Err(msg) => {
-                    state
-                        .popup_manager
-                        .open(PopupMessage::new("Ошибка во время парсинга", msg));
-                    return;
-                }
-            };
+        if let Some(file_name) = Self::choose_path(state, Some("mm")) {
+            self.load_from_file(state, &file_name);
+        }
+    }
+
+    fn load_from_file(&mut self, state: &mut GuiState, file_name: &str) {
+        let Some(mut f) = Self::open_file(state, file_name) else {
+            return;
+        };
+
+        let (parse_result, labels) = match crate::parse::file::parse_file_with_labels(
+            &mut f,
+            &self.page.borrow().parser,
+            0xFF,
+        ) {
+            Ok(result) => result,
+            Err(msg) => {
+                state
+                    .popup_manager
+                    .open(PopupMessage::new("Ошибка во время парсинга", msg));
+                return;
+            }
+        };
 
         let mem = &mut self.page.borrow_mut().data;
         for x in mem.iter_mut() {
-            x.set(0)
+            x.set(0);
+            x.name = None;
         }
 
         for (pos, v) in parse_result {
             mem.get_mut(pos as usize).unwrap().set(v);
         }
+        for (label, pos) in labels {
+            mem[pos as usize].name = Some(label);
+        }
+        state.computer.on_memory_edit();
+        self.remember_file(state, file_name);
     }
 
     fn load_bpc(&mut self, state: &mut GuiState) {
@@ -367,6 +968,7 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
                 .unwrap()
                 .set(v);
         }
+        state.computer.on_memory_edit();
 
         if let Some(pos) = start_pos {
             state.computer.registers.r_command_counter = pos;
@@ -384,27 +986,33 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             if ui.menu_item("Загрузить .bpc") {
                 self.load_bpc(state);
             }
+            self.draw_recent_files(state, ui);
 
             token.end()
         }
     }
 
+    fn draw_recent_files(&mut self, state: &mut GuiState, ui: &Ui) {
+        let Some(key) = self.settings_key else {
+            return;
+        };
+        let recent = state
+            .settings
+            .list(&format!("memory.{key}.recent"))
+            .to_vec();
+        if let Some(token) = ui.begin_menu_with_enabled("Недавние", !recent.is_empty()) {
+            for file in recent {
+                if ui.menu_item(&file) {
+                    self.load_from_file(state, &file);
+                }
+            }
+            token.end()
+        }
+    }
+
     fn draw_representation_selection(&mut self, ui: &Ui) {
         if let Some(token) = ui.begin_menu("Представление ячеек") {
-            if ui
-                .menu_item_config(CellRepresentation::Hex.title())
-                .selected(self.representation == CellRepresentation::Hex)
-                .build()
-            {
-                self.representation = CellRepresentation::Hex
-            }
-            if ui
-                .menu_item_config(CellRepresentation::Binary.title())
-                .selected(self.representation == CellRepresentation::Binary)
-                .build()
-            {
-                self.representation = CellRepresentation::Binary
-            }
+            CellRepresentation::draw_selection(ui, &mut self.representation);
             token.end()
         }
     }
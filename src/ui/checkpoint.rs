@@ -0,0 +1,160 @@
+use crate::model::{Computer, Memory, MemoryCheckpoint, MemoryDelta};
+use crate::parse::{CommandInfo, Parser};
+use crate::ui::gui::GuiState;
+use crate::ui::window::Tool;
+use imgui::{Io, StyleColor, TableFlags, Ui};
+
+/// Что поменялось в памяти с запомненного момента. Поменявшиеся ячейки можно вернуть как было
+pub struct CheckpointTool {
+    checkpoint: Option<MemoryCheckpoint>,
+}
+
+/// Что сделать с ячейкой после отрисовки таблицы
+enum Revert {
+    General(u16, u16),
+    Micro(u16, u16),
+    All,
+}
+
+impl CheckpointTool {
+    pub fn new() -> CheckpointTool {
+        CheckpointTool { checkpoint: None }
+    }
+
+    fn draw_menu(&mut self, ui: &Ui, computer: &Computer) {
+        ui.menu_bar(|| {
+            let token = ui.push_style_color(StyleColor::Button, [0.0, 0.0, 0.0, 0.0]);
+            if ui.button("Запомнить память") {
+                self.checkpoint = Some(computer.checkpoint());
+            }
+            if self.checkpoint.is_some() && ui.button("Забыть") {
+                self.checkpoint = None;
+            }
+            token.pop();
+
+            if let Some(checkpoint) = &self.checkpoint {
+                ui.text(format!(
+                    "Запомнено после {} команд, сейчас {}",
+                    checkpoint.step, computer.steps
+                ));
+            }
+        });
+    }
+
+    /// Строки одной страницы памяти. Возвращает адрес и старое значение ячейки,
+    /// которую попросили вернуть
+    fn draw_rows<I: CommandInfo, P: Parser<I>>(
+        ui: &Ui,
+        title: &str,
+        memory: &Memory<I, P>,
+        deltas: &[MemoryDelta],
+    ) -> Option<(u16, u16)> {
+        let mut revert = None;
+        for delta in deltas {
+            let _id = ui.push_id_usize(delta.address as usize);
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(title);
+            ui.table_next_column();
+            ui.text(format!("{:0>3X}", delta.address));
+            ui.table_next_column();
+            ui.text(format!("{:0>4X}", delta.before));
+            ui.table_next_column();
+            ui.text(format!("{:0>4X}", delta.after));
+            ui.table_next_column();
+            ui.text(memory.parser.parse(delta.before).mnemonic());
+            ui.table_next_column();
+            ui.text(memory.parser.parse(delta.after).mnemonic());
+            ui.table_next_column();
+            if ui.small_button("Вернуть") {
+                revert = Some((delta.address, delta.before));
+            }
+        }
+        revert
+    }
+
+    fn draw_table(&self, ui: &Ui, computer: &Computer) -> Option<Revert> {
+        let Some(checkpoint) = &self.checkpoint else {
+            ui.text_wrapped(
+                "Нажмите \"Запомнить память\", поработайте с программой и посмотрите здесь, \
+                какие ячейки основной памяти и МПУ поменялись",
+            );
+            return None;
+        };
+
+        let (general, micro) = checkpoint.diff(computer);
+        if general.is_empty() && micro.is_empty() {
+            ui.text("С запомненного момента память не менялась");
+            return None;
+        }
+
+        let mut revert = None;
+        if ui.button(format!("Вернуть все ({})", general.len() + micro.len())) {
+            revert = Some(Revert::All);
+        }
+
+        let flags = TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SCROLL_Y;
+        let Some(table) = ui.begin_table_with_flags("###memory_diff", 7, flags) else {
+            return revert;
+        };
+        ui.table_setup_scroll_freeze(0, 1);
+        for title in [
+            "Память",
+            "Адрес",
+            "Было",
+            "Стало",
+            "Мнемоника была",
+            "Мнемоника стала",
+            "",
+        ] {
+            ui.table_setup_column(title);
+        }
+        ui.table_headers_row();
+
+        let general_memory = computer.general_memory.borrow();
+        if let Some((address, value)) = Self::draw_rows(ui, "ОП", &general_memory, &general) {
+            revert = Some(Revert::General(address, value));
+        }
+        let mc_memory = computer.mc_memory.borrow();
+        let micro_id = ui.push_id("micro");
+        if let Some((address, value)) = Self::draw_rows(ui, "МПУ", &mc_memory, &micro) {
+            revert = Some(Revert::Micro(address, value));
+        }
+        micro_id.pop();
+
+        table.end();
+        revert
+    }
+}
+
+impl Tool for CheckpointTool {
+    fn draw(&mut self, ui: &Ui, _: &Io, state: &mut GuiState) {
+        self.draw_menu(ui, &state.computer);
+
+        let computer = &state.computer;
+        let revert = self.draw_table(ui, computer);
+        if revert.is_some() {
+            computer.on_memory_edit();
+        }
+        match revert {
+            Some(Revert::General(address, value)) => {
+                computer.general_memory.borrow_mut().data[address as usize].set(value)
+            }
+            Some(Revert::Micro(address, value)) => {
+                computer.mc_memory.borrow_mut().data[address as usize].set(value)
+            }
+            Some(Revert::All) => {
+                let checkpoint = self.checkpoint.as_ref().unwrap();
+                let (general, micro) = checkpoint.diff(computer);
+                for delta in general {
+                    computer.general_memory.borrow_mut().data[delta.address as usize]
+                        .set(delta.before);
+                }
+                for delta in micro {
+                    computer.mc_memory.borrow_mut().data[delta.address as usize].set(delta.before);
+                }
+            }
+            None => {}
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::model::Registers;
+use crate::model::{Computer, Engine, Registers};
 
 use crate::parse::mc::ExecutionResult;
 use crate::ui::gui::GuiState;
@@ -40,12 +40,39 @@ impl SmartControlsTool {
         }
     }
 
+    fn draw_engine_selection(&mut self, state: &mut GuiState, ui: &Ui) {
+        if let Some(token) = ui.begin_menu("Движок") {
+            let stock = state.computer.is_stock_microprogram();
+            for engine in [Engine::Microcode, Engine::Instruction] {
+                if ui
+                    .menu_item_config(engine.title())
+                    .selected(state.computer.engine == engine)
+                    .enabled(stock || engine == Engine::Microcode)
+                    .build()
+                {
+                    state.computer.engine = engine;
+                }
+            }
+            token.end();
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text(
+                "Микропрограмма - каждая команда честно исполняется микрокомандами из памяти МПУ.\n\
+                Команды напрямую - команды исполняются сразу целиком. Так намного быстрее, но в логе не будет микрокоманд.\n\
+                Работает только пока в памяти МПУ лежит стандартная микропрограмма. Если ее поменять, \
+                ЭВМ сама вернется к микропрограмме.",
+            );
+        }
+    }
+
     fn draw_control(&mut self, state: &mut GuiState, ui: &Ui) {
         if let Some(tok) = ui.begin_menu_bar() {
             if ui.menu_item("Сброс ЭВМ!") {
                 state.computer.reset_memory();
-                state.computer.registers = Registers::new()
+                state.computer.registers = Registers::new();
+                state.recorder.cancel();
             }
+            self.draw_engine_selection(state, ui);
             tok.end();
         }
 
@@ -57,7 +84,9 @@ impl SmartControlsTool {
             state.computer.registers.set_execute_by_tick(true);
             state.computer.registers.set_lever(false);
             state.computer.registers.set_program_mode(false);
-            state.computer.micro_step();
+            state
+                .recorder
+                .step(&mut state.computer, Computer::micro_step);
         }
 
         if ui.is_item_hovered() {
@@ -68,10 +97,7 @@ impl SmartControlsTool {
 
         if ui.button_with_size("Большой шаг", [w, h]) {
             self.make_history_entry(state);
-            state.computer.registers.set_execute_by_tick(false);
-            state.computer.registers.set_lever(false);
-            state.computer.registers.set_program_mode(false);
-            while !matches!(state.computer.micro_step(), ExecutionResult::Halted) {}
+            state.recorder.command_step(&mut state.computer);
         }
         if ui.is_item_hovered() {
             ui.tooltip_text("Устанавливает флаг \"Исполнение\" в 0\nУстанавливает флаг \"Состояние тумблера\" в 0.\nУстанавливается флаг \"Программа\" в 0.\nВыполняется полный цикл микрокоманд.\nГрубо говоря выполняется одна команда.")
@@ -82,6 +108,7 @@ impl SmartControlsTool {
         if ui.button_with_size("Назад", [w, h]) && !self.history.is_empty() {
             let entry = self.history.pop().unwrap();
             state.computer.registers = entry.registers;
+            state.recorder.cancel();
 
             self.auto_run = false
         }
@@ -92,6 +119,7 @@ impl SmartControlsTool {
         if ui.button_with_size("Пуск", [w, h]) {
             self.make_history_entry(state);
             state.computer.registers.r_micro_command_counter = 0xA8;
+            state.recorder.cancel();
             state.computer.registers.set_execute_by_tick(false);
             state.computer.registers.set_lever(true);
             state.computer.registers.set_program_mode(true);
@@ -121,8 +149,13 @@ impl SmartControlsTool {
             self.auto_run = true;
         }
         if self.auto_run {
-            for _ in 0..100 {
-                if matches!(state.computer.micro_step(), ExecutionResult::Halted) {
+            let budget = match state.computer.effective_engine() {
+                Engine::Microcode => 100,
+                Engine::Instruction => 10000,
+            };
+            for _ in 0..budget {
+                let result = state.recorder.step(&mut state.computer, Computer::step);
+                if matches!(result, ExecutionResult::Halted) {
                     if state.computer.registers.get_lever() {
                         state.popup_manager.open(PopupMessage::new(
                             "Остановочка",
@@ -133,6 +166,12 @@ impl SmartControlsTool {
                     self.auto_run = false;
                     break;
                 }
+                if state.computer.at_breakpoint() {
+                    state.computer.registers.set_lever(false);
+                    self.auto_run = false;
+                    state.jump_requested = true;
+                    break;
+                }
             }
         }
     }
@@ -0,0 +1,393 @@
+use crate::datapath::{active_paths, Path};
+use crate::model::{Computer, Register};
+use crate::parse::{CommandInfo, Parser};
+use crate::ui::gui::GuiState;
+use crate::ui::window::Tool;
+use imgui::{DrawListMut, Io, StyleColor, Ui};
+
+/// Размер схемы, по нему же скроллится окно
+const SIZE: [f32; 2] = [700.0, 380.0];
+const BOX: [f32; 2] = [70.0, 36.0];
+/// Верх и низ строки регистров
+const REGISTERS_TOP: f32 = 22.0;
+const REGISTERS_BOTTOM: f32 = REGISTERS_TOP + BOX[1];
+
+const INACTIVE: [f32; 4] = [0.35, 0.35, 0.35, 1.0];
+const INPUT: [f32; 4] = [0.3, 0.6, 1.0, 1.0];
+const OUTPUT: [f32; 4] = [0.3, 0.85, 0.3, 1.0];
+const MEMORY: [f32; 4] = [1.0, 0.6, 0.2, 1.0];
+const ALU: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+const FLAGS: [f32; 4] = [0.8, 0.4, 1.0, 1.0];
+
+/// Регистры в верхней строке схемы и их центры по горизонтали
+const REGISTERS: [(Register, f32); 6] = [
+    (Register::Address, 150.0),
+    (Register::Status, 250.0),
+    (Register::Counter, 340.0),
+    (Register::Command, 430.0),
+    (Register::Data, 520.0),
+    (Register::CommandCounter, 610.0),
+];
+
+fn register_x(register: Register) -> f32 {
+    REGISTERS
+        .iter()
+        .find(|(r, _)| *r == register)
+        .map(|(_, x)| *x)
+        .unwrap_or(0.0)
+}
+
+/// Ломаная связи на схеме и ее цвет, когда связь работает
+fn path_line(path: Path) -> (Vec<[f32; 2]>, [f32; 4]) {
+    let bottom = REGISTERS_BOTTOM;
+    match path {
+        Path::Left(r) => {
+            let x = register_x(r) - 12.0;
+            (
+                vec![[x, bottom], [x, 130.0], [360.0, 130.0], [360.0, 190.0]],
+                INPUT,
+            )
+        }
+        Path::Right(r) => {
+            let x = register_x(r) - 4.0;
+            (
+                vec![[x, bottom], [x, 150.0], [500.0, 150.0], [500.0, 190.0]],
+                INPUT,
+            )
+        }
+        Path::Alu => (vec![[430.0, 235.0], [430.0, 265.0]], ALU),
+        Path::Shift => {
+            let x = register_x(Register::Counter) + 4.0;
+            (
+                vec![
+                    [x, bottom],
+                    [x, 110.0],
+                    [640.0, 110.0],
+                    [640.0, 195.0],
+                    [640.0, 230.0],
+                    [640.0, 282.0],
+                    [470.0, 282.0],
+                ],
+                ALU,
+            )
+        }
+        Path::MemoryRead => (
+            vec![
+                [60.0, REGISTERS_TOP],
+                [60.0, 8.0],
+                [520.0, 8.0],
+                [520.0, REGISTERS_TOP],
+            ],
+            MEMORY,
+        ),
+        Path::MemoryWrite => (
+            vec![
+                [520.0, REGISTERS_TOP],
+                [520.0, 8.0],
+                [60.0, 8.0],
+                [60.0, REGISTERS_TOP],
+            ],
+            MEMORY,
+        ),
+        Path::Output(r) => {
+            let x = register_x(r) + 12.0;
+            (
+                vec![[430.0, 300.0], [430.0, 320.0], [x, 320.0], [x, bottom]],
+                OUTPUT,
+            )
+        }
+        Path::Carry => (vec![[390.0, 276.0], [330.0, 276.0]], FLAGS),
+        Path::Flags => (vec![[390.0, 290.0], [330.0, 290.0]], FLAGS),
+        Path::Io => {
+            let x = register_x(Register::Counter) + 22.0;
+            (vec![[100.0, 348.0], [x, 348.0], [x, bottom]], FLAGS)
+        }
+        Path::Check(..) => (vec![], FLAGS),
+    }
+}
+
+/// Все связи, какие есть на схеме. Рисуются серым, если микрокоманда их не использует
+fn all_paths() -> Vec<Path> {
+    let mut result = vec![
+        Path::Left(Register::Status),
+        Path::Left(Register::Counter),
+        Path::Left(Register::Command),
+        Path::Right(Register::Command),
+        Path::Right(Register::Data),
+        Path::Right(Register::CommandCounter),
+        Path::Alu,
+        Path::Shift,
+        Path::MemoryRead,
+        Path::Carry,
+        Path::Flags,
+        Path::Io,
+    ];
+    for register in [
+        Register::Address,
+        Register::Data,
+        Register::Command,
+        Register::CommandCounter,
+        Register::Counter,
+    ] {
+        result.push(Path::Output(register));
+    }
+    result
+}
+
+/// Операционная часть БЭВМ с подсветкой связей, которые работают в микрокоманде
+pub struct DatapathTool {
+    /// Показывать уже выполненную микрокоманду из РМК, а не следующую по СчМК
+    executed: bool,
+}
+
+impl DatapathTool {
+    pub fn new() -> DatapathTool {
+        DatapathTool { executed: false }
+    }
+
+    /// Адрес и код микрокоманды. None, если последнюю команду выполнил движок команд
+    /// и выполненной микрокоманды нет
+    fn opcode(&self, computer: &Computer) -> Option<(u8, u16)> {
+        if self.executed {
+            let address = computer.micro_command_address()?;
+            Some((address, computer.registers.r_micro_command))
+        } else {
+            let counter = computer.registers.r_micro_command_counter;
+            let memory = computer.mc_memory.borrow();
+            Some((counter, memory.data[counter as usize].get()))
+        }
+    }
+
+    fn draw_line(
+        draw_list: &DrawListMut,
+        at: impl Fn([f32; 2]) -> [f32; 2],
+        points: &[[f32; 2]],
+        color: [f32; 4],
+        active: bool,
+    ) {
+        let thickness = if active { 3.0 } else { 1.0 };
+        for pair in points.windows(2) {
+            draw_list
+                .add_line(at(pair[0]), at(pair[1]), color)
+                .thickness(thickness)
+                .build();
+        }
+        if !active || points.len() < 2 {
+            return;
+        }
+        let (from, to) = (points[points.len() - 2], points[points.len() - 1]);
+        let dir = [to[0] - from[0], to[1] - from[1]];
+        let len = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt().max(1.0);
+        let dir = [dir[0] / len * 9.0, dir[1] / len * 9.0];
+        draw_list
+            .add_triangle(
+                at(to),
+                at([to[0] - dir[0] - dir[1] / 2.0, to[1] - dir[1] + dir[0] / 2.0]),
+                at([to[0] - dir[0] + dir[1] / 2.0, to[1] - dir[1] - dir[0] / 2.0]),
+                color,
+            )
+            .filled(true)
+            .build();
+    }
+
+    fn draw_box(
+        ui: &Ui,
+        draw_list: &DrawListMut,
+        p1: [f32; 2],
+        p2: [f32; 2],
+        text: &str,
+        border: Option<[f32; 4]>,
+    ) {
+        draw_list
+            .add_rect(p1, p2, [0.15, 0.15, 0.2, 1.0])
+            .filled(true)
+            .rounding(3.0)
+            .build();
+        draw_list
+            .add_rect(p1, p2, border.unwrap_or(INACTIVE))
+            .thickness(if border.is_some() { 3.0 } else { 1.0 })
+            .rounding(3.0)
+            .build();
+        draw_list.add_text(
+            [p1[0] + 6.0, p1[1] + 3.0],
+            ui.style_color(StyleColor::Text),
+            text,
+        );
+    }
+
+    fn draw_scheme(ui: &Ui, computer: &Computer, active: &[Path]) {
+        let Some(token) = ui
+            .child_window("###datapath")
+            .horizontal_scrollbar(true)
+            .begin()
+        else {
+            return;
+        };
+        let origin = ui.cursor_screen_pos();
+        let at = |p: [f32; 2]| [origin[0] + p[0], origin[1] + p[1]];
+        let draw_list = ui.get_window_draw_list();
+
+        // сначала серые связи, поверх них работающие
+        let paths = all_paths();
+        for path in paths.iter().filter(|p| !active.contains(p)) {
+            let (points, _) = path_line(*path);
+            if *path != Path::MemoryRead || !active.contains(&Path::MemoryWrite) {
+                Self::draw_line(&draw_list, at, &points, INACTIVE, false);
+            }
+        }
+        let memory = active.contains(&Path::MemoryRead) || active.contains(&Path::MemoryWrite);
+        let address = vec![
+            [register_x(Register::Address) - BOX[0] / 2.0, 40.0],
+            [100.0, 40.0],
+        ];
+        Self::draw_line(&draw_list, at, &address, MEMORY, memory);
+        for path in active {
+            let (points, color) = path_line(*path);
+            Self::draw_line(&draw_list, at, &points, color, true);
+        }
+
+        let registers = &computer.registers;
+        let memory_border = if memory { Some(MEMORY) } else { None };
+        Self::draw_box(
+            ui,
+            &draw_list,
+            at([20.0, 22.0]),
+            at([100.0, 94.0]),
+            "ОП",
+            memory_border,
+        );
+        let io_border = if active.contains(&Path::Io) {
+            Some(FLAGS)
+        } else {
+            None
+        };
+        Self::draw_box(
+            ui,
+            &draw_list,
+            at([20.0, 330.0]),
+            at([100.0, 366.0]),
+            "ВУ",
+            io_border,
+        );
+
+        for (register, x) in REGISTERS {
+            let border = active.iter().find_map(|p| match p {
+                Path::Left(r) | Path::Right(r) if *r == register => Some(INPUT),
+                Path::Output(r) if *r == register => Some(OUTPUT),
+                Path::Check(r, _) if *r == register => Some(FLAGS),
+                Path::Shift if register == Register::Counter => Some(ALU),
+                Path::MemoryRead | Path::MemoryWrite if register == Register::Data => Some(MEMORY),
+                _ => None,
+            });
+            let text = format!("{}\n{:0>4X}", register.mnemonic(), register.get(computer));
+            let p1 = at([x - BOX[0] / 2.0, REGISTERS_TOP]);
+            let p2 = at([x + BOX[0] / 2.0, REGISTERS_BOTTOM]);
+            Self::draw_box(ui, &draw_list, p1, p2, &text, border);
+        }
+
+        // АЛУ - трапеция, входы сверху, выход снизу
+        let alu_color = if active.contains(&Path::Alu) {
+            ALU
+        } else {
+            INACTIVE
+        };
+        let alu = [
+            [300.0, 190.0],
+            [560.0, 190.0],
+            [520.0, 235.0],
+            [340.0, 235.0],
+            [300.0, 190.0],
+        ];
+        Self::draw_line(&draw_list, at, &alu, alu_color, false);
+        draw_list.add_text(at([415.0, 205.0]), ui.style_color(StyleColor::Text), "АЛУ");
+
+        let shift_border = if active.contains(&Path::Shift) {
+            Some(ALU)
+        } else {
+            None
+        };
+        Self::draw_box(
+            ui,
+            &draw_list,
+            at([600.0, 195.0]),
+            at([680.0, 230.0]),
+            "Сдвиг",
+            shift_border,
+        );
+
+        let buffer_border = if active.iter().any(|p| {
+            matches!(
+                p,
+                Path::Alu | Path::Shift | Path::Output(_) | Path::Carry | Path::Flags
+            )
+        }) {
+            Some(ALU)
+        } else {
+            None
+        };
+        let buffer = format!("БР\n{:0>5X}", registers.r_buffer);
+        Self::draw_box(
+            ui,
+            &draw_list,
+            at([390.0, 265.0]),
+            at([470.0, 300.0]),
+            &buffer,
+            buffer_border,
+        );
+
+        let flags_border = if active.contains(&Path::Carry) || active.contains(&Path::Flags) {
+            Some(FLAGS)
+        } else {
+            None
+        };
+        let flags = format!(
+            "C N Z\n{} {} {}",
+            registers.get_overflow() as u8,
+            registers.get_negative() as u8,
+            registers.get_null() as u8
+        );
+        Self::draw_box(
+            ui,
+            &draw_list,
+            at([230.0, 265.0]),
+            at([330.0, 300.0]),
+            &flags,
+            flags_border,
+        );
+
+        ui.dummy(SIZE);
+        token.end();
+    }
+}
+
+impl Tool for DatapathTool {
+    fn draw(&mut self, ui: &Ui, _: &Io, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            if ui.radio_button_bool("Следующая (СчМК)", !self.executed) {
+                self.executed = false;
+            }
+            if ui.radio_button_bool("Выполненная (РМК)", self.executed) {
+                self.executed = true;
+            }
+        });
+
+        let computer = &state.computer;
+        let Some((address, opcode)) = self.opcode(computer) else {
+            ui.text_disabled("Команда выполнена напрямую, без микрокоманд");
+            Self::draw_scheme(ui, computer, &[]);
+            return;
+        };
+        let mnemonic = computer.mc_memory.borrow().parser.parse(opcode).mnemonic();
+        ui.text(format!("{:0>2X}: {:0>4X}  {}", address, opcode, mnemonic));
+
+        let active = active_paths(opcode);
+        for path in &active {
+            if let Path::Check(register, bit) = path {
+                ui.same_line();
+                ui.text_colored(FLAGS, format!("проверка {}({})", register.mnemonic(), bit));
+            }
+        }
+
+        Self::draw_scheme(ui, computer, &active);
+    }
+}
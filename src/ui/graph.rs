@@ -0,0 +1,457 @@
+use crate::cfg::{Block, EdgeKind, Graph, MICRO_PHASES};
+use crate::model::Computer;
+use crate::ui::gui::GuiState;
+use crate::ui::popup::PopupMessage;
+use crate::ui::window::Tool;
+use imgui::{Io, MouseButton, StyleColor, Ui};
+use rfd::FileDialog;
+use std::collections::BTreeMap;
+
+const PADDING: f32 = 6.0;
+const GAP: [f32; 2] = [40.0, 50.0];
+
+/// Рисует граф блоков ярусами: точка входа сверху, дальше по числу переходов от нее.
+/// Если блоки разбиты на группы, каждая группа рисуется отдельной колонкой
+pub struct GraphView {
+    /// Левый верхний угол и размер каждого блока, относительно начала холста
+    boxes: BTreeMap<u16, ([f32; 2], [f32; 2])>,
+    /// Заголовки колонок групп
+    titles: Vec<([f32; 2], &'static str)>,
+    size: [f32; 2],
+    selected: Option<u16>,
+    /// Блок, к которому прокрутить при следующей отрисовке
+    scroll_to: Option<u16>,
+}
+
+impl GraphView {
+    pub fn new() -> GraphView {
+        GraphView {
+            boxes: BTreeMap::new(),
+            titles: vec![],
+            size: [0.0, 0.0],
+            selected: None,
+            scroll_to: None,
+        }
+    }
+
+    /// Прокручивает холст так, чтобы блок с этим адресом оказался посередине
+    pub fn scroll_to(&mut self, address: u16) {
+        self.scroll_to = Some(address);
+    }
+
+    /// Раскладывает блоки заново. Звать, когда граф поменялся
+    pub fn layout(&mut self, ui: &Ui, graph: &Graph) {
+        self.boxes.clear();
+        self.titles.clear();
+        self.size = [0.0, 0.0];
+        if graph.blocks.iter().any(|b| b.group.is_some()) {
+            self.layout_groups(ui, graph);
+        } else {
+            self.layout_ranks(ui, graph);
+        }
+        if let Some(selected) = self.selected {
+            if !self.boxes.contains_key(&selected) {
+                self.selected = None;
+            }
+        }
+    }
+
+    fn block_size(ui: &Ui, block: &Block) -> [f32; 2] {
+        let text = ui.calc_text_size(block.lines.join("\n"));
+        [text[0] + PADDING * 2.0, text[1] + PADDING * 2.0]
+    }
+
+    fn layout_groups(&mut self, ui: &Ui, graph: &Graph) {
+        let mut columns: Vec<(&'static str, Vec<&Block>)> = vec![];
+        for block in &graph.blocks {
+            let group = block.group.unwrap_or("Прочее");
+            match columns.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, blocks)) => blocks.push(block),
+                None => columns.push((group, vec![block])),
+            }
+        }
+
+        let title_height = ui.text_line_height_with_spacing();
+        let mut x = PADDING;
+        for (group, blocks) in columns {
+            let mut y = PADDING + title_height;
+            let mut width = ui.calc_text_size(group)[0];
+            for block in blocks {
+                let size = Self::block_size(ui, block);
+                self.boxes.insert(block.start, ([x, y], size));
+                y += size[1] + GAP[1] / 2.0;
+                width = width.max(size[0]);
+            }
+            self.titles.push(([x, PADDING], group));
+            x += width + GAP[0] * 2.0;
+            self.size[1] = self.size[1].max(y);
+        }
+        self.size[0] = x;
+    }
+
+    fn layout_ranks(&mut self, ui: &Ui, graph: &Graph) {
+        let ranks = graph.ranks();
+        let mut rows: Vec<Vec<u16>> = vec![];
+        for block in &graph.blocks {
+            // блоки, до которых от входа не дойти, уходят в последний ярус
+            let rank = ranks.get(&block.start).copied().unwrap_or(usize::MAX);
+            let rank = rank.min(ranks.values().max().map_or(0, |r| r + 1));
+            if rows.len() <= rank {
+                rows.resize(rank + 1, vec![]);
+            }
+            rows[rank].push(block.start);
+        }
+
+        let mut y = PADDING;
+        for row in rows {
+            let mut x = PADDING;
+            let mut height: f32 = 0.0;
+            for start in row {
+                let size = Self::block_size(ui, graph.block(start).unwrap());
+                self.boxes.insert(start, ([x, y], size));
+                x += size[0] + GAP[0];
+                height = height.max(size[1]);
+            }
+            self.size[0] = self.size[0].max(x);
+            y += height + GAP[1];
+        }
+        self.size[1] = y;
+    }
+
+    /// Рисует граф. `current` - адрес, блок с которым подсвечивается.
+    /// `color` дает цвет блока, если он не текущий
+    pub fn draw(
+        &mut self,
+        ui: &Ui,
+        graph: &Graph,
+        current: Option<u16>,
+        color: impl Fn(u16) -> [f32; 4],
+    ) {
+        let Some(token) = ui
+            .child_window("###graph")
+            .horizontal_scrollbar(true)
+            .begin()
+        else {
+            return;
+        };
+
+        if let Some(start) = self.scroll_to.take() {
+            if let Some((pos, size)) = self.boxes.get(&start) {
+                let window = ui.window_size();
+                ui.set_scroll_x(pos[0] + size[0] / 2.0 - window[0] * 0.5);
+                ui.set_scroll_y(pos[1] + size[1] / 2.0 - window[1] * 0.5);
+            }
+        }
+
+        let origin = ui.cursor_screen_pos();
+        let at = |p: [f32; 2]| [origin[0] + p[0], origin[1] + p[1]];
+        let current = current.and_then(|a| graph.block_at(a)).map(|b| b.start);
+        let draw_list = ui.get_window_draw_list();
+        for (pos, title) in &self.titles {
+            draw_list.add_text(at(*pos), [1.0, 0.9, 0.2, 1.0], title);
+        }
+
+        for edge in &graph.edges {
+            let (Some(from), Some(to)) = (self.boxes.get(&edge.from), self.boxes.get(&edge.to))
+            else {
+                continue;
+            };
+            let edge_color = match edge.kind {
+                EdgeKind::Next => [0.6, 0.6, 0.6, 1.0],
+                EdgeKind::Jump => [0.3, 0.6, 1.0, 1.0],
+                EdgeKind::Skip => [1.0, 0.6, 0.2, 1.0],
+                EdgeKind::Call => [0.3, 0.8, 0.3, 1.0],
+            };
+            let thickness = if self.selected == Some(edge.from) || self.selected == Some(edge.to) {
+                3.0
+            } else {
+                1.5
+            };
+
+            // вниз - от нижнего края к верхнему, назад или вбок - справа от блоков
+            let (p1, c1, c2, p2) = if to.0[1] > from.0[1] {
+                let p1 = at([from.0[0] + from.1[0] / 2.0, from.0[1] + from.1[1]]);
+                let p2 = at([to.0[0] + to.1[0] / 2.0, to.0[1]]);
+                (p1, [p1[0], p1[1] + 30.0], [p2[0], p2[1] - 30.0], p2)
+            } else {
+                let p1 = at([from.0[0] + from.1[0], from.0[1] + from.1[1] / 2.0]);
+                let p2 = at([to.0[0] + to.1[0], to.0[1] + to.1[1] / 2.0]);
+                let bend = p1[0].max(p2[0]) + GAP[0];
+                (p1, [bend, p1[1]], [bend, p2[1]], p2)
+            };
+            draw_list
+                .add_bezier_curve(p1, c1, c2, p2, edge_color)
+                .thickness(thickness)
+                .build();
+
+            // стрелка по направлению последнего отрезка кривой
+            let dir = [p2[0] - c2[0], p2[1] - c2[1]];
+            let len = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt().max(1.0);
+            let dir = [dir[0] / len * 8.0, dir[1] / len * 8.0];
+            draw_list
+                .add_triangle(
+                    p2,
+                    [p2[0] - dir[0] - dir[1] / 2.0, p2[1] - dir[1] + dir[0] / 2.0],
+                    [p2[0] - dir[0] + dir[1] / 2.0, p2[1] - dir[1] - dir[0] / 2.0],
+                    edge_color,
+                )
+                .filled(true)
+                .build();
+        }
+
+        let mouse = ui.io().mouse_pos;
+        let mut hovered = None;
+        for block in &graph.blocks {
+            let Some((pos, size)) = self.boxes.get(&block.start) else {
+                continue;
+            };
+            let p1 = at(*pos);
+            let p2 = at([pos[0] + size[0], pos[1] + size[1]]);
+            let fill = if current == Some(block.start) {
+                [0.8, 0.1, 0.1, 0.8]
+            } else {
+                color(block.start)
+            };
+            draw_list
+                .add_rect(p1, p2, fill)
+                .filled(true)
+                .rounding(3.0)
+                .build();
+            let border = if self.selected == Some(block.start) {
+                [1.0, 0.9, 0.2, 1.0]
+            } else {
+                [0.7, 0.7, 0.7, 1.0]
+            };
+            draw_list.add_rect(p1, p2, border).rounding(3.0).build();
+            draw_list.add_text(
+                [p1[0] + PADDING, p1[1] + PADDING],
+                ui.style_color(StyleColor::Text),
+                block.lines.join("\n"),
+            );
+
+            if (p1[0]..p2[0]).contains(&mouse[0]) && (p1[1]..p2[1]).contains(&mouse[1]) {
+                hovered = Some(block);
+            }
+        }
+
+        ui.dummy(self.size);
+
+        if let Some(block) = hovered {
+            if ui.is_window_hovered() {
+                if ui.is_mouse_clicked(MouseButton::Left) {
+                    self.selected = Some(block.start);
+                }
+                let incoming = graph.edges.iter().filter(|e| e.to == block.start).count();
+                let outgoing = graph.edges.iter().filter(|e| e.from == block.start).count();
+                let group = block.group.map(|g| format!("{g}\n")).unwrap_or_default();
+                ui.tooltip_text(format!(
+                    "{}Ячейки {:0>3X}-{:0>3X}\nВходов: {}, выходов: {}\nКлик - выделить связи",
+                    group, block.start, block.end, incoming, outgoing
+                ));
+            }
+        }
+
+        token.end();
+    }
+}
+
+/// Сохраняет граф в .dot через диалог выбора файла
+pub fn save_dot(state: &mut GuiState, graph: &Graph, name: &str) {
+    let Some(file) = FileDialog::new().add_filter("", &["dot"]).save_file() else {
+        return;
+    };
+    let filename = file.into_os_string().into_string().unwrap_or_default();
+
+    let message = match std::fs::write(&filename, graph.to_dot(name)) {
+        Ok(_) => PopupMessage::new(
+            "Успех",
+            format!("Сохранил граф в \"{filename}\". Картинку из него делает Graphviz: dot -Tpng"),
+        ),
+        Err(e) => PopupMessage::new(
+            "Ошибка записи",
+            format!("Не могу записать в файл \"{filename}\": {e}"),
+        ),
+    };
+    state.popup_manager.open(message);
+}
+
+/// Граф переходов программы в основной памяти
+pub struct ProgramGraphTool {
+    view: GraphView,
+    graph: Graph,
+    entry: String,
+    /// Где стоял СК, когда программу загрузили или поменяли, и номер этого изменения
+    /// (`Computer::memory_edits`). Отсюда строится граф, если начало не задано
+    loaded_entry: Option<(u16, u64)>,
+    /// Точка входа и код, по которому строился граф: адреса и значения ячеек его блоков.
+    /// От остальных ячеек граф не зависит, так что данные программы его не перестраивают
+    built_for: Option<(u16, Vec<(u16, u16)>)>,
+}
+
+impl ProgramGraphTool {
+    pub fn new() -> ProgramGraphTool {
+        ProgramGraphTool {
+            view: GraphView::new(),
+            graph: Graph::default(),
+            entry: String::new(),
+            loaded_entry: None,
+            built_for: None,
+        }
+    }
+
+    /// Начало графа: заданное вручную или СК на момент загрузки программы. Не текущий СК,
+    /// иначе граф перестраивался бы на каждой команде
+    fn entry(&mut self, computer: &Computer) -> u16 {
+        if let Ok(entry) = u16::from_str_radix(&self.entry, 16) {
+            return entry & 0x7FF;
+        }
+        let edits = computer.memory_edits();
+        match self.loaded_entry {
+            Some((entry, at)) if at == edits => entry,
+            _ => {
+                let entry = computer.registers.r_command_counter;
+                self.loaded_entry = Some((entry, edits));
+                entry
+            }
+        }
+    }
+
+    fn draw_menu(&mut self, ui: &Ui, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            let hint = match self.loaded_entry {
+                Some((entry, _)) => format!("{:0>3X}", entry),
+                None => "СК".to_string(),
+            };
+            let width_t = ui.push_item_width(50.0);
+            ui.input_text("Начало", &mut self.entry)
+                .chars_hexadecimal(true)
+                .chars_noblank(true)
+                .hint(hint)
+                .build();
+            width_t.end();
+            if ui.is_item_hovered() {
+                ui.tooltip_text(
+                    "С какого адреса строить граф. Если пусто - с того, где стоял СК, \
+                    когда программу загрузили или поменяли",
+                );
+            }
+
+            let token = ui.push_style_color(StyleColor::Button, [0.0, 0.0, 0.0, 0.0]);
+            if ui.button("Сохранить DOT") {
+                save_dot(state, &self.graph, "program");
+            }
+            token.pop();
+
+            ui.text_colored([0.3, 0.6, 1.0, 1.0], "переход");
+            ui.text_colored([1.0, 0.6, 0.2, 1.0], "пропуск");
+            ui.text_colored([0.3, 0.8, 0.3, 1.0], "вызов");
+        });
+    }
+}
+
+impl Tool for ProgramGraphTool {
+    fn draw(&mut self, ui: &Ui, _: &Io, state: &mut GuiState) {
+        self.draw_menu(ui, state);
+
+        let counter = state.computer.registers.r_command_counter;
+        let entry = self.entry(&state.computer);
+        let memory = state.computer.general_memory.borrow();
+        let built = match &self.built_for {
+            Some((built_entry, code)) => {
+                *built_entry == entry
+                    && code
+                        .iter()
+                        .all(|(address, value)| memory.data[*address as usize].get() == *value)
+            }
+            None => false,
+        };
+        if !built {
+            self.graph = Graph::general(&memory, entry);
+            self.view.layout(ui, &self.graph);
+            let code = self
+                .graph
+                .blocks
+                .iter()
+                .flat_map(|b| b.start..=b.end)
+                .map(|address| (address, memory.data[address as usize].get()))
+                .collect();
+            self.built_for = Some((entry, code));
+        }
+        drop(memory);
+
+        self.view
+            .draw(ui, &self.graph, Some(counter), |_| [0.2, 0.2, 0.25, 1.0]);
+    }
+}
+
+/// Граф микропрограммы по частям: выборка, адрес, исполнение, прерывание
+pub struct MicroGraphTool {
+    view: GraphView,
+    graph: Graph,
+    built_for: Option<Vec<u16>>,
+    follow: bool,
+    last_counter: u8,
+}
+
+impl MicroGraphTool {
+    pub fn new() -> MicroGraphTool {
+        MicroGraphTool {
+            view: GraphView::new(),
+            graph: Graph::default(),
+            built_for: None,
+            follow: true,
+            last_counter: 0,
+        }
+    }
+
+    fn group_color(group: Option<&str>) -> [f32; 4] {
+        let idx = MICRO_PHASES
+            .iter()
+            .position(|(_, _, name)| Some(*name) == group);
+        match idx {
+            Some(0) => [0.15, 0.25, 0.4, 1.0],
+            Some(1) => [0.15, 0.35, 0.35, 1.0],
+            Some(2) => [0.2, 0.3, 0.15, 1.0],
+            Some(3) => [0.3, 0.3, 0.15, 1.0],
+            Some(4) => [0.35, 0.2, 0.15, 1.0],
+            _ => [0.2, 0.2, 0.25, 1.0],
+        }
+    }
+}
+
+impl Tool for MicroGraphTool {
+    fn draw(&mut self, ui: &Ui, _: &Io, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            ui.checkbox("Следить за СчМК", &mut self.follow);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Прокручивать граф к текущей микрокоманде, когда СчМК меняется");
+            }
+            let token = ui.push_style_color(StyleColor::Button, [0.0, 0.0, 0.0, 0.0]);
+            if ui.button("Сохранить DOT") {
+                save_dot(state, &self.graph, "microprogram");
+            }
+            token.pop();
+        });
+
+        let memory = state.computer.mc_memory.borrow();
+        if !matches!(&self.built_for, Some(values) if memory.same_values(values)) {
+            self.graph = Graph::micro(&memory);
+            self.view.layout(ui, &self.graph);
+            self.built_for = Some(memory.values());
+        }
+        drop(memory);
+
+        let counter = state.computer.registers.r_micro_command_counter;
+        if self.follow && counter != self.last_counter {
+            if let Some(block) = self.graph.block_at(counter as u16) {
+                self.view.scroll_to(block.start);
+            }
+        }
+        self.last_counter = counter;
+
+        let graph = &self.graph;
+        self.view.draw(ui, graph, Some(counter as u16), |start| {
+            Self::group_color(graph.block(start).and_then(|b| b.group))
+        });
+    }
+}
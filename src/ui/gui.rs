@@ -11,24 +11,18 @@ use sdl2::video::Window as SDLWindow;
 
 use crate::model::Computer;
 use crate::parse::CommandInfo;
-use crate::ui::cells::CellsTool;
-use crate::ui::controls::SmartControlsTool;
-use crate::ui::help::HelpTool;
-use crate::ui::highlight::CommandHighlightTool;
-use crate::ui::io::IOTool;
+use crate::settings::Settings;
+use crate::trace::Recorder;
+use crate::ui::cells::HeatClock;
 use crate::ui::layout::LayoutTool;
-use crate::ui::log::LogTool;
+use crate::ui::panels::{LayoutPreset, LayoutRequest, Panels};
 use crate::ui::popup::Popup;
-use crate::ui::registers::RegistersTool;
-use crate::ui::status::StatusTool;
-use crate::ui::window::{Tool, WindowTool};
+use crate::ui::window::{SharedTool, Tool, WindowTool};
 
 use self::imgui::sys::ImGuiKey_Backspace;
 use self::imgui::{Context, FontConfig, FontGlyphRanges, FontId, FontSource, Io};
 use self::sdl2::keyboard::Scancode;
 
-use crate::ui::tracing::TraceTool;
-
 pub struct PopupManager {
     popup_delayed: Vec<Box<dyn Popup>>,
 }
@@ -58,26 +52,53 @@ pub struct GuiState {
     pub popup_manager: PopupManager,
     pub current_command: Option<Box<dyn CommandInfo>>,
     pub jump_requested: bool,
+    pub recorder: Recorder,
+    pub heat_clock: HeatClock,
+    pub layout_requests: Vec<LayoutRequest>,
+    /// Настройки, которые сохраняются при выходе. Инструменты пишут сюда свои
+    pub settings: Settings,
 }
 
 impl GuiState {
-    pub fn new(computer: Computer) -> GuiState {
+    pub fn new(computer: Computer, settings: Settings) -> GuiState {
         GuiState {
             editor_enabled: false,
-            theme_requested: None,
-            last_file_general: None,
-            last_file_mc: None,
+            theme_requested: settings.get("theme").and_then(Theme::from_key),
+            last_file_general: settings.get("memory.general.last_file").map(str::to_string),
+            last_file_mc: settings.get("memory.mc.last_file").map(str::to_string),
             computer,
             popup_manager: PopupManager::new(),
             current_command: None,
             jump_requested: false,
+            recorder: Recorder::new(),
+            heat_clock: HeatClock::new(),
+            layout_requests: vec![],
+            settings,
+        }
+    }
+
+    /// Запоминает файл, загруженный в память или сохраненный из нее: он становится последним
+    /// для этой памяти и первым в списке недавних. `memory` - "general" или "mc"
+    pub fn remember_file(&mut self, memory: &str, file: &str) {
+        match memory {
+            "general" => self.last_file_general = Some(file.to_string()),
+            "mc" => self.last_file_mc = Some(file.to_string()),
+            _ => {}
         }
+        self.settings
+            .set(&format!("memory.{memory}.last_file"), file);
+        self.settings
+            .push_recent(&format!("memory.{memory}.recent"), file);
     }
 }
 
 pub struct Gui {
     popup: Option<Box<dyn Popup>>,
     content: LayoutTool,
+    panels: Panels,
+    preset: LayoutPreset,
+    /// Открепленные панели, каждая в своем окне
+    floating: Vec<(&'static str, SharedTool)>,
     state: GuiState,
 }
 
@@ -87,145 +108,40 @@ pub enum Theme {
     Classic,
 }
 
+impl Theme {
+    fn key(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Classic => "classic",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Theme> {
+        match key {
+            "dark" => Some(Theme::Dark),
+            "light" => Some(Theme::Light),
+            "classic" => Some(Theme::Classic),
+            _ => None,
+        }
+    }
+}
+
 impl Gui {
     pub fn new(computer: Computer) -> Gui {
+        let settings = Settings::load();
+        let panels = Panels::new(&computer, &settings);
+        let preset = settings
+            .get("layout.preset")
+            .and_then(LayoutPreset::from_key)
+            .unwrap_or(LayoutPreset::Assembly);
         Gui {
             popup: None,
-            content: LayoutTool::new_vertical("root")
-                .append(
-                    -210.,
-                    LayoutTool::new_horizontal("main")
-                        .append(
-                            250.,
-                            WindowTool::new("mem")
-                                .append(
-                                    "Основная память",
-                                    CellsTool::new(computer.general_memory.clone(), |c| {
-                                        c.registers.r_command_counter
-                                    }),
-                                )
-                                .append(
-                                    "Память МПУ",
-                                    CellsTool::new(computer.mc_memory.clone(), |c| {
-                                        c.registers.r_micro_command_counter as u16
-                                    }),
-                                ),
-                        )
-                        .append(
-                            0.,
-                            LayoutTool::new_vertical("right")
-                                .append(
-                                    250.,
-                                    WindowTool::single_tool(
-                                        0,
-                                        250,
-                                        "Состояние ЭВМ",
-                                        LayoutTool::new_horizontal("regandstat")
-                                            .append(
-                                                300.,
-                                                WindowTool::single_tool(
-                                                    300,
-                                                    0,
-                                                    "Регистры",
-                                                    RegistersTool::new(),
-                                                ),
-                                            )
-                                            .append(
-                                                0.,
-                                                WindowTool::single_tool(
-                                                    0,
-                                                    0,
-                                                    "Разбор регистра статуса (РС)",
-                                                    StatusTool::new(),
-                                                ),
-                                            ),
-                                    ),
-                                )
-                                .append(
-                                    0.,
-                                    LayoutTool::new_horizontal("middle")
-                                        .append(
-                                            335.,
-                                            WindowTool::single_tool(
-                                                315,
-                                                0,
-                                                "Панель управления",
-                                                LayoutTool::new_vertical("execandio")
-                                                    .append(
-                                                        135.,
-                                                        WindowTool::single_tool(
-                                                            0,
-                                                            135,
-                                                            "Управление исполнением",
-                                                            SmartControlsTool::new(),
-                                                        ),
-                                                    )
-                                                    .append(
-                                                        0.,
-                                                        WindowTool::single_tool(
-                                                            0,
-                                                            0,
-                                                            "Внешние устройства",
-                                                            IOTool::new(),
-                                                        ),
-                                                    ),
-                                            )
-                                            .append("Таблица трассировки", TraceTool::new()),
-                                        )
-                                        .append(
-                                            350.,
-                                            LayoutTool::new_vertical("infoandload")
-                                                .append(
-                                                    0.,
-                                                    WindowTool::single_tool(
-                                                        0,
-                                                        0,
-                                                        "Информация о команде",
-                                                        CommandHighlightTool::new(),
-                                                    ),
-                                                )
-                                                .size(315, 0),
-                                        )
-                                        .append(
-                                            0.,
-                                            WindowTool::new("help")
-                                                .append(
-                                                    "Прелюдия",
-                                                    HelpTool::new(include_str!(
-                                                        "../help/prelude.txt"
-                                                    )),
-                                                )
-                                                .append(
-                                                    "Синтаксис",
-                                                    HelpTool::new(include_str!("../help/file.txt")),
-                                                )
-                                                .append(
-                                                    "Шпора",
-                                                    HelpTool::new(include_str!(
-                                                        "../help/cheatsheet.txt"
-                                                    )),
-                                                )
-                                                .append(
-                                                    "Нотация",
-                                                    HelpTool::new(include_str!(
-                                                        "../help/notation.txt"
-                                                    )),
-                                                )
-                                                .append(
-                                                    "Да как остановить епт",
-                                                    HelpTool::new(include_str!(
-                                                        "../help/run_and_stop.txt"
-                                                    )),
-                                                ),
-                                        ),
-                                ),
-                        ),
-                )
-                .append(
-                    200.,
-                    WindowTool::new("bottom").append("Логи", LogTool::new()),
-                ),
-            state: GuiState::new(computer),
+            content: panels.build(preset),
+            panels,
+            preset,
+            floating: vec![],
+            state: GuiState::new(computer, settings),
         }
     }
 
@@ -320,6 +236,7 @@ impl Gui {
                     Theme::Light => imgui.style_mut().use_light_colors(),
                     Theme::Classic => imgui.style_mut().use_classic_colors(),
                 };
+                self.state.settings.set("theme", theme.key());
                 self.state.theme_requested = None;
             };
 
@@ -330,6 +247,8 @@ impl Gui {
             last_frame = now;
             imgui.io_mut().delta_time = delta;
 
+            self.state.heat_clock.tick(self.state.computer.steps);
+
             let io = unsafe { &mut *(imgui.io_mut() as *mut Io) };
             let ui = imgui.frame();
 
@@ -355,6 +274,10 @@ impl Gui {
                 break;
             }
         }
+
+        if let Err(e) = self.state.settings.save() {
+            eprintln!("Не смог сохранить настройки: {e}");
+        }
     }
 
     fn init_font(imgui: &mut Context) -> FontId {
@@ -382,6 +305,9 @@ impl Gui {
         );
         window = window.no_decoration();
         window = window.movable(false);
+        window = window.menu_bar(true);
+        // иначе клик по главному окну прячет открепленные панели под него
+        window = window.bring_to_front_on_focus(false);
         if self.state.editor_enabled {
             let mut style = ui.clone_style();
             ui.window("Editor")
@@ -391,10 +317,150 @@ impl Gui {
         window = window.position([0.0, 0.0], Condition::Appearing);
 
         if let Some(token) = window.begin() {
+            ui.menu_bar(|| self.draw_layout_menu(ui));
             self.content.draw(ui, io, &mut self.state);
             token.end();
         }
+        self.draw_floating(ui, io);
+        self.apply_layout_requests();
 
         opened
     }
+
+    fn set_preset(&mut self, preset: LayoutPreset) {
+        self.content = self.panels.build(preset);
+        self.floating.clear();
+        self.preset = preset;
+        self.state.settings.set("layout.preset", preset.key());
+    }
+
+    /// Все группы вкладок: и в раскладке, и внутри открепленных панелей
+    fn for_each_group(&mut self, visitor: &mut dyn FnMut(&mut WindowTool)) {
+        self.content.visit_groups(visitor);
+        for (_, tool) in &self.floating {
+            tool.borrow_mut().visit_groups(visitor);
+        }
+    }
+
+    /// Панели, которые сейчас где-то видны
+    fn shown_panels(&mut self) -> Vec<&'static str> {
+        let mut shown: Vec<&'static str> = self.floating.iter().map(|(name, _)| *name).collect();
+        self.for_each_group(&mut |group| shown.extend(group.names()));
+        shown
+    }
+
+    /// Убирает панель оттуда, где она сейчас есть
+    fn take_panel(&mut self, panel: &str) -> Option<SharedTool> {
+        if let Some(idx) = self.floating.iter().position(|(name, _)| *name == panel) {
+            return Some(self.floating.remove(idx).1);
+        }
+        let mut taken = None;
+        self.for_each_group(&mut |group| {
+            if taken.is_none() {
+                taken = group.remove(panel);
+            }
+        });
+        taken
+    }
+
+    fn draw_layout_menu(&mut self, ui: &Ui) {
+        ui.menu("Раскладка", || {
+            for preset in LayoutPreset::ALL {
+                if ui
+                    .menu_item_config(preset.title())
+                    .selected(self.preset == preset)
+                    .build()
+                {
+                    self.set_preset(preset);
+                }
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Выбор раскладки заново расставляет панели и их размеры");
+            }
+            ui.separator();
+
+            let shown = self.shown_panels();
+            ui.menu("Панели", || {
+                for name in self.panels.names() {
+                    let visible = shown.contains(&name);
+                    if ui.menu_item_config(name).selected(visible).build() {
+                        if visible {
+                            self.take_panel(name);
+                        } else if let Some(tool) = self.panels.get(name) {
+                            self.floating.push((name, tool));
+                        }
+                    }
+                }
+            });
+        });
+        ui.text_disabled("(панели можно открепить и скрыть через меню их заголовков)");
+    }
+
+    fn draw_floating(&mut self, ui: &Ui, io: &Io) {
+        let mut groups = vec![];
+        self.content
+            .visit_groups(&mut |group| groups.push((group.id().to_string(), group.title())));
+
+        let mut dock = None;
+        let mut closed = None;
+        let state = &mut self.state;
+        for (idx, (name, tool)) in self.floating.iter().enumerate() {
+            let mut opened = true;
+            ui.window(name)
+                .opened(&mut opened)
+                .size([500.0, 400.0], Condition::FirstUseEver)
+                .menu_bar(true)
+                .build(|| {
+                    ui.menu_bar(|| {
+                        ui.menu("Прикрепить к", || {
+                            for (id, title) in &groups {
+                                if ui.menu_item(title) {
+                                    dock = Some((idx, id.clone()));
+                                }
+                            }
+                        });
+                    });
+                    tool.borrow_mut().draw(ui, io, state);
+                });
+            if !opened {
+                closed = Some(idx);
+            }
+        }
+
+        if let Some((idx, id)) = dock {
+            let (name, tool) = self.floating.remove(idx);
+            self.content.visit_groups(&mut |group| {
+                if group.id() == id {
+                    group.insert(name, tool.clone());
+                }
+            });
+        } else if let Some(idx) = closed {
+            self.floating.remove(idx);
+        }
+    }
+
+    fn apply_layout_requests(&mut self) {
+        for request in std::mem::take(&mut self.state.layout_requests) {
+            match request {
+                LayoutRequest::Undock { group, panel } => {
+                    let mut taken = None;
+                    self.for_each_group(&mut |g| {
+                        if g.id() == group && taken.is_none() {
+                            taken = g.remove(panel);
+                        }
+                    });
+                    if let Some(tool) = taken {
+                        self.floating.push((panel, tool));
+                    }
+                }
+                LayoutRequest::Hide { group, panel } => {
+                    self.for_each_group(&mut |g| {
+                        if g.id() == group {
+                            g.remove(panel);
+                        }
+                    });
+                }
+            }
+        }
+    }
 }
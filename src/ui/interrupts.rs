@@ -0,0 +1,199 @@
+use crate::model::{InterruptEvent, InterruptEventKind};
+use crate::ui::gui::GuiState;
+use crate::ui::relative_width;
+use crate::ui::window::Tool;
+use crate::utils::bit_registers::bit_at;
+use imgui::{Io, StyleColor, Ui};
+
+pub struct InterruptsTool;
+
+impl InterruptsTool {
+    pub fn new() -> InterruptsTool {
+        InterruptsTool {}
+    }
+
+    fn draw_devices(&self, ui: &Ui, state: &GuiState) {
+        let computer = &state.computer;
+        let requests = computer.interrupt_requests();
+
+        let allowed = computer.registers.get_allow_interupt();
+        ui.text(format!(
+            "Прерывания разрешены (РС[4]): {}",
+            if allowed { "да" } else { "нет" }
+        ));
+        ui.text(format!(
+            "Флаг прерывания (РС[5]): {}",
+            if computer.registers.get_interupt() { "1" } else { "0" }
+        ));
+
+        if requests.is_empty() {
+            ui.text("Никто не запрашивает прерывание");
+        } else {
+            ui.text(format!(
+                "Запрашивают прерывание: {}",
+                requests
+                    .iter()
+                    .map(|n| format!("ВУ-{n}"))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+            if !allowed {
+                ui.same_line();
+                ui.text_colored([1.0, 0.6, 0.0, 1.0], "(но прерывания запрещены)");
+            }
+        }
+
+        let masked = computer
+            .io_devices
+            .iter()
+            .enumerate()
+            .filter(|(num, cell)| cell.ready && !bit_at(computer.interrupt_mask, *num as u8))
+            .map(|(num, _)| format!("ВУ-{num}"))
+            .collect::<Vec<String>>();
+        if !masked.is_empty() {
+            ui.text(format!("Готовы, но замаскированы: {}", masked.join(", ")));
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Маску можно поменять на панели \"Внешние устройства\"");
+            }
+        }
+    }
+
+    fn draw_last(&self, ui: &Ui, state: &GuiState) {
+        let computer = &state.computer;
+
+        let last = computer
+            .interrupt_events()
+            .iter()
+            .rev()
+            .find(|e| matches!(e.kind, InterruptEventKind::Entry { .. }));
+
+        match last {
+            Some(InterruptEvent {
+                step,
+                kind: InterruptEventKind::Entry { return_address, .. },
+            }) => ui.text(format!(
+                "Последнее прерывание: на {} команде, адрес возврата {:0>3X}",
+                step, return_address
+            )),
+            _ => ui.text("Прерываний еще не было"),
+        }
+
+        ui.text(format!(
+            "Сохраненный адрес возврата (ячейка 000): {:0>4X}",
+            computer.general_memory.borrow().data[0].get()
+        ));
+
+        if let Some(address) = computer.pending_returns().last() {
+            ui.text(format!(
+                "Сейчас выполняется обработчик. Ждем возврата на {:0>3X}",
+                address
+            ));
+        }
+    }
+
+    fn draw_timeline(&self, ui: &Ui, state: &GuiState) {
+        let computer = &state.computer;
+        let events = computer.interrupt_events();
+
+        let first = events.first().map_or(0, |e| e.step);
+        let last = computer.steps.max(first + 1);
+        let width = relative_width(0.0, ui).max(50.0);
+        let height = 24.0;
+
+        let origin = ui.cursor_screen_pos();
+        let x = |step: u64| origin[0] + (step - first) as f32 / (last - first) as f32 * width;
+
+        let draw_list = ui.get_window_draw_list();
+        draw_list
+            .add_rect(
+                origin,
+                [origin[0] + width, origin[1] + height],
+                [0.5, 0.5, 0.5, 0.3],
+            )
+            .filled(true)
+            .build();
+
+        let mut entry: Option<u64> = None;
+        for event in events {
+            match event.kind {
+                InterruptEventKind::Entry { .. } => {
+                    entry = Some(event.step);
+                }
+                InterruptEventKind::Exit { .. } => {
+                    let from = entry.take().unwrap_or(first);
+                    draw_list
+                        .add_rect(
+                            [x(from), origin[1]],
+                            [x(event.step).max(x(from) + 2.0), origin[1] + height],
+                            [0.2, 0.6, 1.0, 0.7],
+                        )
+                        .filled(true)
+                        .build();
+                }
+            }
+        }
+        if let Some(from) = entry {
+            // обработчик еще не закончился
+            draw_list
+                .add_rect(
+                    [x(from), origin[1]],
+                    [x(last).max(x(from) + 2.0), origin[1] + height],
+                    [1.0, 0.4, 0.2, 0.7],
+                )
+                .filled(true)
+                .build();
+        }
+
+        ui.dummy([width, height]);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(format!(
+                "Команды с {} по {}\nСиним - обработчики прерываний\nОранжевым - обработчик, который еще не завершился",
+                first, last
+            ));
+        }
+
+        for event in events.iter().rev() {
+            match &event.kind {
+                InterruptEventKind::Entry {
+                    devices,
+                    return_address,
+                } => ui.text(format!(
+                    "{:>8}: вход в прерывание от {}, адрес возврата {:0>3X}",
+                    event.step,
+                    if devices.is_empty() {
+                        "неизвестно кого".to_string()
+                    } else {
+                        devices
+                            .iter()
+                            .map(|n| format!("ВУ-{n}"))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    },
+                    return_address
+                )),
+                InterruptEventKind::Exit { return_address } => ui.text(format!(
+                    "{:>8}: возврат из прерывания на {:0>3X}",
+                    event.step, return_address
+                )),
+            }
+        }
+    }
+}
+
+impl Tool for InterruptsTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            let token = ui.push_style_color(StyleColor::Button, [0.0, 0.0, 0.0, 0.0]);
+            if ui.button("Очистить") {
+                state.computer.clear_interrupt_events();
+            }
+            token.pop();
+        });
+
+        self.draw_devices(ui, state);
+        ui.separator();
+        self.draw_last(ui, state);
+        ui.separator();
+        self.draw_timeline(ui, state);
+    }
+}
@@ -1,11 +1,15 @@
+use crate::devices::{DeviceDriver, FileDevice, ScriptedDevice};
 use crate::ui::gui::GuiState;
+use crate::ui::popup::PopupMessage;
 use crate::ui::window::Tool;
+use crate::utils::bit_registers::{bit_at, set_bit_at};
 use imgui::sys::{
     igBeginTable, igEndTable, igTableNextColumn, igTableNextRow, ImGuiTableFlags_None,
     ImGuiTableRowFlags_None, ImVec2,
 };
 
 use imgui::{ImString, Io, Ui};
+use rfd::FileDialog;
 use std::os::raw::c_int;
 
 pub struct IOTool;
@@ -23,14 +27,23 @@ impl Tool for IOTool {
         unsafe {
             igBeginTable(
                 ImString::new("io_devices").as_ptr(),
-                3,
+                5,
                 ImGuiTableFlags_None as c_int,
                 ImVec2::zero(),
                 0.0,
             );
         }
 
-        for (id, cell) in &mut state.computer.io_devices.iter_mut().enumerate() {
+        let mut driver_requested: Option<(usize, DriverRequest)> = None;
+        let mut interrupt_mask = state.computer.interrupt_mask;
+
+        for (id, (cell, driver)) in state
+            .computer
+            .io_devices
+            .iter_mut()
+            .zip(state.computer.io_drivers.iter())
+            .enumerate()
+        {
             unsafe {
                 igTableNextRow(ImGuiTableRowFlags_None as c_int, 0.0);
                 igTableNextColumn();
@@ -54,6 +67,30 @@ impl Tool for IOTool {
             unsafe { igTableNextColumn() };
 
             ui.checkbox("Готов", &mut cell.ready);
+            unsafe { igTableNextColumn() };
+
+            let mut interrupt = bit_at(interrupt_mask, id as u8);
+            if ui.checkbox("Прер.", &mut interrupt) {
+                interrupt_mask = set_bit_at(interrupt_mask, id as u16, interrupt);
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Может ли это ВУ запрашивать прерывание, когда оно готово.");
+            }
+            unsafe { igTableNextColumn() };
+
+            if ui.button(driver.title()) {
+                ui.open_popup("driver");
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Откуда ВУ берет данные. Можно привязать его к файлу или сценарию.");
+            }
+            ui.popup("driver", || {
+                for (request, title) in DriverRequest::ALL {
+                    if ui.selectable(title) {
+                        driver_requested = Some((id, request));
+                    }
+                }
+            });
 
             id_tok.pop();
         }
@@ -64,12 +101,65 @@ impl Tool for IOTool {
 
         w_tok.end();
 
-        if state.computer.io_devices.iter().any(|a| a.ready) {
-            if state.computer.registers.get_allow_interupt() {
-                state.computer.registers.set_interrupt(true);
+        state.computer.interrupt_mask = interrupt_mask;
+
+        if let Some((id, request)) = driver_requested {
+            match request.make(&state.computer.io_drivers[id]) {
+                Ok(Some(driver)) => state.computer.io_drivers[id] = driver,
+                Ok(None) => {}
+                Err(e) => state
+                    .popup_manager
+                    .open(PopupMessage::new("Ошибка привязки ВУ", e)),
             }
-        } else {
-            state.computer.registers.set_interrupt(false);
         }
     }
 }
+
+#[derive(Copy, Clone)]
+enum DriverRequest {
+    Manual,
+    Input,
+    Output,
+    Script,
+}
+
+impl DriverRequest {
+    const ALL: [(DriverRequest, &'static str); 4] = [
+        (DriverRequest::Manual, "Вручную"),
+        (DriverRequest::Input, "Ввод из файла..."),
+        (DriverRequest::Output, "Вывод в файл..."),
+        (DriverRequest::Script, "Сценарий..."),
+    ];
+
+    fn make(&self, current: &DeviceDriver) -> Result<Option<DeviceDriver>, String> {
+        if let DriverRequest::Manual = self {
+            return Ok(Some(DeviceDriver::Manual));
+        }
+
+        let dialog = FileDialog::new();
+        let file = if let DriverRequest::Output = self {
+            dialog.save_file()
+        } else {
+            dialog.pick_file()
+        };
+        let Some(file) = file else {
+            return Ok(None);
+        };
+        let file = file.into_os_string().into_string().unwrap_or_default();
+
+        // ввод и вывод можно повесить на одно и то же ВУ
+        let device = match current {
+            DeviceDriver::File(f) => f.clone(),
+            _ => FileDevice::new(),
+        };
+
+        let driver = match self {
+            DriverRequest::Manual => unreachable!(),
+            DriverRequest::Input => DeviceDriver::File(device.with_input(&file)?),
+            DriverRequest::Output => DeviceDriver::File(device.with_output(&file)?),
+            DriverRequest::Script => DeviceDriver::Scripted(ScriptedDevice::from_file(&file)?),
+        };
+
+        Ok(Some(driver))
+    }
+}
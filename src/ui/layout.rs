@@ -1,5 +1,5 @@
 use crate::ui::gui::GuiState;
-use crate::ui::window::Tool;
+use crate::ui::window::{Tool, WindowTool};
 use crate::ui::{relative_height, relative_width};
 use imgui::{Io, Ui};
 
@@ -38,6 +38,12 @@ impl Tool for LayoutTool {
                 }
             });
     }
+
+    fn visit_groups(&mut self, visitor: &mut dyn FnMut(&mut WindowTool)) {
+        for container in &mut self.tools {
+            container.tool.visit_groups(visitor);
+        }
+    }
 }
 
 impl LayoutTool {
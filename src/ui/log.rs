@@ -1,23 +1,240 @@
+use crate::model::{Computer, LogCategory, LogEntry};
+use crate::parse::{CommandInfo, Parser};
+use crate::settings::Settings;
 use crate::ui::gui::GuiState;
+use crate::ui::popup::PopupMessage;
+use crate::ui::tracing::xml_escape;
 use crate::ui::window::Tool;
-use imgui::{Io, StyleColor, Ui};
+use imgui::{Io, ListClipper, StyleColor, Ui};
+use rfd::FileDialog;
+use std::collections::VecDeque;
+
+/// Строка лога на экране: заголовок команды или запись
+enum Line {
+    Header(String),
+    /// Номер записи за все время, см. `Computer::logged`
+    Entry(u64),
+}
+
+/// Фильтры, по которым строились строки: микрокоманды, категории и поиск в нижнем регистре.
+/// Если что-то поменялось, строки надо построить заново
+type Filter = (bool, Vec<LogCategory>, String);
 
 pub struct LogTool {
     show_micro: bool,
+    categories: Vec<LogCategory>,
+    search: String,
+    unlimited: bool,
+    limit: i32,
+    lines: VecDeque<Line>,
+    built_for: Option<Filter>,
+    /// До какой записи лога строки уже построены
+    built_to: u64,
+    last_step: Option<u64>,
     last_size: usize,
 }
 
 impl LogTool {
-    pub fn new() -> LogTool {
+    pub fn new(settings: &Settings) -> LogTool {
+        let categories = match settings.list("log.categories") {
+            [] => LogCategory::ALL.to_vec(),
+            keys => LogCategory::ALL
+                .iter()
+                .copied()
+                .filter(|c| keys.iter().any(|k| k == c.key()))
+                .collect(),
+        };
         LogTool {
-            show_micro: false,
+            show_micro: settings.parsed("log.show_micro").unwrap_or(false),
+            categories,
+            search: settings.get("log.search").unwrap_or_default().to_string(),
+            unlimited: settings.parsed("log.unlimited").unwrap_or(false),
+            limit: settings.parsed("log.limit").unwrap_or(100_000),
+            lines: VecDeque::new(),
+            built_for: None,
+            built_to: 0,
+            last_step: None,
             last_size: 0,
         }
     }
-}
 
-impl Tool for LogTool {
-    fn draw(&mut self, ui: &Ui, _io: &Io, gui: &mut GuiState) {
+    fn visible(filter: &Filter, entry: &LogEntry) -> bool {
+        let (show_micro, categories, search) = filter;
+        (!entry.micro_command || *show_micro)
+            && categories.contains(&entry.category)
+            && (search.is_empty() || entry.info.to_lowercase().contains(search))
+    }
+
+    /// Запись по ее номеру за все время
+    fn entry(computer: &Computer, id: u64) -> &LogEntry {
+        let first = computer.logged() - computer.logs().len() as u64;
+        &computer.logs()[(id - first) as usize]
+    }
+
+    fn header(computer: &Computer, entry: &LogEntry) -> Line {
+        let memory = computer.general_memory.borrow();
+        Line::Header(format!(
+            "Команда {}, адрес {:0>3X}: {}",
+            entry.step,
+            entry.command_address,
+            memory.parser.parse(entry.command).mnemonic()
+        ))
+    }
+
+    /// Группирует видимые записи по командам, во время которых они были сделаны.
+    ///
+    /// Строки достраиваются: добавляются только новые записи и убираются выкинутые из начала
+    /// лога. Заново все строится, только если поменялись фильтры или лог откатили
+    fn build_lines(&mut self, computer: &Computer) {
+        let logs = computer.logs();
+        let logged = computer.logged();
+        let first = logged - logs.len() as u64;
+
+        let filter = (
+            self.show_micro,
+            self.categories.clone(),
+            self.search.to_lowercase(),
+        );
+        if self.built_for.as_ref() != Some(&filter) || logged < self.built_to {
+            self.lines.clear();
+            self.built_to = first;
+            self.last_step = None;
+            self.built_for = Some(filter);
+        }
+
+        // записи, выкинутые из начала лога
+        let fresh = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, Line::Entry(id) if *id >= first))
+            .unwrap_or(self.lines.len());
+        if fresh > 0 {
+            self.lines.drain(..fresh);
+            match self.lines.front() {
+                Some(Line::Entry(id)) => {
+                    let header = Self::header(computer, Self::entry(computer, *id));
+                    self.lines.push_front(header);
+                }
+                Some(Line::Header(_)) => {}
+                None => self.last_step = None,
+            }
+        }
+
+        let filter = self.built_for.as_ref().unwrap();
+        for id in self.built_to.max(first)..logged {
+            let entry = &logs[(id - first) as usize];
+            if !Self::visible(filter, entry) {
+                continue;
+            }
+            if self.last_step != Some(entry.step) {
+                self.lines.push_back(Self::header(computer, entry));
+                self.last_step = Some(entry.step);
+            }
+            self.lines.push_back(Line::Entry(id));
+        }
+        self.built_to = logged;
+    }
+
+    fn entry_text(entry: &LogEntry) -> String {
+        format!(
+            "СК: {:0>3X}, СчМК: {:0>2X}, {}",
+            entry.command_counter, entry.micro_counter, entry.info
+        )
+    }
+
+    fn to_text(&self, computer: &Computer) -> String {
+        let mut result = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Header(header) => {
+                    result.push('\n');
+                    result.push_str(header);
+                }
+                Line::Entry(idx) => {
+                    result.push_str("\n    ");
+                    result.push_str(&Self::entry_text(Self::entry(computer, *idx)));
+                }
+            }
+        }
+        result.push('\n');
+        result
+    }
+
+    fn to_html(&self, computer: &Computer) -> String {
+        let mut content = String::new();
+        let mut opened = false;
+        for line in &self.lines {
+            match line {
+                Line::Header(header) => {
+                    if opened {
+                        content.push_str("</ul>\n");
+                    }
+                    content.push_str(&format!("<h3>{}</h3>\n<ul>\n", xml_escape(header)));
+                    opened = true;
+                }
+                Line::Entry(idx) => {
+                    let entry = Self::entry(computer, *idx);
+                    content.push_str(&format!(
+                        "\t<li title=\"{}\"{}>{}</li>\n",
+                        entry.category.title(),
+                        if entry.micro_command {
+                            " class=micro"
+                        } else {
+                            ""
+                        },
+                        xml_escape(&Self::entry_text(entry))
+                    ));
+                }
+            }
+        }
+        if opened {
+            content.push_str("</ul>\n");
+        }
+
+        format!(
+            "<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <style>h3 {{ margin-bottom: 0 }} .micro {{ color: gray }}</style>\n\
+            </head>\n<body>\n{content}</body>\n</html>\n"
+        )
+    }
+
+    fn save(&self, state: &mut GuiState, postfix: &str, content: String) {
+        let Some(file) = FileDialog::new().add_filter("", &[postfix]).save_file() else {
+            return;
+        };
+        let filename = file.into_os_string().into_string().unwrap_or_default();
+
+        let message = match std::fs::write(&filename, content) {
+            Ok(_) => PopupMessage::new("Успех", format!("Сохранил лог в файл \"{filename}\"")),
+            Err(e) => PopupMessage::new(
+                "Ошибка записи",
+                format!("Не могу записать в файл \"{filename}\": {e}"),
+            ),
+        };
+        state.popup_manager.open(message);
+    }
+
+    /// Фильтр переживает перезапуск
+    fn remember(&self, settings: &mut Settings) {
+        settings.set("log.show_micro", self.show_micro);
+        settings.set("log.search", &self.search);
+        settings.set("log.unlimited", self.unlimited);
+        settings.set("log.limit", self.limit);
+        // пустой список значит "по умолчанию", так что выключенные все категории помечаются отдельно
+        let categories = if self.categories.is_empty() {
+            vec![String::new()]
+        } else {
+            self.categories
+                .iter()
+                .map(|c| c.key().to_string())
+                .collect()
+        };
+        settings.set_list("log.categories", categories);
+    }
+
+    /// Возвращает, поменялись ли фильтр или лимит
+    fn draw_menu(&mut self, ui: &Ui, gui: &mut GuiState) -> bool {
+        let mut changed = false;
         ui.menu_bar(|| {
             if let Some(t) = ui.begin_menu("Фильтр") {
                 if ui
@@ -26,35 +243,107 @@ impl Tool for LogTool {
                     .build()
                 {
                     self.show_micro = !self.show_micro;
+                    changed = true;
+                }
+                ui.separator();
+                for category in LogCategory::ALL {
+                    let shown = self.categories.contains(&category);
+                    if ui
+                        .menu_item_config(category.title())
+                        .selected(shown)
+                        .build()
+                    {
+                        if shown {
+                            self.categories.retain(|c| *c != category);
+                        } else {
+                            self.categories.push(category);
+                        }
+                        changed = true;
+                    }
+                }
+                t.end();
+            }
+
+            if let Some(t) = ui.begin_menu("Лимит") {
+                changed |= ui.checkbox("Без ограничений", &mut self.unlimited);
+                if !self.unlimited {
+                    let width_t = ui.push_item_width(120.0);
+                    changed |= ui.input_int("записей", &mut self.limit).build();
+                    width_t.end();
+                    self.limit = self.limit.max(100);
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Старые записи выкидываются, когда лог становится длиннее");
+                }
+                t.end();
+            }
+
+            if let Some(t) = ui.begin_menu("Сохранить") {
+                if ui.menu_item("Текст") {
+                    let text = self.to_text(&gui.computer);
+                    self.save(gui, "txt", text);
+                }
+                if ui.menu_item("HTML") {
+                    let html = self.to_html(&gui.computer);
+                    self.save(gui, "html", html);
                 }
                 t.end();
             }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Сохраняет то, что сейчас видно: с теми же фильтрами и поиском");
+            }
+
             let token = ui.push_style_color(StyleColor::Button, [0.0, 0.0, 0.0, 0.0]);
             if ui.button("Очистить") {
                 gui.computer.clear_logs();
             }
             token.pop();
+
+            let width_t = ui.push_item_width(200.0);
+            changed |= ui.input_text("Поиск", &mut self.search).build();
+            width_t.end();
         });
+        changed
+    }
+}
 
-        let mut last_idx = 0u16;
-        for l in gui
-            .computer
-            .logs()
-            .iter()
-            .filter(|l| !l.micro_command || self.show_micro)
-        {
-            if last_idx != l.command_counter {
-                ui.separator();
-                last_idx = l.command_counter;
+impl Tool for LogTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, gui: &mut GuiState) {
+        if self.draw_menu(ui, gui) {
+            self.remember(&mut gui.settings);
+        }
+
+        let limit = if self.unlimited {
+            None
+        } else {
+            Some(self.limit.max(100) as usize)
+        };
+        if gui.computer.log_limit != limit {
+            gui.computer.log_limit = limit;
+            gui.computer.trim_logs();
+        }
+        self.build_lines(&gui.computer);
+
+        let computer = &gui.computer;
+        let mut clipper = ListClipper::new(self.lines.len() as i32).begin(ui);
+        while clipper.step() {
+            for line in self
+                .lines
+                .range(clipper.display_start() as usize..clipper.display_end() as usize)
+            {
+                match line {
+                    Line::Header(header) => ui.text_colored([0.4, 0.7, 1.0, 1.0], header),
+                    Line::Entry(id) => ui.text(format!(
+                        "    {}",
+                        Self::entry_text(Self::entry(computer, *id))
+                    )),
+                }
             }
-            ui.text(format!(
-                "СК: {:0>3X}, СчМК: {:0>2X}, msg: {}",
-                l.command_counter, l.micro_counter, l.info
-            ));
         }
-        if self.last_size != gui.computer.logs().len() {
+
+        if self.last_size != self.lines.len() {
             ui.set_scroll_here_y();
-            self.last_size = gui.computer.logs().len();
+            self.last_size = self.lines.len();
         }
     }
 }
@@ -5,13 +5,19 @@ use std::process::Command;
 pub mod gui;
 
 mod cells;
+mod checkpoint;
 mod controls;
+mod datapath;
+mod graph;
 mod help;
 mod highlight;
+mod interrupts;
 mod io;
 mod layout;
 mod log;
+mod panels;
 mod popup;
+mod recorder;
 mod registers;
 mod status;
 mod tracing;
@@ -0,0 +1,289 @@
+use crate::model::Computer;
+use crate::settings::Settings;
+use crate::ui::cells::CellsTool;
+use crate::ui::checkpoint::CheckpointTool;
+use crate::ui::controls::SmartControlsTool;
+use crate::ui::datapath::DatapathTool;
+use crate::ui::graph::{MicroGraphTool, ProgramGraphTool};
+use crate::ui::help::HelpTool;
+use crate::ui::highlight::CommandHighlightTool;
+use crate::ui::interrupts::InterruptsTool;
+use crate::ui::io::IOTool;
+use crate::ui::layout::LayoutTool;
+use crate::ui::log::LogTool;
+use crate::ui::recorder::RecorderTool;
+use crate::ui::registers::RegistersTool;
+use crate::ui::status::StatusTool;
+use crate::ui::tracing::TraceTool;
+use crate::ui::window::{SharedTool, Tool, WindowTool};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const GENERAL_MEMORY: &str = "Основная память";
+const MC_MEMORY: &str = "Память МПУ";
+const REGISTERS: &str = "Регистры";
+const STATUS: &str = "Разбор регистра статуса (РС)";
+const CONTROLS: &str = "Управление исполнением";
+const IO: &str = "Внешние устройства";
+const TRACE: &str = "Таблица трассировки";
+const PROGRAM_GRAPH: &str = "Граф программы";
+const MICRO_GRAPH: &str = "Граф микропрограммы";
+const DATAPATH: &str = "Схема БЭВМ";
+const COMMAND: &str = "Информация о команде";
+const LOG: &str = "Логи";
+const INTERRUPTS: &str = "Прерывания";
+const RECORDER: &str = "Запись трассировки";
+const CHECKPOINT: &str = "Изменения памяти";
+const HELP: [&str; 5] = [
+    "Прелюдия",
+    "Синтаксис",
+    "Шпора",
+    "Нотация",
+    "Да как остановить епт",
+];
+
+/// Что попросили сделать с раскладкой из меню окна. Выполняется после отрисовки кадра
+pub enum LayoutRequest {
+    /// Вынести панель из группы в отдельное окно
+    Undock { group: String, panel: &'static str },
+    /// Убрать панель из группы. Вернуть ее можно из меню "Раскладка"
+    Hide { group: String, panel: &'static str },
+}
+
+/// Готовые раскладки под разные занятия
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum LayoutPreset {
+    /// Программа в основной памяти: память, регистры, управление, справка
+    Assembly,
+    /// Микропрограмма: МПУ, схема БЭВМ, граф микропрограммы
+    Microcode,
+    /// Таблицы трассировки и их запись
+    Tracing,
+}
+
+impl LayoutPreset {
+    pub const ALL: [LayoutPreset; 3] = [
+        LayoutPreset::Assembly,
+        LayoutPreset::Microcode,
+        LayoutPreset::Tracing,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            LayoutPreset::Assembly => "Ассемблер",
+            LayoutPreset::Microcode => "Микрокод",
+            LayoutPreset::Tracing => "Трассировка",
+        }
+    }
+
+    /// Имя в настройках
+    pub fn key(&self) -> &'static str {
+        match self {
+            LayoutPreset::Assembly => "assembly",
+            LayoutPreset::Microcode => "microcode",
+            LayoutPreset::Tracing => "tracing",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<LayoutPreset> {
+        LayoutPreset::ALL.iter().copied().find(|p| p.key() == key)
+    }
+}
+
+/// Все панели интерфейса. Раскладки только расставляют их, так что при смене раскладки
+/// панели ничего не забывают
+pub struct Panels {
+    panels: Vec<(&'static str, SharedTool)>,
+}
+
+impl Panels {
+    pub fn new(computer: &Computer, settings: &Settings) -> Panels {
+        let mut panels = Panels { panels: vec![] };
+        panels.add(
+            GENERAL_MEMORY,
+            CellsTool::new(computer.general_memory.clone(), |c| {
+                c.registers.r_command_counter
+            })
+            .with_breakpoints()
+            .with_settings(settings, "general"),
+        );
+        panels.add(
+            MC_MEMORY,
+            CellsTool::new(computer.mc_memory.clone(), |c| {
+                c.registers.r_micro_command_counter as u16
+            })
+            .with_settings(settings, "mc"),
+        );
+        panels.add(REGISTERS, RegistersTool::new(settings));
+        panels.add(STATUS, StatusTool::new());
+        panels.add(CONTROLS, SmartControlsTool::new());
+        panels.add(IO, IOTool::new());
+        panels.add(TRACE, TraceTool::new(settings));
+        panels.add(PROGRAM_GRAPH, ProgramGraphTool::new());
+        panels.add(MICRO_GRAPH, MicroGraphTool::new());
+        panels.add(DATAPATH, DatapathTool::new());
+        panels.add(COMMAND, CommandHighlightTool::new());
+        let help = [
+            include_str!("../help/prelude.txt"),
+            include_str!("../help/file.txt"),
+            include_str!("../help/cheatsheet.txt"),
+            include_str!("../help/notation.txt"),
+            include_str!("../help/run_and_stop.txt"),
+        ];
+        for (name, text) in HELP.iter().copied().zip(help) {
+            panels.add(name, HelpTool::new(text));
+        }
+        panels.add(LOG, LogTool::new(settings));
+        panels.add(INTERRUPTS, InterruptsTool::new());
+        panels.add(RECORDER, RecorderTool::new());
+        panels.add(CHECKPOINT, CheckpointTool::new());
+        panels
+    }
+
+    fn add(&mut self, name: &'static str, tool: impl Tool + 'static) {
+        self.panels.push((name, Rc::new(RefCell::new(tool))));
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.panels.iter().map(|(name, _)| *name).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<SharedTool> {
+        self.panels
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, tool)| tool.clone())
+    }
+
+    /// Группа вкладок из панелей с такими названиями
+    fn group(&self, id: &str, names: &[&'static str]) -> WindowTool {
+        let mut group = WindowTool::new(id);
+        for name in names {
+            group = group.append_shared(name, self.get(name).unwrap());
+        }
+        group
+    }
+
+    pub fn build(&self, preset: LayoutPreset) -> LayoutTool {
+        match preset {
+            LayoutPreset::Assembly => self.assembly(),
+            LayoutPreset::Microcode => self.microcode(),
+            LayoutPreset::Tracing => self.tracing(),
+        }
+    }
+
+    fn assembly(&self) -> LayoutTool {
+        LayoutTool::new_vertical("root")
+            .append(
+                -210.,
+                LayoutTool::new_horizontal("main")
+                    .append(250., self.group("mem", &[GENERAL_MEMORY, MC_MEMORY]))
+                    .append(
+                        0.,
+                        LayoutTool::new_vertical("right")
+                            .append(
+                                250.,
+                                WindowTool::single_tool(
+                                    0,
+                                    250,
+                                    "Состояние ЭВМ",
+                                    LayoutTool::new_horizontal("regandstat")
+                                        .append(300., self.group(REGISTERS, &[REGISTERS]))
+                                        .append(0., self.group(STATUS, &[STATUS])),
+                                ),
+                            )
+                            .append(
+                                0.,
+                                LayoutTool::new_horizontal("middle")
+                                    .append(
+                                        335.,
+                                        WindowTool::single_tool(
+                                            315,
+                                            0,
+                                            "Панель управления",
+                                            LayoutTool::new_vertical("execandio")
+                                                .append(135., self.group(CONTROLS, &[CONTROLS]))
+                                                .append(0., self.group(IO, &[IO])),
+                                        )
+                                        .append_shared(TRACE, self.get(TRACE).unwrap())
+                                        .append_shared(
+                                            PROGRAM_GRAPH,
+                                            self.get(PROGRAM_GRAPH).unwrap(),
+                                        )
+                                        .append_shared(MICRO_GRAPH, self.get(MICRO_GRAPH).unwrap())
+                                        .append_shared(DATAPATH, self.get(DATAPATH).unwrap()),
+                                    )
+                                    .append(
+                                        350.,
+                                        LayoutTool::new_vertical("infoandload")
+                                            .append(0., self.group(COMMAND, &[COMMAND]))
+                                            .size(315, 0),
+                                    )
+                                    .append(0., self.group("help", &HELP)),
+                            ),
+                    ),
+            )
+            .append(
+                200.,
+                self.group("bottom", &[LOG, INTERRUPTS, RECORDER, CHECKPOINT]),
+            )
+    }
+
+    fn microcode(&self) -> LayoutTool {
+        LayoutTool::new_vertical("root")
+            .append(
+                -210.,
+                LayoutTool::new_horizontal("main")
+                    .append(250., self.group("mem", &[MC_MEMORY, GENERAL_MEMORY]))
+                    .append(
+                        0.,
+                        LayoutTool::new_vertical("right")
+                            .append(
+                                420.,
+                                LayoutTool::new_horizontal("scheme")
+                                    .append(
+                                        300.,
+                                        LayoutTool::new_vertical("state")
+                                            .append(250., self.group(REGISTERS, &[REGISTERS]))
+                                            .append(0., self.group(CONTROLS, &[CONTROLS])),
+                                    )
+                                    .append(0., self.group(DATAPATH, &[DATAPATH, STATUS])),
+                            )
+                            .append(
+                                0.,
+                                LayoutTool::new_horizontal("middle")
+                                    .append(450., self.group(COMMAND, &[COMMAND]))
+                                    .append(0., self.group(MICRO_GRAPH, &[MICRO_GRAPH])),
+                            ),
+                    ),
+            )
+            .append(200., self.group("bottom", &[LOG, CHECKPOINT, INTERRUPTS]))
+    }
+
+    fn tracing(&self) -> LayoutTool {
+        LayoutTool::new_vertical("root")
+            .append(
+                -260.,
+                LayoutTool::new_horizontal("main")
+                    .append(250., self.group("mem", &[GENERAL_MEMORY, MC_MEMORY]))
+                    .append(
+                        0.,
+                        LayoutTool::new_vertical("right")
+                            .append(
+                                250.,
+                                LayoutTool::new_horizontal("state")
+                                    .append(300., self.group(REGISTERS, &[REGISTERS]))
+                                    .append(335., self.group(CONTROLS, &[CONTROLS, IO]))
+                                    .append(0., self.group(STATUS, &[STATUS])),
+                            )
+                            .append(0., self.group(TRACE, &[TRACE, PROGRAM_GRAPH])),
+                    ),
+            )
+            .append(
+                250.,
+                LayoutTool::new_horizontal("bottom")
+                    .append(650., self.group(RECORDER, &[RECORDER, CHECKPOINT]))
+                    .append(0., self.group(LOG, &[LOG, INTERRUPTS])),
+            )
+    }
+}
@@ -0,0 +1,143 @@
+use crate::trace::{
+    presets, Column, Preset, StopReason, TraceKind, TraceRecord, TraceRow, RECORDER_LIMIT,
+};
+use crate::ui::gui::GuiState;
+use crate::ui::tracing::{converters, draw_column_list, enum_chooser, Converter, TraceSource};
+use crate::ui::window::Tool;
+use imgui::{Io, ListClipper, StyleColor, TableFlags, TreeNodeId, Ui};
+
+use std::cell::RefCell;
+
+/// Трассировка, которая пишется сама, пока вы жмете кнопки на панели управления
+pub struct RecorderTool {
+    converter: usize,
+    converters: [(&'static str, Converter); 8],
+    presets: Vec<Preset>,
+    columns: Vec<Column>,
+    last_total: usize,
+}
+
+impl RecorderTool {
+    pub fn new() -> RecorderTool {
+        let presets: Vec<Preset> = presets()
+            .into_iter()
+            .filter(|p| p.kind == TraceKind::General)
+            .collect();
+        RecorderTool {
+            converter: 0,
+            converters: converters(),
+            columns: presets[0].columns.clone(),
+            presets,
+            last_total: 0,
+        }
+    }
+
+    fn draw_menu(&mut self, ui: &Ui, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            ui.checkbox("Записывать", &mut state.recorder.enabled);
+            if ui.is_item_hovered() {
+                ui.tooltip_text(format!(
+                    "Записывается каждая команда, выполненная кнопками \"Большой шаг\", \"Микро шаг\" \
+                    или после \"Пуск\"/\"Продолжить\". Хранятся последние {RECORDER_LIMIT} команд.\n\n\
+                    Запись копирует память на каждой команде, так что без нее автоматический режим быстрее"
+                ));
+            }
+
+            if let Some(t) = ui.begin_menu("Колонки") {
+                for preset in self.presets.iter() {
+                    if ui.menu_item(preset.name) {
+                        self.columns = preset.columns.clone();
+                    }
+                }
+                ui.separator();
+                draw_column_list(ui, TraceKind::General, &mut self.columns);
+                t.end();
+            }
+
+            let token = ui.push_style_color(StyleColor::Button, [0.0, 0.0, 0.0, 0.0]);
+            if ui.button("Очистить") {
+                state.recorder.clear();
+            }
+            token.pop();
+
+            ui.text(format!("Команд: {}", state.recorder.total));
+        });
+    }
+
+    fn draw_table(&mut self, ui: &Ui, state: &GuiState) {
+        let records = &state.recorder.records;
+        if self.columns.is_empty() {
+            ui.text("Не выбрано ни одной колонки");
+            return;
+        }
+        if records.is_empty() {
+            ui.text_wrapped("Пока ничего не записано. Выполните пару команд с панели управления");
+            return;
+        }
+
+        let flags = TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SCROLL_Y;
+        let Some(table) = ui.begin_table_with_flags("###recorded", self.columns.len(), flags)
+        else {
+            return;
+        };
+
+        ui.table_setup_scroll_freeze(0, 1);
+        for title in Column::header(TraceKind::General, &self.columns) {
+            ui.table_setup_column(title);
+        }
+        ui.table_headers_row();
+
+        let mut clipper = ListClipper::new(records.len() as i32).begin(ui);
+        while clipper.step() {
+            for idx in clipper.display_start()..clipper.display_end() {
+                let row = TraceRow::new(TraceKind::General, &self.columns, &records[idx as usize]);
+                ui.table_next_row();
+                // изменившиеся ячейки тут в одну строку, на отдельные строки они разбиваются при сохранении
+                for values in row.cells.iter() {
+                    ui.table_next_column();
+                    ui.text(values.join(" "));
+                }
+            }
+        }
+
+        if self.last_total != state.recorder.total {
+            ui.set_scroll_here_y_with_ratio(1.0);
+            self.last_total = state.recorder.total;
+        }
+
+        table.end();
+    }
+}
+
+impl Tool for RecorderTool {
+    fn draw(&mut self, ui: &Ui, _: &Io, state: &mut GuiState) {
+        self.draw_menu(ui, state);
+
+        ui.tree_node_config(TreeNodeId::Str("Сохранить")).build(|| {
+            let (_, converter) =
+                *enum_chooser(ui, "###converter", &mut self.converter, &self.converters);
+            let columns = &self.columns;
+            let cell = RefCell::new(&mut *state);
+            let mut runner = |max_len: usize, sink: &mut dyn FnMut(TraceRecord)| {
+                for record in cell.borrow().recorder.records.iter().take(max_len) {
+                    sink(record.clone());
+                }
+                StopReason::Recorded
+            };
+            converter(
+                ui,
+                &cell,
+                &mut TraceSource {
+                    kind: TraceKind::General,
+                    columns,
+                    // записанное уже лежит в памяти, так что ограничивать нечего
+                    max_len: usize::MAX,
+                    collect_len: usize::MAX,
+                    runner: &mut runner,
+                },
+            );
+        });
+
+        self.draw_table(ui, state);
+    }
+}
@@ -1,5 +1,9 @@
-use crate::model::{Computer, Register};
+use crate::model::Register;
+use crate::parse::{CommandInfo, Parser};
+use crate::settings::Settings;
+use crate::ui::cells::CellRepresentation;
 use crate::ui::gui::GuiState;
+use crate::ui::popup::PopupParseError;
 use crate::ui::window::Tool;
 use imgui::sys::{
     igBeginTable, igEndTable, igTableNextColumn, igTableNextRow, ImGuiTableFlags_None,
@@ -8,36 +12,109 @@ use imgui::sys::{
 use imgui::{im_str, ImString, Io, Ui};
 use std::os::raw::c_int;
 
-pub struct RegistersTool;
+pub struct RegistersTool {
+    representation: CellRepresentation,
+}
 
 impl RegistersTool {
-    pub fn new() -> RegistersTool {
-        RegistersTool {}
+    pub fn new(settings: &Settings) -> RegistersTool {
+        RegistersTool {
+            representation: settings
+                .get("registers.representation")
+                .and_then(CellRepresentation::from_key)
+                .unwrap_or(CellRepresentation::Hex),
+        }
     }
 }
 
 impl Tool for RegistersTool {
     fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
-        fn reg_field(ui: &Ui, computer: &mut Computer, register: Register, tooltip: &str) {
-            let mut content = register.format(computer);
-            let t = ui.push_item_width(80.0);
-            if ui
-                .input_text(register.mnemonic(), &mut content)
-                .chars_hexadecimal(true)
-                .allow_tab_input(false)
-                .build()
-            {
-                if let Ok(parsed) = u32::from_str_radix(&content, 16) {
-                    register.assign_wide(computer, parsed);
-                }
+        fn field<I: CommandInfo, P: Parser<I>>(
+            ui: &Ui,
+            label: &str,
+            representation: CellRepresentation,
+            value: u32,
+            bits: u32,
+            parser: &P,
+        ) -> Result<Option<u32>, (String, String)> {
+            representation.input(ui, label, value, bits, parser, || {
+                parser.parse(value as u16).mnemonic()
+            })
+        }
+
+        fn reg_field(
+            ui: &Ui,
+            state: &mut GuiState,
+            representation: CellRepresentation,
+            register: Register,
+            tooltip: &str,
+        ) {
+            let computer = &mut state.computer;
+            let bits = register.bits();
+            let value = match register {
+                Register::Buffer => computer.registers.r_buffer,
+                _ => register.get(computer) as u32,
+            };
+            let label = register.mnemonic();
+            let memory = computer.general_memory.clone();
+            let mc_memory = computer.mc_memory.clone();
+            // РМК разбирается как микрокоманда, регистры с данными и адресами - как команды
+            let result = match (representation, register) {
+                (CellRepresentation::Instruction, Register::MicroCommand) => field(
+                    ui,
+                    &label,
+                    representation,
+                    value,
+                    bits,
+                    &mc_memory.borrow().parser,
+                ),
+                (
+                    CellRepresentation::Instruction,
+                    Register::Status | Register::McCounter | Register::Buffer | Register::Address,
+                ) => field(
+                    ui,
+                    &label,
+                    CellRepresentation::Hex,
+                    value,
+                    bits,
+                    &memory.borrow().parser,
+                ),
+                _ => field(
+                    ui,
+                    &label,
+                    representation,
+                    value,
+                    bits,
+                    &memory.borrow().parser,
+                ),
+            };
+            match result {
+                Ok(Some(value)) => register.assign_wide(computer, value),
+                Ok(None) => {}
+                Err((content, msg)) => state.popup_manager.open(PopupParseError::new(content, msg)),
             }
-            t.end();
             if ui.is_item_hovered() {
                 ui.tooltip_text(tooltip);
             }
         }
 
-        let computer = &mut state.computer;
+        ui.menu_bar(|| {
+            if let Some(token) = ui.begin_menu("Представление") {
+                CellRepresentation::draw_selection(ui, &mut self.representation);
+                token.end()
+            }
+        });
+        state
+            .settings
+            .set("registers.representation", self.representation.key());
+
+        let representation = self.representation;
+        // поля в двоичном виде и командах шире, таблицы под них растягиваются
+        let table_width = match representation {
+            CellRepresentation::Binary => 500.0,
+            CellRepresentation::Instruction => 600.0,
+            _ => 250.0,
+        };
 
         ui.text("Основные регистры");
         unsafe {
@@ -45,21 +122,23 @@ impl Tool for RegistersTool {
                 ImString::new("general_reg").as_ptr(),
                 2,
                 ImGuiTableFlags_None as c_int,
-                ImVec2::new(250.0, 0.0),
+                ImVec2::new(table_width, 0.0),
                 0.0,
             );
             igTableNextRow(ImGuiTableRowFlags_None as c_int, 0.0);
             igTableNextColumn();
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::Counter,
                 "Аккумулятор. Основной регистр с данными.",
             );
             igTableNextColumn();
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::CommandCounter,
                 "Счетчик команд. Указывает на текущую выполняемую команду.",
             );
@@ -72,21 +151,23 @@ impl Tool for RegistersTool {
                 im_str!("mc_reg").as_ptr(),
                 2,
                 ImGuiTableFlags_None as c_int,
-                ImVec2::new(250.0, 0.0),
+                ImVec2::new(table_width, 0.0),
                 0.0,
             );
             igTableNextRow(ImGuiTableRowFlags_None as c_int, 0.0);
             igTableNextColumn();
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::McCounter,
                 "Счетчик микрокоманд. Текущая микрокоманда.",
             );
             igTableNextColumn();
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::Status,
                 "Регистр состояния. В битах этого регистра хранится информация о состоянии ЭВМ.",
             );
@@ -95,25 +176,28 @@ impl Tool for RegistersTool {
 
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::MicroCommand,
                 "Регистр микрокоманды. Сюда цпу помещает микрокоманду во время ее выполнения.",
             );
             igTableNextColumn();
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::Buffer,
                 "Буфферный регистр. Через него проходят данные в микрокомандах.",
             );
             igTableNextRow(ImGuiTableRowFlags_None as c_int, 0.0);
             igTableNextColumn();
 
-            reg_field(ui, computer, Register::Address, "Регистр адреса. Микрокоманда должна поместить адрес сюда, чтобы положить данные в БР");
+            reg_field(ui, state, representation, Register::Address, "Регистр адреса. Микрокоманда должна поместить адрес сюда, чтобы положить данные в БР");
             igTableNextColumn();
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::Command,
                 "Регистр команды. Микрокоманда помещает команду из БР сюда.",
             );
@@ -122,7 +206,8 @@ impl Tool for RegistersTool {
 
             reg_field(
                 ui,
-                computer,
+                state,
+                representation,
                 Register::Data,
                 "Регистр данных. Сюда микрокоманды помещают данные.",
             );
@@ -72,10 +72,6 @@ impl Tool for StatusTool {
             4,
         );
 
-        if !state.computer.registers.get_allow_interupt() {
-            state.computer.registers.set_interrupt(false);
-        }
-
         if state.computer.registers.get_io() {
             state.computer.process_io_command();
         }
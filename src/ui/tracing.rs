@@ -1,54 +1,471 @@
 use crate::model::Computer;
-use crate::parse::mc::ExecutionResult;
+use crate::settings::Settings;
+use crate::trace::{
+    compare, presets, run, Column, Comparison, Divergence, Preset, Reference, StopConditions,
+    StopReason, TraceKind, TraceRecord, TraceRow, Tracing,
+};
 use crate::ui::gui::{GuiState, PopupManager};
 use crate::ui::open_in_app;
 use crate::ui::popup::PopupMessage;
 use crate::ui::window::Tool;
 use imgui::TreeNodeId::Str;
-use imgui::{Io, Ui};
+use imgui::{Io, ListClipper, TableFlags, Ui};
 use rfd::FileDialog;
 
+use std::cell::Cell;
 use std::cell::RefCell;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
 
-type Converter = (
+/// Имя формата в настройках и функция, которая рисует его описание и сохраняет таблицу
+pub type Converter = (
     &'static str,
-    fn(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnMut() -> Tracing),
-);
-type Tracer = (
-    &'static str,
-    fn(computer: &mut Computer, len: usize) -> Tracing,
+    fn(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource),
 );
 
+/// Самая длинная трассировка
+const MAX_LEN: i32 = 1_000_000;
+
+/// Сколько строк можно собрать в памяти для форматов, которые пишутся в файл целиком.
+/// Трассировка идет прямо в кадре, так что больше окно просто зависнет
+pub const IN_MEMORY_LIMIT: usize = 10_000;
+
+/// Запускает трассировку не длиннее переданного числа шагов и отдает каждый шаг
+/// в переданную функцию сразу после выполнения
+pub type Runner<'a> = dyn FnMut(usize, &mut dyn FnMut(TraceRecord)) -> StopReason + 'a;
+
+/// Откуда конвертеры берут трассировку
+pub struct TraceSource<'a, 'b> {
+    pub kind: TraceKind,
+    pub columns: &'a [Column],
+    /// Сколько шагов выполнять, когда строки сразу пишутся в файл
+    pub max_len: usize,
+    /// Сколько шагов выполнять, когда таблица собирается в памяти
+    pub collect_len: usize,
+    pub runner: &'a mut Runner<'b>,
+}
+
+impl TraceSource<'_, '_> {
+    /// Выполняет трассировку целиком и собирает таблицу в памяти
+    fn collect(&mut self) -> Tracing {
+        let mut records = vec![];
+        (self.runner)(self.collect_len, &mut |r| records.push(r));
+        Tracing::new(self.kind, self.columns, records)
+    }
+
+    /// Выполняет трассировку, не собирая ее в памяти
+    fn stream(&mut self, sink: &mut dyn FnMut(&TraceRecord)) -> StopReason {
+        (self.runner)(self.max_len, &mut |r| sink(&r))
+    }
+}
+
+/// Где выполняется трассировка
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum TraceMode {
+    /// На копии ЭВМ. Настоящая ЭВМ остается как была
+    Copy,
+    /// На настоящей ЭВМ, после чего регистры и память возвращаются как были
+    Restore,
+    /// На настоящей ЭВМ, которая так и остается после HLT
+    Live,
+}
+
+impl TraceMode {
+    /// Имя в настройках
+    fn key(&self) -> &'static str {
+        match self {
+            TraceMode::Copy => "copy",
+            TraceMode::Restore => "restore",
+            TraceMode::Live => "live",
+        }
+    }
+
+    fn run<T>(&self, computer: &mut Computer, tracer: impl FnOnce(&mut Computer) -> T) -> T {
+        match self {
+            TraceMode::Copy => tracer(&mut computer.fork()),
+            TraceMode::Restore => {
+                let saved = computer.fork();
+                let result = tracer(computer);
+                computer.restore(&saved);
+                result
+            }
+            TraceMode::Live => tracer(computer),
+        }
+    }
+}
+
+pub fn converters() -> [(&'static str, Converter); 8] {
+    [
+        ("CSV", ("csv", csv_converter)),
+        ("HTML", ("html", html_converter)),
+        ("LaTeX", ("latex", latex_converter)),
+        ("Markdown", ("markdown", markdown_converter)),
+        ("Typst", ("typst", typst_converter)),
+        ("Excel (XLSX)", ("xlsx", xlsx_converter)),
+        ("JSON", ("json", json_converter)),
+        ("JSON Lines", ("jsonl", json_lines_converter)),
+    ]
+}
+
 pub struct TraceTool {
     converter: usize,
     tracer: usize,
-    converters: [Converter; 3],
-    tracers: [Tracer; 2],
+    mode: usize,
+    modes: [(&'static str, TraceMode); 3],
+    converters: [(&'static str, Converter); 8],
+    tracers: [(&'static str, TraceKind); 2],
+    presets: Vec<Preset>,
+    columns: Vec<Column>,
     max_len: i32,
+    stop: StopConditions,
+    /// Сколько шагов было в последней трассировке и почему она остановилась
+    last_run: Option<(usize, StopReason)>,
+    /// Последняя сверка с эталонной таблицей
+    reference: Option<(Reference, Comparison)>,
+    scroll_to_divergence: bool,
 }
 
 impl TraceTool {
-    pub fn new() -> TraceTool {
-        TraceTool {
+    pub fn new(settings: &Settings) -> TraceTool {
+        let mut tool = TraceTool {
             converter: 0,
             tracer: 0,
-            tracers: [
-                ("Основная память", general_tracing),
-                ("Память МПУ", mc_tracing),
+            mode: 0,
+            modes: [
+                ("На копии ЭВМ", TraceMode::Copy),
+                ("На ЭВМ, потом вернуть как было", TraceMode::Restore),
+                ("На ЭВМ и оставить после HLT", TraceMode::Live),
             ],
-            converters: [
-                ("CSV", csv_converter),
-                ("HTML", html_converter),
-                ("LaTeX", latex_converter),
+            tracers: [
+                (TraceKind::General.title(), TraceKind::General),
+                (TraceKind::Micro.title(), TraceKind::Micro),
             ],
+            converters: converters(),
+            columns: presets()[0].columns.clone(),
+            presets: presets(),
             max_len: 200,
+            stop: StopConditions::default(),
+            last_run: None,
+            reference: None,
+            scroll_to_divergence: false,
+        };
+        tool.restore(settings);
+        tool
+    }
+
+    /// Формат, вид трассировки и где выполнять - как в прошлый раз
+    fn restore(&mut self, settings: &Settings) {
+        fn position<T>(
+            variants: &[(&str, T)],
+            key: impl Fn(&T) -> &'static str,
+            name: Option<&str>,
+        ) -> Option<usize> {
+            variants.iter().position(|(_, v)| Some(key(v)) == name)
+        }
+
+        let format = settings.get("trace.format");
+        if let Some(idx) = position(&self.converters, |c| c.0, format) {
+            self.converter = idx;
+        }
+        if let Some(idx) = position(&self.modes, TraceMode::key, settings.get("trace.mode")) {
+            self.mode = idx;
+        }
+        if let Some(idx) = position(&self.tracers, TraceKind::key, settings.get("trace.kind")) {
+            self.tracer = idx;
+            if let Some(preset) = self.presets.iter().find(|p| p.kind == self.kind()) {
+                self.columns = preset.columns.clone();
+            }
+        }
+        if let Some(max_len) = settings.parsed::<i32>("trace.max_len") {
+            self.max_len = max_len.clamp(1, MAX_LEN);
+        }
+    }
+
+    fn remember(&self, settings: &mut Settings) {
+        settings.set("trace.format", self.converters[self.converter].1 .0);
+        settings.set("trace.mode", self.modes[self.mode].1.key());
+        settings.set("trace.kind", self.tracers[self.tracer].1.key());
+        settings.set("trace.max_len", self.max_len);
+    }
+
+    fn kind(&self) -> TraceKind {
+        self.tracers[self.tracer].1
+    }
+
+    fn draw_reference(&mut self, ui: &Ui, state: &mut GuiState, mode: TraceMode) {
+        let text = "Загружает эталонную таблицу в CSV (например, присланную студентом) и выполняет программу, \
+            сверяя таблицы строка за строкой. Регистр букв и ведущие нули не важны.\n\n\
+            Колонки берутся из шапки эталона, так что они должны называться так же, как в сохраненных отсюда таблицах. \
+            Вид трассировки, место выполнения и условия остановки - как выбрано выше.";
+        ui.text_wrapped(text);
+
+        if ui.button_with_size("Загрузить эталон", [160.0, 30.0]) {
+            self.load_reference(state, mode);
         }
+
+        self.draw_comparison(ui);
+    }
+
+    fn load_reference(&mut self, state: &mut GuiState, mode: TraceMode) {
+        let Some(file) = FileDialog::new().add_filter("", &["csv"]).pick_file() else {
+            return;
+        };
+        let filename = file.into_os_string().into_string().unwrap_or_default();
+
+        let kind = self.kind();
+        let reference = std::fs::read_to_string(&filename)
+            .map_err(|e| format!("Не могу прочитать файл \"{filename}\": {e}"))
+            .and_then(|text| Reference::parse(kind, &text));
+        let reference = match reference {
+            Ok(reference) => reference,
+            Err(e) => {
+                state
+                    .popup_manager
+                    .open(PopupMessage::new("Не получилось загрузить эталон", e));
+                return;
+            }
+        };
+
+        let stop = &self.stop;
+        let comparison = mode.run(&mut state.computer, |computer| {
+            compare(kind, computer, &reference, stop)
+        });
+        self.reference = Some((reference, comparison));
+        self.scroll_to_divergence = true;
+    }
+
+    fn draw_comparison(&mut self, ui: &Ui) {
+        let Some((reference, comparison)) = &self.reference else {
+            return;
+        };
+
+        let red = [1.0, 0.3, 0.3, 1.0];
+        let divergence = comparison.divergence.as_ref();
+        match divergence {
+            None => ui.text_colored(
+                [0.3, 0.9, 0.3, 1.0],
+                format!(
+                    "Все {} строк совпали. {}",
+                    reference.rows.len(),
+                    comparison.stop.title()
+                ),
+            ),
+            Some(Divergence {
+                row,
+                column: Some(column),
+            }) => ui.text_colored(
+                red,
+                format!(
+                    "Расходится в строке {}, колонка \"{}\"",
+                    row + 1,
+                    reference.header[*column]
+                ),
+            ),
+            Some(Divergence { row, column: None }) if *row < reference.rows.len() => ui
+                .text_colored(
+                    red,
+                    format!(
+                    "Расходится в строке {}: в эталоне строки еще есть, а ЭВМ уже остановилась. {}",
+                    row + 1,
+                    comparison.stop.title()
+                ),
+                ),
+            Some(Divergence { row, .. }) => ui.text_colored(
+                red,
+                format!(
+                    "Расходится в строке {}: эталон закончился посреди команды",
+                    row + 1
+                ),
+            ),
+        }
+
+        // эталон до первого расхождения и под ним то, что получилось у ЭВМ
+        let shown = divergence.map_or(reference.rows.len(), |d| {
+            (d.row + 1).min(reference.rows.len())
+        });
+        let actual = divergence.and_then(|d| comparison.actual.get(d.row));
+        let count = shown + actual.is_some() as usize;
+
+        let flags = TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SCROLL_Y;
+        let Some(table) = ui.begin_table_with_sizing(
+            "###reference",
+            reference.header.len() + 1,
+            flags,
+            [0.0, 300.0],
+            0.0,
+        ) else {
+            return;
+        };
+
+        ui.table_setup_scroll_freeze(0, 1);
+        ui.table_setup_column("Строка");
+        for title in reference.header.iter() {
+            ui.table_setup_column(title);
+        }
+        ui.table_headers_row();
+
+        let mut clipper = ListClipper::new(count as i32).begin(ui);
+        while clipper.step() {
+            for idx in clipper.display_start() as usize..clipper.display_end() as usize {
+                let (label, row) = if idx < shown {
+                    ((idx + 1).to_string(), &reference.rows[idx])
+                } else {
+                    ("ЭВМ".to_string(), actual.unwrap())
+                };
+                let wrong = divergence
+                    .filter(|d| d.row == idx || idx == shown)
+                    .and_then(|d| d.column);
+
+                ui.table_next_row();
+                ui.table_next_column();
+                ui.text(label);
+                for (column, value) in row.iter().enumerate() {
+                    ui.table_next_column();
+                    if wrong == Some(column) {
+                        ui.text_colored(red, value);
+                    } else {
+                        ui.text(value);
+                    }
+                }
+            }
+        }
+
+        if self.scroll_to_divergence {
+            ui.set_scroll_here_y_with_ratio(1.0);
+            self.scroll_to_divergence = false;
+        }
+
+        table.end();
+    }
+
+    fn draw_stop_conditions(&mut self, ui: &Ui) {
+        let stop = &mut self.stop;
+
+        let mut enabled = stop.address.is_some();
+        ui.checkbox("Дошли до адреса", &mut enabled);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Останавливается, когда следующая команда лежит по этому адресу");
+        }
+        if enabled {
+            let mut address = stop.address.unwrap_or(0);
+            ui.same_line();
+            address_input(ui, "###stop_address", &mut address);
+            stop.address = Some(address);
+        } else {
+            stop.address = None;
+        }
+
+        ui.checkbox("Точки останова", &mut stop.breakpoints);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(
+                "Точку останова можно поставить правым кликом по адресу в основной памяти",
+            );
+        }
+
+        let mut enabled = stop.loop_head.is_some();
+        ui.checkbox("Начало цикла", &mut enabled);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(
+                "Останавливается, когда команда по адресу собирается выполниться в N-й раз",
+            );
+        }
+        if enabled {
+            let (mut address, count) = stop.loop_head.unwrap_or((0, 10));
+            let mut count = count as i32;
+            ui.same_line();
+            address_input(ui, "###loop_address", &mut address);
+            ui.same_line();
+            let width_t = ui.push_item_width(100.0);
+            ui.input_int("раз###loop_count", &mut count).build();
+            width_t.end();
+            stop.loop_head = Some((address, count.max(1) as u32));
+        } else {
+            stop.loop_head = None;
+        }
+
+        ui.checkbox("Зацикливание", &mut stop.infinite_loop);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(
+                "Останавливается, если регистры, память и ВУ стали такими же, какими уже были. \
+            Дальше ЭВМ будет только ходить по кругу. Проверяется между командами и не \
+            проверяется, пока у ВУ не закончился сценарий",
+            );
+        }
+
+        ui.checkbox("Вход в прерывание", &mut stop.interrupt);
+    }
+
+    fn draw_columns(&mut self, ui: &Ui) {
+        let kind = self.kind();
+
+        if let Some(t) = ui.begin_combo("###preset", "Как в методичке...") {
+            for (idx, preset) in self.presets.iter().enumerate() {
+                if ui.selectable(preset.name) {
+                    self.tracer = self
+                        .tracers
+                        .iter()
+                        .position(|(_, k)| *k == preset.kind)
+                        .unwrap_or(0);
+                    self.columns = self.presets[idx].columns.clone();
+                }
+            }
+            t.end()
+        }
+
+        draw_column_list(ui, kind, &mut self.columns);
     }
 }
 
-fn html_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnMut() -> Tracing) {
+/// Список колонок с кнопками для перестановки и удаления и выбор новой колонки
+pub fn draw_column_list(ui: &Ui, kind: TraceKind, columns: &mut Vec<Column>) {
+    let mut action: Option<(usize, i32)> = None;
+    for (idx, column) in columns.iter().enumerate() {
+        let id = ui.push_id_int(idx as i32);
+        if ui.small_button("^") {
+            action = Some((idx, -1));
+        }
+        ui.same_line();
+        if ui.small_button("v") {
+            action = Some((idx, 1));
+        }
+        ui.same_line();
+        if ui.small_button("x") {
+            action = Some((idx, 0));
+        }
+        ui.same_line();
+        ui.text(column.title(kind));
+        id.pop();
+    }
+
+    match action {
+        Some((idx, 0)) => {
+            columns.remove(idx);
+        }
+        Some((idx, shift)) => {
+            let other = idx as i32 + shift;
+            if other >= 0 && (other as usize) < columns.len() {
+                columns.swap(idx, other as usize);
+            }
+        }
+        None => {}
+    }
+
+    if let Some(t) = ui.begin_combo("###add_column", "Добавить колонку") {
+        for column in Column::all(kind) {
+            let title = match column {
+                Column::ChangedAddress => "Адрес изменившейся ячейки".to_string(),
+                Column::ChangedValue => "Новое значение изменившейся ячейки".to_string(),
+                _ => column.title(kind),
+            };
+            if ui.selectable(title) {
+                columns.push(column);
+            }
+        }
+        t.end()
+    }
+}
+
+fn html_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
     let text = "Сохраняет трассировку в формате HTML\n\n\
     Это удобно когда вам нужно быстро на нее посмотреть, но неудобно когда нужно ее куда то вставить.\n\n";
 
@@ -67,26 +484,44 @@ fn html_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnM
         return;
     }
 
-    let trace = tracing();
+    let trace = source.collect();
     let mut content = String::new();
 
     content.push_str("\t<tr>");
 
     for x in trace.header {
         content.push_str("\t\t<th>");
-        content.push_str(x.as_str());
+        content.push_str(&html_escape(&x));
         content.push_str("</th>\n");
     }
 
     content.push_str("\t</tr>");
 
-    for x in trace.tracing {
-        content.push_str("\t<tr>");
-
-        for s in x {
-            content.push_str(format!("\t\t<td>{}</td>", s).as_str());
+    for row in trace.tracing {
+        let height = row.height();
+        for sub_row in 0..height {
+            content.push_str("\t<tr>");
+
+            for column in 0..row.cells.len() {
+                if row.is_spanning(column) {
+                    if sub_row == 0 {
+                        content.push_str(
+                            format!(
+                                "\t\t<td rowspan={}>{}</td>",
+                                height,
+                                html_escape(row.get(column, 0))
+                            )
+                            .as_str(),
+                        );
+                    }
+                } else {
+                    content.push_str(
+                        format!("\t\t<td>{}</td>", html_escape(row.get(column, sub_row))).as_str(),
+                    );
+                }
+            }
+            content.push_str("\t</tr>");
         }
-        content.push_str("\t</tr>");
     }
 
     let formatted = format!(
@@ -97,7 +532,7 @@ fn html_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnM
     );
 
     let name = write_to_file(
-        formatted.as_str(),
+        formatted.as_bytes(),
         "html",
         &mut state.borrow_mut().popup_manager,
     );
@@ -116,7 +551,12 @@ fn html_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnM
     }
 }
 
-fn csv_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnMut() -> Tracing) {
+/// В HTML экранируется то же, что и в XML
+fn html_escape(s: &str) -> String {
+    xml_escape(s)
+}
+
+fn csv_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
     let text = "Сохраняет трассировку в формате CSV\n\n\
     Разделитель: таб\n\
     Кодировка: UTF-8\n\
@@ -124,34 +564,56 @@ fn csv_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnMu
     ";
 
     ui.text_wrapped(text);
+    ui.text_wrapped(STREAMING_DESCRIPTION);
     if ui.button_with_size("Погнали!", [160.0, 30.0]) {
-        let trace = tracing();
+        let Some((filename, f)) = create_file("csv", &mut state.borrow_mut().popup_manager) else {
+            return;
+        };
+        let mut out = BufWriter::new(f);
 
         let mut content = String::new();
-        for x in trace.header.iter().map(std::string::ToString::to_string) {
+        for x in Column::header(source.kind, source.columns) {
             content.push_str(x.as_str());
             content.push('\t');
         }
-
-        for x in trace.tracing {
-            content.push('\n');
-            for x in x.iter().map(std::string::ToString::to_string) {
-                content.push('"');
-                content.push_str(x.as_str());
-                content.push('"');
-                content.push('\t');
+        let mut result = out.write_all(content.as_bytes());
+
+        let (kind, columns) = (source.kind, source.columns);
+        let mut rows = 0;
+        let stop = source.stream(&mut |record| {
+            let mut content = String::new();
+            for x in TraceRow::new(kind, columns, record).sub_rows() {
+                content.push('\n');
+                for x in x.iter() {
+                    content.push('"');
+                    content.push_str(x.as_str());
+                    content.push('"');
+                    content.push('\t');
+                }
             }
-        }
+            if result.is_ok() {
+                result = out.write_all(content.as_bytes());
+            }
+            rows += 1;
+        });
 
-        write_to_file(
-            content.as_str(),
-            "csv",
+        finish_streaming(
+            out,
+            result,
+            filename.as_str(),
+            rows,
+            stop,
             &mut state.borrow_mut().popup_manager,
         );
     }
 }
 
-fn enum_chooser<'a, T>(ui: &Ui, name: &str, num: &mut usize, variants: &'a [(&str, T)]) -> &'a T {
+pub fn enum_chooser<'a, T>(
+    ui: &Ui,
+    name: &str,
+    num: &mut usize,
+    variants: &'a [(&str, T)],
+) -> &'a T {
     let title = variants.get(*num).unwrap().0;
 
     if let Some(t) = ui.begin_combo(name, title) {
@@ -168,7 +630,24 @@ fn enum_chooser<'a, T>(ui: &Ui, name: &str, num: &mut usize, variants: &'a [(&st
 
     return &variants.get(*num).unwrap().1;
 }
-fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn FnMut() -> Tracing) {
+fn latex_escape(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\textbackslash{}"),
+            '~' => result.push_str("\\textasciitilde{}"),
+            '^' => result.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                result.push('\\');
+                result.push(c);
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
     let text = "Сохраняет трассировку в LaTeX\n\n\
     Используются пекеджи: multirow, babel, geometry и longtable.\n\n";
     let warning = "\nУВАГА!!! В связи с тем, что используется longtable, \
@@ -179,9 +658,22 @@ fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn Fn
 
     ui.text_wrapped(text);
     if ui.button_with_size("Погнали!", [160.0, 30.0]) {
-        let trace: Tracing = tracing();
-
-        let header = trace.header.join(" & ");
+        let trace = source.collect();
+
+        let header = trace
+            .header
+            .iter()
+            .map(|x| latex_escape(x))
+            .collect::<Vec<String>>()
+            .join(" & ");
+        let groups = trace
+            .groups
+            .iter()
+            .map(|(name, count)| {
+                format!("\\multicolumn{{{count}}}{{|c|}}{{{}}}", latex_escape(name))
+            })
+            .collect::<Vec<String>>()
+            .join(" & ");
 
         let mut content = format!(
             "\\documentclass{{article}}\n\
@@ -189,12 +681,10 @@ fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn Fn
         \\usepackage[margin=1.5cm]{{geometry}}\n\
         \\usepackage[english,russian]{{babel}}\n\
         \\begin{{document}}\n\
-        \\begin{{longtable}}{{|c|c|c|c|c|c|c|c|c|c|}}\n\
+        \\begin{{longtable}}{{|{}}}\n\
         \t\\caption{{Таблица трассировки}} \\\\ \n\
         \t\\hline\n\
-        \t\\multicolumn{{2}}{{|c|}}{{Выполняемая команда}} & \n\
-        \t\\multicolumn{{6}}{{|c|}}{{Содержимое регистров после выполнения команды}} & \n\
-        \t\\multicolumn{{2}}{{|c|}}{{Изменившаяся ячейка}} \\\\\n\
+        \t{} \\\\\n\
         \t\\hline\n\
         \t{} \\\\\n\
         \t\\hline\n\
@@ -205,12 +695,37 @@ fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn Fn
         \t\\endhead\n\
         \t\\hline\n\
         \t\\endfoot\n",
-            header, header
+            "c|".repeat(trace.header.len()),
+            groups,
+            header,
+            header
         );
 
-        for x in trace.tracing {
-            content.push_str(x.join(" & ").as_str());
-            content.push_str("\\\\\n");
+        for row in trace.tracing {
+            let height = row.height();
+            for sub_row in 0..height {
+                let line = (0..row.cells.len())
+                    .map(|column| match (row.is_spanning(column), sub_row) {
+                        (true, 0) => {
+                            format!(
+                                "\\multirow{{{height}}}{{*}}{{{}}}",
+                                latex_escape(row.get(column, 0))
+                            )
+                        }
+                        (true, _) => String::new(),
+                        (false, _) => latex_escape(row.get(column, sub_row)),
+                    })
+                    .collect::<Vec<String>>();
+                content.push_str(line.join(" & ").as_str());
+                content.push_str("\\\\\n");
+
+                if sub_row + 1 < height {
+                    // между подстроками линия только под колонками, которые не растянуты
+                    for column in (0..row.cells.len()).filter(|c| !row.is_spanning(*c)) {
+                        content.push_str(format!("\t\\cline{{{0}-{0}}}\n", column + 1).as_str());
+                    }
+                }
+            }
 
             content.push_str("\t\\hline\n");
         }
@@ -219,7 +734,7 @@ fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn Fn
         \\end{document}\n",
         );
         write_to_file(
-            content.as_str(),
+            content.as_bytes(),
             "tex",
             &mut state.borrow_mut().popup_manager,
         );
@@ -228,6 +743,347 @@ fn latex_converter(ui: &Ui, state: &RefCell<&mut GuiState>, tracing: &mut dyn Fn
         .build(|| ui.text_wrapped(warning));
 }
 
+fn markdown_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
+    let text = "Сохраняет трассировку в формате Markdown (GitHub)\n\n\
+    Такую таблицу можно вставить в README, Obsidian, Notion и вообще почти куда угодно.\n\n";
+
+    ui.text_wrapped(text);
+    if ui.button_with_size("Погнали!", [160.0, 30.0]) {
+        let trace = source.collect();
+        let width = trace.header.len();
+
+        let row = |cells: &[String]| {
+            let mut line = String::from("|");
+            for i in 0..width {
+                line.push(' ');
+                line.push_str(
+                    &cells
+                        .get(i)
+                        .map_or(String::new(), |c| c.replace('|', "\\|")),
+                );
+                line.push_str(" |");
+            }
+            line.push('\n');
+            line
+        };
+
+        let mut content = row(&trace.header);
+        content.push_str(&"|---".repeat(width));
+        content.push_str("|\n");
+        for x in trace.tracing.iter().flat_map(|r| r.sub_rows()) {
+            content.push_str(&row(&x));
+        }
+
+        write_to_file(
+            content.as_bytes(),
+            "md",
+            &mut state.borrow_mut().popup_manager,
+        );
+    }
+}
+
+fn typst_escape(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        if "\\#$*_[]<>@`~=-+/".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn typst_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
+    let text = "Сохраняет трассировку в Typst\n\n\
+    Получается отдельный документ с одной таблицей. Ее можно скопировать в свой отчет \
+    или подключить целиком через #include.\n\n";
+
+    ui.text_wrapped(text);
+    if ui.button_with_size("Погнали!", [160.0, 30.0]) {
+        let trace = source.collect();
+        let width = trace.header.len();
+
+        let mut content = format!(
+            "#set page(flipped: true, margin: 1.5cm)\n\
+            #set text(lang: \"ru\")\n\
+            \n\
+            #figure(\n\
+            \ttable(\n\
+            \t\tcolumns: {width},\n\
+            \t\talign: center,\n\
+            \t\ttable.header(\n"
+        );
+
+        for (name, count) in &trace.groups {
+            content.push_str(&format!(
+                "\t\t\ttable.cell(colspan: {count})[*{}*],\n",
+                typst_escape(name.as_str())
+            ));
+        }
+        content.push_str("\t\t\t");
+        for h in &trace.header {
+            content.push_str(&format!("[*{}*], ", typst_escape(h)));
+        }
+        content.push_str("\n\t\t),\n");
+
+        for row in &trace.tracing {
+            let height = row.height();
+            for sub_row in 0..height {
+                content.push_str("\t\t");
+                for column in 0..row.cells.len() {
+                    match (row.is_spanning(column), sub_row) {
+                        (true, 0) => content.push_str(&format!(
+                            "table.cell(rowspan: {height})[{}], ",
+                            typst_escape(row.get(column, 0))
+                        )),
+                        // растянутую ячейку typst сам пропустит
+                        (true, _) => {}
+                        (false, _) => content
+                            .push_str(&format!("[{}], ", typst_escape(row.get(column, sub_row)))),
+                    }
+                }
+                content.push('\n');
+            }
+        }
+        content.push_str("\t),\n\tcaption: [Таблица трассировки],\n)\n");
+
+        write_to_file(
+            content.as_bytes(),
+            "typ",
+            &mut state.borrow_mut().popup_manager,
+        );
+    }
+}
+
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Имя колонки в Excel: A, B, ..., Z, AA, AB, ...
+fn xlsx_column(mut idx: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.insert(0, (b'A' + (idx % 26) as u8) as char);
+        if idx < 26 {
+            return name;
+        }
+        idx = idx / 26 - 1;
+    }
+}
+
+fn xlsx_document(trace: &Tracing) -> Vec<u8> {
+    let mut rows = String::new();
+    let mut merges = vec![];
+
+    let push_row = |rows: &mut String, number: usize, cells: &[String], style: &str| {
+        rows.push_str(&format!("<row r=\"{number}\">"));
+        for (idx, cell) in cells.iter().enumerate() {
+            rows.push_str(&format!(
+                "<c r=\"{}{}\" t=\"inlineStr\"{}><is><t>{}</t></is></c>",
+                xlsx_column(idx),
+                number,
+                style,
+                xml_escape(cell)
+            ));
+        }
+        rows.push_str("</row>");
+    };
+
+    push_row(&mut rows, 1, &trace.header, " s=\"1\"");
+    let mut number = 2;
+    for row in &trace.tracing {
+        let height = row.height();
+        for column in (0..row.cells.len()).filter(|c| row.is_spanning(*c)) {
+            let name = xlsx_column(column);
+            merges.push(format!("{name}{number}:{name}{}", number + height - 1));
+        }
+        for sub_row in row.sub_rows() {
+            push_row(&mut rows, number, &sub_row, "");
+            number += 1;
+        }
+    }
+
+    let merges = if merges.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<mergeCells count=\"{}\">{}</mergeCells>",
+            merges.len(),
+            merges
+                .iter()
+                .map(|m| format!("<mergeCell ref=\"{m}\"/>"))
+                .collect::<String>()
+        )
+    };
+
+    let header = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n";
+    let content_types = format!(
+        "{header}<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+        <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+        <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+        <Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+        <Override PartName=\"/xl/worksheets/sheet1.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>\
+        <Override PartName=\"/xl/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml\"/>\
+        </Types>"
+    );
+    let rels = format!(
+        "{header}<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+        <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"xl/workbook.xml\"/>\
+        </Relationships>"
+    );
+    let workbook = format!(
+        "{header}<workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" \
+        xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">\
+        <sheets><sheet name=\"Трассировка\" sheetId=\"1\" r:id=\"rId1\"/></sheets>\
+        </workbook>"
+    );
+    let workbook_rels = format!(
+        "{header}<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+        <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet1.xml\"/>\
+        <Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/>\
+        </Relationships>"
+    );
+    // второй стиль - жирный шрифт для шапки
+    let styles = format!(
+        "{header}<styleSheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\">\
+        <fonts count=\"2\"><font/><font><b/></font></fonts>\
+        <fills count=\"1\"><fill><patternFill patternType=\"none\"/></fill></fills>\
+        <borders count=\"1\"><border/></borders>\
+        <cellStyleXfs count=\"1\"><xf/></cellStyleXfs>\
+        <cellXfs count=\"2\"><xf/><xf fontId=\"1\" applyFont=\"1\"/></cellXfs>\
+        </styleSheet>"
+    );
+    let sheet = format!(
+        "{header}<worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\">\
+        <sheetData>{rows}</sheetData>{merges}\
+        </worksheet>"
+    );
+
+    crate::utils::zip::store(&[
+        ("[Content_Types].xml", content_types.into_bytes()),
+        ("_rels/.rels", rels.into_bytes()),
+        ("xl/workbook.xml", workbook.into_bytes()),
+        ("xl/_rels/workbook.xml.rels", workbook_rels.into_bytes()),
+        ("xl/styles.xml", styles.into_bytes()),
+        ("xl/worksheets/sheet1.xml", sheet.into_bytes()),
+    ])
+}
+
+fn xlsx_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
+    let text = "Сохраняет трассировку в таблицу Excel (XLSX)\n\n\
+    Открывается в Excel, LibreOffice Calc и Google Таблицах, а оттуда таблицу \
+    можно просто скопировать в Word.\n\n";
+
+    ui.text_wrapped(text);
+
+    let save = ui.button_with_size("Сохранить", [160.0, 30.0]);
+    let open = ui.button_with_size("Открыть", [160.0, 30.0]);
+    if ui.is_item_hovered() {
+        ui.tooltip_text("Сохраняет и пытается открыть");
+    }
+
+    if !open && !save {
+        return;
+    }
+
+    let trace = source.collect();
+    let name = write_to_file(
+        xlsx_document(&trace).as_slice(),
+        "xlsx",
+        &mut state.borrow_mut().popup_manager,
+    );
+
+    if let (Some(filename), true) = (name, open) {
+        if let Err(s) = open_in_app(filename.as_str()) {
+            state.borrow_mut().popup_manager.open(PopupMessage::new(
+                "Упс",
+                format!("Не удалось открыть файл с трассировкой: {s}"),
+            ));
+        }
+    }
+}
+
+const JSON_DESCRIPTION: &str =
+    "Для каждого шага записывается: номер, адрес и код команды, мнемоника, \
+все регистры до и после, какие флаги поменялись, какие ячейки памяти и ВУ изменились \
+и сколько на это ушло микрокоманд (null, если команды исполнялись напрямую).\n\n\
+Выбранные колонки на это не влияют. Числа записаны в десятичном виде.\n\n";
+
+fn json_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
+    ui.text_wrapped("Сохраняет трассировку в JSON для разбора своими скриптами\n\n");
+    ui.text_wrapped(JSON_DESCRIPTION);
+    if ui.button_with_size("Погнали!", [160.0, 30.0]) {
+        let trace = source.collect();
+        write_to_file(
+            trace.to_json().as_bytes(),
+            "json",
+            &mut state.borrow_mut().popup_manager,
+        );
+    }
+}
+
+fn json_lines_converter(ui: &Ui, state: &RefCell<&mut GuiState>, source: &mut TraceSource) {
+    ui.text_wrapped("Сохраняет трассировку в JSON Lines: по одному объекту на строку\n\n");
+    ui.text_wrapped(JSON_DESCRIPTION);
+    ui.text_wrapped(STREAMING_DESCRIPTION);
+    if ui.button_with_size("Погнали!", [160.0, 30.0]) {
+        let Some((filename, f)) = create_file("jsonl", &mut state.borrow_mut().popup_manager)
+        else {
+            return;
+        };
+        let mut out = BufWriter::new(f);
+
+        let kind = source.kind;
+        let mut result = Ok(());
+        let mut rows = 0;
+        let stop = source.stream(&mut |record| {
+            if result.is_ok() {
+                result = out.write_all((record.to_json(kind) + "\n").as_bytes());
+            }
+            rows += 1;
+        });
+
+        finish_streaming(
+            out,
+            result,
+            filename.as_str(),
+            rows,
+            stop,
+            &mut state.borrow_mut().popup_manager,
+        );
+    }
+}
+
+const STREAMING_DESCRIPTION: &str = "Строки пишутся в файл по мере выполнения, так что таблица может быть сколь угодно длинной.\n\n";
+
+fn finish_streaming(
+    mut out: BufWriter<File>,
+    result: std::io::Result<()>,
+    filename: &str,
+    rows: usize,
+    stop: StopReason,
+    popup_manager: &mut PopupManager,
+) {
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        popup_manager.open(PopupMessage::new(
+            "Ошибка записи",
+            format!("Не могу записать в файл \"{filename}\": {e}"),
+        ));
+        return;
+    }
+
+    popup_manager.open(PopupMessage::new(
+        "Успех",
+        format!(
+            "Успешно сохранил трассировку в файл \"{filename}\"\n\nШагов: {rows}\n{}",
+            stop.title()
+        ),
+    ));
+}
+
 impl Tool for TraceTool {
     fn draw(&mut self, ui: &Ui, _: &Io, state: &mut GuiState) {
         let text = "Инструмент для создания таблицы трассировок.\n\n\
@@ -240,7 +1096,7 @@ impl Tool for TraceTool {
                 "Выполняет программу шаг за шагом так же, как если бы вы нажимали кнопку \"Большой шаг\" и записывали бы это в табличку.\n\n\
                 Прямо следует из этого факта, что трассировка будет выполняться начиная с текущего значения регистра СК\n\n\
                 БЭВМ будет выполнять команду за командой до тех пор пока либо не достигнет максимальной длинны таблицы, либо в регистре РК не появится значение F000\
-                , что, как правило, означает, что выполнилась команда HLT";
+                , что, как правило, означает, что выполнилась команда HLT. Другие поводы остановиться можно выбрать в \"Условиях остановки\"";
 
             ui.text_wrapped(text);
             ui.separator();
@@ -253,38 +1109,144 @@ impl Tool for TraceTool {
 
             ui.text_wrapped(text);
             ui.separator();
+            let text =
+                "По умолчанию трассировка выполняется на копии ЭВМ, так что после нее можно продолжить отладку с того же места. \
+                Файловые ВУ копии читают тот же файл, но ничего не пишут на диск.\n\n\
+                \"Потом вернуть как было\" выполняет программу на настоящей ЭВМ с настоящими ВУ, а затем возвращает \
+                регистры, память и состояние ВУ. То, что уже записано в файлы, конечно, остается.\n\n\
+                \"Оставить после HLT\" - как было раньше: ЭВМ остается в том состоянии, в котором закончилась трассировка.";
+
+            ui.text_wrapped(text);
+            ui.separator();
         });
 
         let width_t = ui.push_item_width(160.0);
         ui.input_int("###max_len", &mut self.max_len).build();
         width_t.end();
-        self.max_len = self.max_len.clamp(0, 200);
+        self.max_len = self.max_len.clamp(1, MAX_LEN);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(format!(
+                "До миллиона строк в CSV и JSON Lines: они пишутся в файл по ходу дела.\n\
+                Остальные форматы собирают таблицу в памяти, для них не больше {IN_MEMORY_LIMIT}"
+            ));
+        }
 
         let text = "Формат таблицы:";
         ui.text_wrapped(text);
-        let converter = enum_chooser(ui, "###converter", &mut self.converter, &self.converters);
+        let (_, converter) =
+            *enum_chooser(ui, "###converter", &mut self.converter, &self.converters);
 
         let text = "Вид трассировки:";
         ui.text_wrapped(text);
-        let tracer = enum_chooser(ui, "###tracer", &mut self.tracer, &self.tracers);
+        let previous = self.tracer;
+        enum_chooser(ui, "###tracer", &mut self.tracer, &self.tracers);
+        if previous != self.tracer {
+            if let Some(preset) = self.presets.iter().find(|p| p.kind == self.kind()) {
+                self.columns = preset.columns.clone();
+            }
+        }
+
+        ui.tree_node_config(Str("Колонки"))
+            .build(|| self.draw_columns(ui));
+
+        let text = "Где выполнять:";
+        ui.text_wrapped(text);
+        let mode = *enum_chooser(ui, "###mode", &mut self.mode, &self.modes);
+        self.remember(&mut state.settings);
+
+        ui.tree_node_config(Str("Условия остановки"))
+            .build(|| self.draw_stop_conditions(ui));
 
+        if let Some((steps, reason)) = self.last_run {
+            ui.text_wrapped(format!(
+                "Последняя трассировка: {steps} шагов. {}",
+                reason.title()
+            ));
+        }
+
+        let kind = self.kind();
+        let max_len = self.max_len as usize;
+        let stop = &self.stop;
+        let last_run = Cell::new(None);
         let cell = RefCell::new(state);
-        converter(ui, &cell, &mut || {
-            tracer(&mut cell.borrow_mut().computer, self.max_len as usize)
-        });
+        let mut runner = |max_len: usize, sink: &mut dyn FnMut(TraceRecord)| {
+            let mut steps = 0;
+            let reason = mode.run(&mut cell.borrow_mut().computer, |computer| {
+                run(kind, computer, max_len, stop, &mut |record| {
+                    steps += 1;
+                    sink(record)
+                })
+            });
+            last_run.set(Some((steps, reason)));
+            reason
+        };
+        converter(
+            ui,
+            &cell,
+            &mut TraceSource {
+                kind,
+                columns: &self.columns,
+                max_len,
+                collect_len: max_len.min(IN_MEMORY_LIMIT),
+                runner: &mut runner,
+            },
+        );
+
+        if let Some(last) = last_run.get() {
+            self.last_run = Some(last);
+        }
+
+        ui.tree_node_config(Str("Сверка с эталоном"))
+            .build(|| self.draw_reference(ui, &mut cell.borrow_mut(), mode));
     }
 }
 
-fn write_to_file(s: &str, postfix: &str, popup_manager: &mut PopupManager) -> Option<String> {
+/// Поле для шестнадцатеричного адреса
+fn address_input(ui: &Ui, label: &str, address: &mut u16) {
+    let mut data = format!("{:0>3X}", address);
+    let width_t = ui.push_item_width(50.0);
+    if ui
+        .input_text(label, &mut data)
+        .chars_hexadecimal(true)
+        .chars_noblank(true)
+        .build()
+    {
+        if let Ok(parsed) = u16::from_str_radix(&data, 16) {
+            *address = parsed & 0x7FF;
+        }
+    }
+    width_t.end();
+}
+
+fn write_to_file(s: &[u8], postfix: &str, popup_manager: &mut PopupManager) -> Option<String> {
+    let (filename, mut f) = create_file(postfix, popup_manager)?;
+
+    if let Err(e) = f.write_all(s) {
+        popup_manager.open(PopupMessage::new(
+            "Ошибка записи",
+            format!("Не могу записать в файл \"{filename}\": {e}"),
+        ));
+        return None;
+    }
+
+    popup_manager.open(PopupMessage::new(
+        "Успех",
+        format!("Успешно сохранил трассировку в файл \"{filename}\""),
+    ));
+
+    Some(filename)
+}
+
+fn create_file(postfix: &str, popup_manager: &mut PopupManager) -> Option<(String, File)> {
     let postfixs = [postfix];
     let dialog = FileDialog::new().add_filter("", &postfixs);
 
-    let Some(filename) =  dialog.save_file() else {
+    let Some(filename) = dialog.save_file() else {
         popup_manager.open(PopupMessage::new(
-                "Ошибка выбора файла",
-                format!("Не могу открыть окно выбора файла"),
-            ));
-            return None;
+            "Ошибка выбора файла",
+            format!("Не могу открыть окно выбора файла"),
+        ));
+        return None;
     };
 
     let filename = filename
@@ -305,176 +1267,6 @@ fn write_to_file(s: &str, postfix: &str, popup_manager: &mut PopupManager) -> Op
         ));
         return None;
     }
-    let mut f = f.unwrap();
-
-    if let Err(e) = f.write(s.as_bytes()) {
-        popup_manager.open(PopupMessage::new(
-            "Ошибка записи",
-            format!("Не могу записать в файл \"{filename}\": {e}"),
-        ));
-        return None;
-    }
-
-    popup_manager.open(PopupMessage::new(
-        "Успех",
-        format!("Успешно сохранил трассировку в файл \"{filename}\""),
-    ));
-
-    Some(filename)
-}
-
-struct Tracing {
-    pub header: Vec<String>,
-    pub tracing: Vec<Vec<String>>,
-}
-
-fn mc_tracing(computer: &mut Computer, len: usize) -> Tracing {
-    let mut steps_left = len;
-
-    let mut result = Vec::new();
-
-    while steps_left > 0 {
-        let pos = computer.registers.r_micro_command_counter;
-        let code = computer
-            .mc_memory
-            .borrow()
-            .data
-            .get(pos as usize)
-            .unwrap()
-            .get();
-
-        computer.registers.set_execute_by_tick(false);
-        computer.registers.set_lever(false);
-        computer.registers.set_program_mode(false);
-
-        computer.micro_step();
-
-        result.push(vec![
-            format!("{pos:0>3X}"),
-            format!("{code:0>4X}"),
-            format!("{:0>3X}", computer.registers.r_command_counter),
-            format!("{:0>3X}", computer.registers.r_address),
-            format!("{:0>4X}", computer.registers.r_command),
-            format!("{:0>4X}", computer.registers.r_data),
-            format!("{:0>4X}", computer.registers.r_counter),
-            if computer.registers.get_overflow() {
-                "1".to_owned()
-            } else {
-                "0".to_owned()
-            },
-            format!("{:0>4X}", computer.registers.r_buffer),
-            if computer.registers.get_negative() {
-                "1".to_owned()
-            } else {
-                "0".to_owned()
-            },
-            if computer.registers.get_null() {
-                "1".to_owned()
-            } else {
-                "0".to_owned()
-            },
-            format!("{:0>3X}", computer.registers.r_micro_command_counter),
-        ]);
-
-        if computer.registers.r_command == 0xF000 {
-            break;
-        }
-
-        steps_left -= 1;
-    }
-
-    Tracing {
-        header: vec![
-            "СчМК до выборки МК",
-            "ВМК",
-            "СК",
-            "РА",
-            "РК",
-            "РД",
-            "А",
-            "С",
-            "БР",
-            "N",
-            "Z",
-            "СчМК",
-        ]
-        .iter()
-        .map(|a| a.to_owned().to_owned())
-        .collect(),
-        tracing: result,
-    }
-}
-fn general_tracing(computer: &mut Computer, len: usize) -> Tracing {
-    let mut steps_left = len;
-
-    let mut result = Vec::new();
-
-    while steps_left > 0 {
-        let pos = computer.registers.r_command_counter;
-        let code = computer
-            .general_memory
-            .borrow()
-            .data
-            .get(pos as usize)
-            .unwrap()
-            .get();
-        let mem_before = computer.general_memory.borrow().data.clone();
-
-        computer.registers.set_execute_by_tick(false);
-        computer.registers.set_lever(false);
-        computer.registers.set_program_mode(false);
-        while !matches!(computer.micro_step(), ExecutionResult::Halted) {}
-
-        let mut line = vec![
-            format!("{pos:0>3X}"),
-            format!("{code:0>4X}"),
-            format!("{:0>4X}", computer.registers.r_command_counter),
-            format!("{:0>4X}", computer.registers.r_address),
-            format!("{:0>4X}", computer.registers.r_command),
-            format!("{:0>4X}", computer.registers.r_data),
-            format!("{:0>4X}", computer.registers.r_counter),
-            if computer.registers.get_overflow() {
-                "1".to_owned()
-            } else {
-                "0".to_owned()
-            },
-        ];
-        for i in 0..mem_before.len() {
-            if computer.general_memory.borrow().data.get(i).unwrap().get()
-                != mem_before.get(i).unwrap().get()
-            {
-                line.push(format!("{i:0>3X}"));
-                line.push(format!(
-                    "{:0>4X}",
-                    computer.general_memory.borrow().data.get(i).unwrap().get()
-                ));
-            }
-        }
-        result.push(line);
-
-        steps_left -= 1;
 
-        if computer.registers.r_command == 0xF000 {
-            break;
-        }
-    }
-
-    Tracing {
-        header: vec![
-            "Адресс",
-            "Код",
-            "СК",
-            "РА",
-            "РК",
-            "РД",
-            "А",
-            "С",
-            "Адрес",
-            "Новый код",
-        ]
-        .iter()
-        .map(|a| a.to_owned().to_owned())
-        .collect(),
-        tracing: result,
-    }
+    Some((filename, f.unwrap()))
 }
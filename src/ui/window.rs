@@ -1,15 +1,25 @@
 use crate::ui::gui::GuiState;
+use crate::ui::panels::LayoutRequest;
 
 use imgui::{ImString, Io, Ui};
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub trait Tool {
     fn draw(&mut self, ui: &Ui, io: &Io, state: &mut GuiState);
+
+    /// Обходит группы вкладок внутри инструмента, чтобы раскладку можно было менять на лету
+    fn visit_groups(&mut self, _visitor: &mut dyn FnMut(&mut WindowTool)) {}
 }
 
+/// Инструмент, который может переезжать между группами вкладок и отдельными окнами,
+/// не теряя своего состояния
+pub type SharedTool = Rc<RefCell<dyn Tool>>;
+
 pub struct WindowTool {
     id: String,
     tool_selector: usize,
-    tools: Vec<(&'static str, Box<dyn Tool>)>,
+    tools: Vec<(&'static str, SharedTool)>,
 
     vertical_scroll: bool,
 }
@@ -29,32 +39,30 @@ impl Tool for WindowTool {
         }
         let token = token.unwrap();
 
-        ui.menu_bar(|| {
-            let title = self.tools.get(self.tool_selector).unwrap().0;
-            if self.tools.len() > 1 {
-                ui.menu(title, || {
-                    for i in 0..self.tools.len() {
-                        let name = ImString::new(self.tools.get(i).unwrap().0);
-                        if ui
-                            .menu_item_config(name)
-                            .selected(i == self.tool_selector)
-                            .build()
-                        {
-                            self.tool_selector = i;
-                        }
-                    }
-                });
-            } else {
-                ui.text(title);
-            }
-        });
-
-        let (_, tool) = self.tools.get_mut(self.tool_selector).unwrap();
+        self.draw_menu(ui, state);
 
-        tool.draw(ui, io, state);
+        match self.tools.get(self.tool_selector) {
+            // если эта же панель сейчас рисуется где-то выше, второй раз ее не рисуем
+            Some((_, tool)) => match tool.try_borrow_mut() {
+                Ok(mut tool) => tool.draw(ui, io, state),
+                Err(_) => ui.text_disabled("Панель уже открыта"),
+            },
+            None => ui.text_wrapped(
+                "Пусто. Сюда можно прикрепить открепленную панель через меню ее окна",
+            ),
+        }
 
         token.end();
     }
+
+    fn visit_groups(&mut self, visitor: &mut dyn FnMut(&mut WindowTool)) {
+        visitor(self);
+        for (_, tool) in &self.tools {
+            if let Ok(mut tool) = tool.try_borrow_mut() {
+                tool.visit_groups(visitor);
+            }
+        }
+    }
 }
 
 impl WindowTool {
@@ -66,8 +74,12 @@ impl WindowTool {
         Self::new(tool_name.to_string()).append(tool_name, tool)
     }
 
-    pub fn append(mut self, name: &'static str, tool: impl Tool + 'static) -> WindowTool {
-        self.tools.push((name, Box::new(tool)));
+    pub fn append(self, name: &'static str, tool: impl Tool + 'static) -> WindowTool {
+        self.append_shared(name, Rc::new(RefCell::new(tool)))
+    }
+
+    pub fn append_shared(mut self, name: &'static str, tool: SharedTool) -> WindowTool {
+        self.tools.push((name, tool));
         self
     }
 
@@ -85,4 +97,73 @@ impl WindowTool {
         self.vertical_scroll = true;
         self
     }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Названия вкладок
+    pub fn names(&self) -> Vec<&'static str> {
+        self.tools.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Как группу называть в меню: по ее вкладкам
+    pub fn title(&self) -> String {
+        if self.tools.is_empty() {
+            return format!("Пустая группа ({})", self.id);
+        }
+        self.names().join(", ")
+    }
+
+    /// Забирает вкладку из группы
+    pub fn remove(&mut self, name: &str) -> Option<SharedTool> {
+        let idx = self.tools.iter().position(|(n, _)| *n == name)?;
+        let (_, tool) = self.tools.remove(idx);
+        if self.tool_selector >= idx && self.tool_selector > 0 {
+            self.tool_selector -= 1;
+        }
+        Some(tool)
+    }
+
+    /// Добавляет вкладку и сразу переключается на нее
+    pub fn insert(&mut self, name: &'static str, tool: SharedTool) {
+        self.tools.push((name, tool));
+        self.tool_selector = self.tools.len() - 1;
+    }
+
+    fn draw_menu(&mut self, ui: &Ui, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            let Some(&(title, _)) = self.tools.get(self.tool_selector) else {
+                ui.text_disabled("Пусто");
+                return;
+            };
+            ui.menu(title, || {
+                if self.tools.len() > 1 {
+                    for i in 0..self.tools.len() {
+                        let name = ImString::new(self.tools.get(i).unwrap().0);
+                        if ui
+                            .menu_item_config(name)
+                            .selected(i == self.tool_selector)
+                            .build()
+                        {
+                            self.tool_selector = i;
+                        }
+                    }
+                    ui.separator();
+                }
+                if ui.menu_item("Открепить") {
+                    state.layout_requests.push(LayoutRequest::Undock {
+                        group: self.id.clone(),
+                        panel: title,
+                    });
+                }
+                if ui.menu_item("Скрыть") {
+                    state.layout_requests.push(LayoutRequest::Hide {
+                        group: self.id.clone(),
+                        panel: title,
+                    });
+                }
+            });
+        });
+    }
 }
@@ -1 +1,2 @@
 pub mod bit_registers;
+pub mod zip;
@@ -0,0 +1,100 @@
+//! Минимальная запись zip-архивов без сжатия. Хватает, чтобы собрать xlsx без внешних зависимостей.
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+fn push_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Собирает архив из файлов (имя, содержимое). Файлы кладутся как есть, без сжатия.
+pub fn store(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    // 1 января 1980 в формате MS-DOS
+    const DATE: u16 = 0x21;
+
+    let mut out = vec![];
+    let mut central = vec![];
+
+    for (name, data) in files {
+        let offset = out.len() as u32;
+        let crc = crc32(data);
+
+        push_u32(&mut out, 0x04034b50);
+        push_u16(&mut out, 20); // нужная версия
+        push_u16(&mut out, 0); // флаги
+        push_u16(&mut out, 0); // без сжатия
+        push_u16(&mut out, 0); // время
+        push_u16(&mut out, DATE);
+        push_u32(&mut out, crc);
+        push_u32(&mut out, data.len() as u32);
+        push_u32(&mut out, data.len() as u32);
+        push_u16(&mut out, name.len() as u16);
+        push_u16(&mut out, 0); // доп. поля
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        push_u32(&mut central, 0x02014b50);
+        push_u16(&mut central, 20); // кем создано
+        push_u16(&mut central, 20); // нужная версия
+        push_u16(&mut central, 0);
+        push_u16(&mut central, 0);
+        push_u16(&mut central, 0);
+        push_u16(&mut central, DATE);
+        push_u32(&mut central, crc);
+        push_u32(&mut central, data.len() as u32);
+        push_u32(&mut central, data.len() as u32);
+        push_u16(&mut central, name.len() as u16);
+        push_u16(&mut central, 0); // доп. поля
+        push_u16(&mut central, 0); // комментарий
+        push_u16(&mut central, 0); // номер диска
+        push_u16(&mut central, 0); // внутренние атрибуты
+        push_u32(&mut central, 0); // внешние атрибуты
+        push_u32(&mut central, offset);
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let central_offset = out.len() as u32;
+    let central_size = central.len() as u32;
+    out.append(&mut central);
+
+    push_u32(&mut out, 0x06054b50);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, files.len() as u16);
+    push_u16(&mut out, files.len() as u16);
+    push_u32(&mut out, central_size);
+    push_u32(&mut out, central_offset);
+    push_u16(&mut out, 0); // комментарий
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::zip::{crc32, store};
+
+    #[test]
+    fn test_store() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+
+        let archive = store(&[("a.txt", b"hello".to_vec()), ("b/c.txt", vec![])]);
+        assert_eq!(&archive[0..4], &[0x50, 0x4B, 0x03, 0x04]);
+        // 2 локальных заголовка + 2 записи оглавления + конец оглавления
+        assert_eq!(archive.len(), (30 + 5 + 5) + (30 + 7) + (46 + 5) + (46 + 7) + 22);
+        assert_eq!(&archive[archive.len() - 22..archive.len() - 18], &[0x50, 0x4B, 0x05, 0x06]);
+    }
+}